@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use either::Either;
+use rand::seq::SliceRandom;
+
+use macros::measured;
+
+use crate::bankruptcy::{Insolvent, Liabilities, Liquidation};
+use crate::business::{Manufacturer, SellOrder, Worker};
+use crate::config::{Config, ProductionLines};
+use crate::logs::LogEvent;
+use crate::money::Money;
+use crate::people::Person;
+use crate::stats::DAYS_IN_MONTH;
+use crate::ui::debug::Performance;
+use crate::wallet::{TradeSide, Transaction, Wallet};
+use crate::Days;
+
+/// What a business owns, valued at what it cost, and what it owes
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BalanceSheet {
+    pub cash: Money,
+    /// Inputs and tools in stock at their buy cost
+    pub inventory: Money,
+    /// Goods waiting to be sold or listed on the market at their production cost
+    pub goods: Money,
+    /// Production lines at the price of a new one
+    pub equipment: Money,
+    pub liabilities: Money,
+}
+
+impl BalanceSheet {
+    pub fn new(
+        manufacturer: &Manufacturer,
+        wallet: &Wallet,
+        liabilities: &Liabilities,
+        listed: Money,
+        config: &ProductionLines,
+    ) -> Self {
+        BalanceSheet {
+            cash: wallet.money(),
+            inventory: manufacturer
+                .assets
+                .items
+                .values()
+                .flatten()
+                .map(|item| item.buy_cost)
+                .sum(),
+            goods: manufacturer
+                .assets
+                .items_to_sell
+                .iter()
+                .map(|item| item.production_cost)
+                .sum::<Money>()
+                + listed,
+            equipment: config.new_line_cost.value * manufacturer.production_lines.len() as u32,
+            liabilities: liabilities.total(),
+        }
+    }
+
+    /// Book value of the business, nothing when it owes more than it owns
+    pub fn total(&self) -> Money {
+        let assets = self.cash + self.inventory + self.goods + self.equipment;
+        if assets > self.liabilities {
+            assets - self.liabilities
+        } else {
+            Money(0)
+        }
+    }
+}
+
+/// Business that changed hands, it is not sold again until the cooldown is over
+#[derive(Component, Debug)]
+pub struct Acquired {
+    pub(crate) day: usize,
+}
+
+/// Business an owner is willing to sell
+struct ForSale {
+    business: Entity,
+    owner: Entity,
+    price: Money,
+    /// Money the buyer lends the business to keep it running
+    recapitalization: Money,
+    distressed: bool,
+}
+
+/// Whether the business is owned by the owner, directly or through other businesses
+fn owned_by(owners: &HashMap<Entity, Entity>, business: Entity, owner: Entity) -> bool {
+    let mut current = business;
+    for _ in 0..owners.len() {
+        match owners.get(&current) {
+            Some(&next) if next == owner => return true,
+            Some(&next) => current = next,
+            None => return false,
+        }
+    }
+    false
+}
+
+/// Owners sell profitable businesses at a premium over their book value once a month, and
+/// distressed ones at a discount before they go bankrupt. A business is distressed when it is
+/// insolvent or short of cash at the start of the month. The richest person or business able to
+/// pay becomes the new owner, workers, stock and strategies stay with the business. Recently
+/// acquired businesses are not sold again and only a few businesses change hands each month.
+#[allow(clippy::too_many_arguments)]
+#[measured]
+pub fn acquire_businesses(
+    mut manufacturers: Query<
+        (Entity, &Name, &mut Manufacturer, &mut Liabilities),
+        Without<Liquidation>,
+    >,
+    insolvent: Query<&Insolvent>,
+    acquired: Query<&Acquired>,
+    people: Query<Entity, With<Person>>,
+    workers: Query<&Worker>,
+    sell_orders: Query<&SellOrder>,
+    mut wallets: Query<&mut Wallet>,
+    mut logs: EventWriter<LogEvent>,
+    mut commands: Commands,
+    date: Res<Days>,
+    config: Res<Config>,
+) {
+    let settings = &config.business.acquisitions;
+    let days_since = |acquired: &Acquired| date.days.saturating_sub(acquired.day);
+    let mut sold_this_month = acquired
+        .iter()
+        .filter(|acquired| days_since(acquired) < DAYS_IN_MONTH)
+        .count() as u32;
+    if sold_this_month >= settings.max_per_month.value {
+        return;
+    }
+    let listed = sell_orders
+        .iter()
+        .fold(HashMap::new(), |mut acc, sell_order| {
+            *acc.entry(sell_order.seller).or_insert(Money(0)) += sell_order
+                .items
+                .iter()
+                .map(|item| item.production_cost)
+                .sum::<Money>();
+            acc
+        });
+    let mut owners: HashMap<Entity, Entity> = manufacturers
+        .iter()
+        .map(|(entity, _, manufacturer, _)| (entity, manufacturer.owner))
+        .collect();
+    let mut for_sale = vec![];
+    for (business, _, manufacturer, liabilities) in manufacturers.iter() {
+        let Ok(wallet) = wallets.get(business) else {
+            continue;
+        };
+        if acquired
+            .get(business)
+            .is_ok_and(|acquired| days_since(acquired) < settings.cooldown_days.value as usize)
+        {
+            continue;
+        }
+        let sheet = BalanceSheet::new(
+            manufacturer,
+            wallet,
+            liabilities,
+            *listed.get(&business).unwrap_or(&Money(0)),
+            &config.business.production_lines,
+        );
+        let salaries: Money = manufacturer
+            .hired_workers()
+            .filter_map(|worker| workers.get(*worker).ok())
+            .map(|worker| worker.salary)
+            .sum();
+        let monthly_costs = salaries * DAYS_IN_MONTH as u32 + manufacturer.fixed_costs();
+        // unpaid salaries have to be paid on top of the running costs
+        let needed_cash =
+            monthly_costs * settings.distress_months.value + liabilities.unpaid_salaries();
+        let profitable = matches!(
+            wallet.calculate_total_change(date.days, DAYS_IN_MONTH),
+            Either::Right(profit) if profit > Money(0)
+        );
+        let short_of_cash = date.next_month && wallet.money() < needed_cash;
+        let (price, recapitalization, distressed) = if insolvent.contains(business) || short_of_cash
+        {
+            // a margin over the missing money keeps the next salary payout from leaving the
+            // business distressed again
+            let target = needed_cash * (1.0 + settings.recapitalization_margin.value);
+            (
+                sheet.total() * (1.0 - settings.distressed_discount.value),
+                if target > wallet.money() {
+                    target - wallet.money()
+                } else {
+                    Money(0)
+                },
+                true,
+            )
+        } else if profitable && date.next_month {
+            (
+                sheet.total() * (1.0 + settings.premium.value),
+                Money(0),
+                false,
+            )
+        } else {
+            continue;
+        };
+        for_sale.push(ForSale {
+            business,
+            owner: manufacturer.owner,
+            price,
+            recapitalization,
+            distressed,
+        });
+    }
+    for_sale.shuffle(&mut rand::thread_rng());
+    // businesses about to go bankrupt are sold first when the cap is reached
+    for_sale.sort_by_key(|sale| !sale.distressed);
+
+    for sale in for_sale {
+        if sold_this_month >= settings.max_per_month.value {
+            break;
+        }
+        let cost = sale.price + sale.recapitalization + settings.buyer_reserve.value;
+        let buyer = people
+            .iter()
+            .chain(owners.keys().copied())
+            .filter(|&buyer| {
+                buyer != sale.business
+                    && buyer != sale.owner
+                    && !owned_by(&owners, buyer, sale.business)
+            })
+            .filter_map(|buyer| Some((buyer, wallets.get(buyer).ok()?.money())))
+            .filter(|(_, money)| *money >= cost)
+            .max_by_key(|(_, money)| *money)
+            .map(|(buyer, _)| buyer);
+        let Some(buyer) = buyer else {
+            continue;
+        };
+        let Ok([mut buyer_wallet, mut owner_wallet]) = wallets.get_many_mut([buyer, sale.owner])
+        else {
+            continue;
+        };
+        buyer_wallet
+            .transaction(
+                &mut owner_wallet,
+                &Transaction::Transfer {
+                    side: TradeSide::Pay,
+                    sender: buyer,
+                    receiver: sale.owner,
+                    amount: sale.price,
+                    date: date.days,
+                },
+                &mut logs,
+            )
+            .unwrap(); // the buyer was chosen to afford it
+        if sale.recapitalization > Money(0) {
+            let [mut buyer_wallet, mut business_wallet] =
+                wallets.get_many_mut([buyer, sale.business]).unwrap();
+            buyer_wallet
+                .transaction(
+                    &mut business_wallet,
+                    &Transaction::Transfer {
+                        side: TradeSide::Pay,
+                        sender: buyer,
+                        receiver: sale.business,
+                        amount: sale.recapitalization,
+                        date: date.days,
+                    },
+                    &mut logs,
+                )
+                .unwrap();
+        }
+        let (_, name, mut manufacturer, mut liabilities) =
+            manufacturers.get_mut(sale.business).unwrap();
+        manufacturer.owner = buyer;
+        if sale.recapitalization > Money(0) {
+            // the buyer lends the money, it is paid back before the owner gets dividends
+            *liabilities.loans.entry(buyer).or_insert(Money(0)) += sale.recapitalization;
+        }
+        owners.insert(sale.business, buyer);
+        commands
+            .entity(sale.business)
+            .insert(Acquired { day: date.days });
+        sold_this_month += 1;
+        let reason = if sale.distressed {
+            "it is running out of money"
+        } else {
+            "the offer was too good to refuse"
+        };
+        logs.send(LogEvent::Generic {
+            text: format!("I sold {} for {}, {}", name.as_str(), sale.price, reason),
+            entity: sale.owner,
+        });
+        let text = if sale.recapitalization > Money(0) {
+            format!(
+                "I bought {} for {} and lent it {} to keep it running",
+                name.as_str(),
+                sale.price,
+                sale.recapitalization
+            )
+        } else {
+            format!("I bought {} for {}", name.as_str(), sale.price)
+        };
+        logs.send(LogEvent::Generic {
+            text,
+            entity: buyer,
+        });
+        logs.send(LogEvent::Generic {
+            text: format!("I was acquired for {}, {}", sale.price, reason),
+            entity: sale.business,
+        });
+        info!("{} was acquired for {}", name.as_str(), sale.price);
+    }
+}
@@ -0,0 +1,646 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use macros::measured;
+
+use crate::business::{BuyOrder, BuyStrategy, Item, Manufacturer, SellOrder, SellStrategy, Worker};
+use crate::config::Config;
+use crate::contracts::SupplyContract;
+use crate::government::TaxAuthority;
+use crate::items::ItemId;
+use crate::logs::LogEvent;
+use crate::money::Money;
+use crate::regions::Region;
+use crate::ui::debug::Performance;
+use crate::wallet::{TradeSide, Transaction, Wallet};
+use crate::Days;
+
+/// Money a business owes. It pays it back as soon as it can, when it is wound up its creditors are
+/// paid before its owner.
+#[derive(Component, Debug, Default, Clone)]
+pub struct Liabilities {
+    /// Salaries the business could not pay, by worker
+    pub(crate) salaries: HashMap<Entity, Money>,
+    /// Corporate income tax the business could not pay
+    pub(crate) taxes: Money,
+    /// Rent and utilities the business could not pay, by landlord
+    pub(crate) rent: HashMap<Entity, Money>,
+    /// Money lent to the business, by lender
+    pub(crate) loans: HashMap<Entity, Money>,
+}
+
+impl Liabilities {
+    pub fn unpaid_salaries(&self) -> Money {
+        self.salaries.values().copied().sum()
+    }
+
+    pub fn unpaid_rent(&self) -> Money {
+        self.rent.values().copied().sum()
+    }
+
+    pub fn total(&self) -> Money {
+        self.unpaid_salaries()
+            + self.taxes
+            + self.unpaid_rent()
+            + self.loans.values().copied().sum::<Money>()
+    }
+}
+
+/// Business that can't pay its bills, it is liquidated unless it recovers in time
+#[derive(Component, Debug)]
+pub struct Insolvent {
+    pub(crate) days_left: u32,
+}
+
+/// Business selling off its stock before it is wound up
+#[derive(Component, Debug)]
+pub struct Liquidation {
+    pub(crate) days_left: u32,
+}
+
+fn is_insolvent(wallet: &Wallet, liabilities: &Liabilities, config: &Config) -> bool {
+    wallet.money() < config.business.new_worker_salary.value
+        || !liabilities.salaries.is_empty()
+        || !liabilities.rent.is_empty()
+}
+
+/// Businesses that can't pay a new worker or owe salaries or rent get a grace period to recover
+#[measured]
+pub fn detect_insolvency(
+    businesses: Query<(Entity, &Wallet, &Liabilities), Without<Liquidation>>,
+    insolvent: Query<&Insolvent>,
+    names: Query<&Name>,
+    mut logs: EventWriter<LogEvent>,
+    mut commands: Commands,
+    config: Res<Config>,
+) {
+    let settings = &config.business.bankruptcy;
+    for (entity, wallet, liabilities) in businesses.iter() {
+        if !insolvent.contains(entity) && is_insolvent(wallet, liabilities, &config) {
+            info!("{} is insolvent", names.get(entity).unwrap().as_str());
+            logs.send(LogEvent::Generic {
+                text: format!(
+                    "I can't pay my bills! I have {} days to recover before I'm liquidated. Money: {}, unpaid salaries: {}, unpaid rent: {}",
+                    settings.grace_days.value,
+                    wallet.money(),
+                    liabilities.unpaid_salaries(),
+                    liabilities.unpaid_rent()
+                ),
+                entity,
+            });
+            commands.entity(entity).insert(Insolvent {
+                days_left: settings.grace_days.value,
+            });
+        }
+    }
+}
+
+/// Insolvent businesses that recovered carry on, the others go into liquidation when their grace
+/// period is over
+#[allow(clippy::too_many_arguments)]
+#[measured]
+pub fn liquidate_insolvent(
+    mut businesses: Query<(
+        Entity,
+        &Name,
+        &Wallet,
+        &Liabilities,
+        &mut Manufacturer,
+        &mut Insolvent,
+    )>,
+    regions: Query<&Region>,
+    mut sell_orders: Query<(Entity, &mut SellOrder)>,
+    buy_orders: Query<(Entity, &BuyOrder)>,
+    contracts: Query<(Entity, &SupplyContract)>,
+    mut logs: EventWriter<LogEvent>,
+    mut commands: Commands,
+    config: Res<Config>,
+) {
+    for (entity, name, wallet, liabilities, mut manufacturer, mut insolvent) in
+        businesses.iter_mut()
+    {
+        if !is_insolvent(wallet, liabilities, &config) {
+            logs.send(LogEvent::Generic {
+                text: "I recovered and can pay my bills again".to_string(),
+                entity,
+            });
+            commands.entity(entity).remove::<Insolvent>();
+        } else if insolvent.days_left > 0 {
+            insolvent.days_left -= 1;
+        } else {
+            info!("{} goes into liquidation", name.as_str());
+            start_liquidation(
+                entity,
+                name,
+                &mut manufacturer,
+                regions.get(entity).unwrap(),
+                &mut sell_orders,
+                &buy_orders,
+                &contracts,
+                &mut logs,
+                &mut commands,
+                &config,
+            );
+        }
+    }
+}
+
+/// Stops production, releases all workers, lists all stock at a discount and ends all trading
+/// relations
+#[allow(clippy::too_many_arguments)]
+fn start_liquidation(
+    entity: Entity,
+    name: &Name,
+    manufacturer: &mut Manufacturer,
+    region: &Region,
+    sell_orders: &mut Query<(Entity, &mut SellOrder)>,
+    buy_orders: &Query<(Entity, &BuyOrder)>,
+    contracts: &Query<(Entity, &SupplyContract)>,
+    logs: &mut EventWriter<LogEvent>,
+    commands: &mut Commands,
+    config: &Config,
+) {
+    let settings = &config.business.bankruptcy;
+    let discount = 1.0 - settings.liquidation_discount.value;
+    // without strategies nothing is bought, produced for sale or repriced anymore
+    commands
+        .entity(entity)
+        .remove::<(Insolvent, SellStrategy, BuyStrategy)>()
+        .insert(Liquidation {
+            days_left: settings.liquidation_days.value,
+        });
+    // released workers cost no more salaries, what they are owed is paid when the business is
+    // wound up
+    for worker in release_workers(manufacturer) {
+        commands.entity(worker).remove::<Worker>();
+        logs.send(LogEvent::Generic {
+            text: format!("I lost my job, {} is being liquidated", name.as_str()),
+            entity: worker,
+        });
+    }
+    for (_, mut sell_order) in sell_orders
+        .iter_mut()
+        .filter(|(_, sell_order)| sell_order.seller == entity)
+    {
+        sell_order.price = (sell_order.base_price * discount).max(Money(1));
+    }
+    for sell_order in list_stock(manufacturer, entity, region, discount) {
+        let order_name = Name::new(format!("{} liquidation sell order", sell_order.item_type));
+        commands.spawn((sell_order, order_name));
+    }
+    buy_orders
+        .iter()
+        .filter(|(_, buy_order)| buy_order.buyer == entity)
+        .for_each(|(order_entity, _)| {
+            commands.entity(order_entity).despawn_recursive();
+        });
+    contracts
+        .iter()
+        .filter(|(_, contract)| contract.involves(entity))
+        .for_each(|(contract_entity, contract)| {
+            let other_party = if contract.buyer == entity {
+                contract.seller
+            } else {
+                contract.buyer
+            };
+            logs.send(LogEvent::Generic {
+                text: format!(
+                    "My supply contract for {} is void, {} is being liquidated",
+                    contract.item_type,
+                    name.as_str()
+                ),
+                entity: other_party,
+            });
+            commands.entity(contract_entity).despawn();
+        });
+    logs.send(LogEvent::Generic {
+        text: format!(
+            "I did not recover in time, I'm selling off my stock for {} days before I close",
+            settings.liquidation_days.value
+        ),
+        entity,
+    });
+}
+
+/// Takes every worker off the lines and keeps the lines from hiring again
+fn release_workers(manufacturer: &mut Manufacturer) -> Vec<Entity> {
+    manufacturer
+        .production_lines
+        .iter_mut()
+        .flat_map(|line| {
+            line.target_workers = 0;
+            line.hired_workers.drain(..)
+        })
+        .collect()
+}
+
+/// Sell orders for everything the business still holds, priced at the given share of what it cost
+fn list_stock(
+    manufacturer: &mut Manufacturer,
+    seller: Entity,
+    region: &Region,
+    discount: f32,
+) -> Vec<SellOrder> {
+    let mut stock: HashMap<ItemId, Vec<Item>> = HashMap::new();
+    let assets = &mut manufacturer.assets;
+    for item in assets
+        .items
+        .drain()
+        .flat_map(|(_, items)| items)
+        .chain(assets.items_to_sell.drain(..))
+    {
+        stock.entry(item.item_type).or_default().push(item);
+    }
+    stock
+        .into_iter()
+        .map(|(item_type, items)| {
+            let cost = items
+                .iter()
+                .map(|item| item.production_cost.max(item.buy_cost))
+                .sum::<Money>()
+                / items.len();
+            SellOrder {
+                items,
+                item_type,
+                seller,
+                price: (cost * discount).max(Money(1)),
+                base_price: cost,
+                region: region.clone(),
+            }
+        })
+        .collect()
+}
+
+/// How the money of a wound up business is split between its creditors and its owner
+#[derive(Debug, Default, PartialEq)]
+struct Payout {
+    salaries: HashMap<Entity, Money>,
+    taxes: Money,
+    rent: HashMap<Entity, Money>,
+    loans: HashMap<Entity, Money>,
+    owner: Money,
+}
+
+/// Shares of the money by the size of the claims, all claims are paid in full when it is enough.
+/// Rounding down leaves at most a few credits over.
+fn pro_rata(money: Money, claims: &HashMap<Entity, Money>) -> HashMap<Entity, Money> {
+    let total = claims.values().copied().sum::<Money>();
+    claims
+        .iter()
+        .map(|(&claimant, &claim)| {
+            let paid = if total <= money {
+                claim
+            } else {
+                Money((claim.0 as u128 * money.0 as u128 / total.0 as u128) as u64)
+            };
+            (claimant, paid)
+        })
+        .collect()
+}
+
+/// Unpaid salaries come first, then taxes when there is a tax authority to collect them and then
+/// unpaid rent, lenders share what is left by the size of their loans and the owner gets the rest
+fn split_payout(money: Money, liabilities: &Liabilities, collect_taxes: bool) -> Payout {
+    let mut left = money;
+    let salaries = pro_rata(left, &liabilities.salaries);
+    left -= salaries.values().copied().sum::<Money>();
+    let taxes = if collect_taxes {
+        liabilities.taxes.min(left)
+    } else {
+        Money(0)
+    };
+    left -= taxes;
+    let rent = pro_rata(left, &liabilities.rent);
+    left -= rent.values().copied().sum::<Money>();
+    let loans = pro_rata(left, &liabilities.loans);
+    left -= loans.values().copied().sum::<Money>();
+    Payout {
+        salaries,
+        taxes,
+        rent,
+        loans,
+        owner: left,
+    }
+}
+
+/// Pays the transaction from the payer to the receiver, returns what was paid
+fn pay(
+    wallets: &mut Query<&mut Wallet>,
+    transaction: Transaction,
+    payer: Entity,
+    receiver: Entity,
+    logs: &mut EventWriter<LogEvent>,
+) -> Money {
+    let Ok([mut payer_wallet, mut receiver_wallet]) = wallets.get_many_mut([payer, receiver])
+    else {
+        return Money(0);
+    };
+    let amount = match &transaction {
+        Transaction::Transfer { amount, .. } => *amount,
+        Transaction::Salary { salary, .. } => *salary,
+        Transaction::FixedCost { amount, .. } => *amount,
+        _ => return Money(0),
+    };
+    if amount == Money(0) {
+        return Money(0);
+    }
+    payer_wallet
+        .transaction(&mut receiver_wallet, &transaction, logs)
+        .map_or(Money(0), |_| amount)
+}
+
+/// Businesses that sold off their stock or ran out of time are wound up. Their money pays
+/// unpaid salaries, taxes and rent first, lenders get their share of what is left and the owner gets
+/// the rest with the unsold stock, including deliveries that arrived during the liquidation.
+/// Owned businesses pass to the owner.
+#[allow(clippy::too_many_arguments)]
+#[measured]
+pub fn wind_up_businesses(
+    mut liquidations: Query<(
+        Entity,
+        &Name,
+        &mut Liquidation,
+        &Liabilities,
+        Option<&TaxAuthority>,
+    )>,
+    mut manufacturers: Query<&mut Manufacturer>,
+    mut wallets: Query<&mut Wallet>,
+    mut sell_orders: Query<&mut SellOrder>,
+    regions: Query<&Region>,
+    mut logs: EventWriter<LogEvent>,
+    mut commands: Commands,
+    date: Res<Days>,
+) {
+    for (entity, name, mut liquidation, liabilities, tax_authority) in liquidations.iter_mut() {
+        let Ok(manufacturer) = manufacturers.get(entity) else {
+            continue;
+        };
+        let sold_out = !sell_orders
+            .iter()
+            .any(|sell_order| sell_order.seller == entity);
+        if liquidation.days_left > 0 && !sold_out {
+            liquidation.days_left -= 1;
+            continue;
+        }
+        let owner = manufacturer.owner;
+        let money = wallets
+            .get(entity)
+            .map_or(Money(0), |wallet| wallet.money());
+        let payout = split_payout(money, liabilities, tax_authority.is_some());
+
+        for (&worker, &salary) in payout.salaries.iter() {
+            pay(
+                &mut wallets,
+                Transaction::Salary {
+                    side: TradeSide::Pay,
+                    employer: entity,
+                    worker,
+                    salary,
+                    date: date.days,
+                },
+                entity,
+                worker,
+                &mut logs,
+            );
+        }
+        if let Some(tax_authority) = tax_authority {
+            pay(
+                &mut wallets,
+                Transaction::Transfer {
+                    side: TradeSide::Pay,
+                    sender: entity,
+                    receiver: tax_authority.authority,
+                    amount: payout.taxes,
+                    date: date.days,
+                },
+                entity,
+                tax_authority.authority,
+                &mut logs,
+            );
+        }
+        for (&landlord, &amount) in payout.rent.iter() {
+            pay(
+                &mut wallets,
+                Transaction::FixedCost {
+                    side: TradeSide::Pay,
+                    payer: entity,
+                    landlord,
+                    amount,
+                    date: date.days,
+                },
+                entity,
+                landlord,
+                &mut logs,
+            );
+        }
+        for (&lender, &amount) in payout.loans.iter() {
+            let paid = pay(
+                &mut wallets,
+                Transaction::Transfer {
+                    side: TradeSide::Pay,
+                    sender: entity,
+                    receiver: lender,
+                    amount,
+                    date: date.days,
+                },
+                entity,
+                lender,
+                &mut logs,
+            );
+            logs.send(LogEvent::Generic {
+                text: format!(
+                    "{} was wound up, I got back {} of the {} I lent it",
+                    name.as_str(),
+                    paid,
+                    liabilities.loans[&lender]
+                ),
+                entity: lender,
+            });
+        }
+        // shares of creditors that could not be paid go to the owner as well
+        let amount = wallets
+            .get(entity)
+            .map_or(payout.owner, |wallet| wallet.money());
+        let rest = pay(
+            &mut wallets,
+            Transaction::Transfer {
+                side: TradeSide::Pay,
+                sender: entity,
+                receiver: owner,
+                amount,
+                date: date.days,
+            },
+            entity,
+            owner,
+            &mut logs,
+        );
+        logs.send(LogEvent::Generic {
+            text: format!(
+                "My business {} was wound up, I got {} that was left after paying its debts and the rest of its stock",
+                name.as_str(),
+                rest
+            ),
+            entity: owner,
+        });
+        sell_orders
+            .iter_mut()
+            .filter(|sell_order| sell_order.seller == entity)
+            .for_each(|mut sell_order| {
+                sell_order.seller = owner;
+                sell_order.price = sell_order.base_price;
+            });
+        if let (Ok(mut manufacturer), Ok(region)) =
+            (manufacturers.get_mut(entity), regions.get(entity))
+        {
+            for sell_order in list_stock(&mut manufacturer, owner, region, 1.0) {
+                let order_name = Name::new(format!("{} sell order", sell_order.item_type));
+                commands.spawn((sell_order, order_name));
+            }
+        }
+        for mut owned in manufacturers.iter_mut() {
+            if owned.owner == entity {
+                owned.owner = owner;
+            }
+        }
+        info!("{} was wound up", name.as_str());
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::business::{Inventory, ProductionCycle, ProductionLine};
+    use crate::items::ItemRegistry;
+    use crate::research::Research;
+
+    const WORKER_A: Entity = Entity::from_raw(1);
+    const WORKER_B: Entity = Entity::from_raw(2);
+    const BANK: Entity = Entity::from_raw(3);
+    const FRIEND: Entity = Entity::from_raw(4);
+    const LANDLORD: Entity = Entity::from_raw(5);
+
+    fn liabilities() -> Liabilities {
+        Liabilities {
+            salaries: HashMap::from([(WORKER_A, Money(300)), (WORKER_B, Money(100))]),
+            taxes: Money(200),
+            rent: HashMap::from([(LANDLORD, Money(100))]),
+            loans: HashMap::from([(BANK, Money(750)), (FRIEND, Money(250))]),
+        }
+    }
+
+    #[test]
+    fn salaries_share_money_that_does_not_cover_them() {
+        let payout = split_payout(Money(200), &liabilities(), true);
+
+        assert_eq!(
+            payout,
+            Payout {
+                salaries: HashMap::from([(WORKER_A, Money(150)), (WORKER_B, Money(50))]),
+                rent: HashMap::from([(LANDLORD, Money(0))]),
+                loans: HashMap::from([(BANK, Money(0)), (FRIEND, Money(0))]),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn taxes_get_what_is_left_after_salaries() {
+        let payout = split_payout(Money(550), &liabilities(), true);
+
+        assert_eq!(
+            payout,
+            Payout {
+                salaries: HashMap::from([(WORKER_A, Money(300)), (WORKER_B, Money(100))]),
+                taxes: Money(150),
+                rent: HashMap::from([(LANDLORD, Money(0))]),
+                loans: HashMap::from([(BANK, Money(0)), (FRIEND, Money(0))]),
+                owner: Money(0),
+            }
+        );
+    }
+
+    #[test]
+    fn rent_gets_what_is_left_after_taxes() {
+        let payout = split_payout(Money(650), &liabilities(), true);
+
+        assert_eq!(payout.taxes, Money(200));
+        assert_eq!(payout.rent, HashMap::from([(LANDLORD, Money(50))]));
+        assert_eq!(
+            payout.loans,
+            HashMap::from([(BANK, Money(0)), (FRIEND, Money(0))])
+        );
+    }
+
+    #[test]
+    fn lenders_share_by_size_of_their_loans_before_the_owner() {
+        let payout = split_payout(Money(1100), &liabilities(), true);
+
+        assert_eq!(payout.taxes, Money(200));
+        assert_eq!(payout.rent, HashMap::from([(LANDLORD, Money(100))]));
+        assert_eq!(
+            payout.loans,
+            HashMap::from([(BANK, Money(300)), (FRIEND, Money(100))])
+        );
+        assert_eq!(payout.owner, Money(0));
+    }
+
+    #[test]
+    fn owner_gets_the_rest_once_all_debts_are_paid() {
+        let payout = split_payout(Money(2100), &liabilities(), true);
+
+        assert_eq!(
+            payout.loans,
+            HashMap::from([(BANK, Money(750)), (FRIEND, Money(250))])
+        );
+        assert_eq!(payout.owner, Money(400));
+    }
+
+    #[test]
+    fn taxes_are_not_paid_without_a_tax_authority() {
+        let payout = split_payout(Money(700), &liabilities(), false);
+
+        assert_eq!(payout.taxes, Money(0));
+        assert_eq!(
+            payout.loans,
+            HashMap::from([(BANK, Money(150)), (FRIEND, Money(50))])
+        );
+    }
+
+    #[test]
+    fn released_workers_leave_every_line_for_good() {
+        let mut items = ItemRegistry::default();
+        items.load();
+        let (main_output, _) = items.iter().next().unwrap();
+        let cycle = ProductionCycle {
+            name: "test".to_string(),
+            input: HashMap::new(),
+            tools: HashMap::new(),
+            output: HashMap::from([(main_output, 1)]),
+            main_output,
+            workdays_needed: 1,
+            workdays_left: 1.0,
+            fixed_costs: Money(0),
+        };
+        let mut manufacturer = Manufacturer {
+            production_lines: vec![
+                ProductionLine::new(cycle.clone(), vec![WORKER_A]),
+                ProductionLine::new(cycle, vec![WORKER_B]),
+            ],
+            assets: Inventory::default(),
+            days_since_last_staff_change: 0,
+            production_log: Default::default(),
+            quality_investment: 0.0,
+            research: Research::default(),
+            storage_capacity: 0.0,
+            owner: FRIEND,
+        };
+
+        assert_eq!(release_workers(&mut manufacturer), vec![WORKER_A, WORKER_B]);
+        assert!(manufacturer
+            .production_lines
+            .iter()
+            .all(|line| line.hired_workers.is_empty() && line.target_workers == 0));
+    }
+}
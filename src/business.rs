@@ -2,7 +2,6 @@ use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt;
 use std::fmt::Display;
-use std::str::FromStr;
 
 use bevy::prelude::*;
 use either::{Either, Right};
@@ -11,36 +10,53 @@ use rand::seq::SliceRandom;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 
-use crate::config::Config;
+use crate::bankruptcy::{Liabilities, Liquidation};
+use crate::config::{Config, CostAllocation, InventoryPolicy, Quality, Skills};
+use crate::contracts::SupplyContract;
+use crate::entrepreneurs::{business_model, choose_business, MarketOverview, RiskScore};
 use macros::measured;
 
 use crate::government::{BusinessPermit, Government, TaxAuthority};
 use crate::init::{ProductionCycleTemplate, Templates};
+use crate::items::{ItemId, ItemRegistry};
 use crate::logs::LogEvent;
 use crate::money::Money;
 use crate::people::Person;
+use crate::pricing::{PriceChange, Pricing, PricingContext};
+use crate::recipes::RecipeGraph;
+use crate::regions::{Region, Regions, Shipment};
+use crate::research::{Research, Technologies};
+use crate::stats::{PriceHistory, DAYS_IN_MONTH};
+use crate::tools::Tools;
 use crate::ui::debug::Performance;
 use crate::wallet::{TradeSide, Transaction, TransactionError, Wallet};
 use crate::Days;
 
-#[derive(Hash, Eq, PartialEq, Debug, Clone, Ord, PartialOrd, Deserialize)]
-pub struct ItemType {
-    pub(crate) name: String,
+#[derive(Debug, Clone)]
+pub struct ProductionCycle {
+    /// Name of the template, technologies improve cycles by it
+    pub name: String,
+    pub input: HashMap<ItemId, u32>,
+    /// Items that must be present in the inventory but are only worn, not consumed
+    pub tools: HashMap<ItemId, u32>,
+    pub output: HashMap<ItemId, u32>,
+    /// Output the business is built around, the others are byproducts
+    pub main_output: ItemId,
+    pub workdays_needed: u32,
+    /// Workers contribute work by their productivity, so a day may bring fractions of a workday
+    pub workdays_left: f32,
+    /// Rent and utilities paid every month, even when nothing is produced
+    pub fixed_costs: Money,
 }
 
-impl Display for ItemType {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.name)?;
-        Ok(())
+impl ProductionCycle {
+    pub fn output_quantity(&self, item_type: &ItemId) -> u32 {
+        *self.output.get(item_type).unwrap_or(&0)
     }
-}
 
-#[derive(Debug, Clone)]
-pub struct ProductionCycle {
-    pub input: HashMap<ItemType, u32>,
-    pub output: (ItemType, u32),
-    pub workdays_needed: u32,
-    pub workdays_left: u32,
+    pub fn main_output_quantity(&self) -> u32 {
+        self.output_quantity(&self.main_output)
+    }
 }
 
 impl Display for ProductionCycle {
@@ -48,17 +64,30 @@ impl Display for ProductionCycle {
         writeln!(f, "Production Cycle:\n")?;
         writeln!(f, "Input:")?;
         for (item_type, count) in &self.input {
-            writeln!(f, "  - {}: {}", item_type.name, count)?;
+            writeln!(f, "  - {}: {}", item_type.name(), count)?;
+        }
+        if !self.tools.is_empty() {
+            writeln!(f, "Tools:")?;
+            for (item_type, count) in &self.tools {
+                writeln!(f, "  - {}: {}", item_type.name(), count)?;
+            }
         }
         writeln!(f, "Output:")?;
-        writeln!(f, "  - {}: {}\n", self.output.0.name, self.output.1)?;
+        for (item_type, count) in &self.output {
+            if *item_type == self.main_output {
+                writeln!(f, "  - {}: {}", item_type.name(), count)?;
+            } else {
+                writeln!(f, "  - {}: {} (byproduct)", item_type.name(), count)?;
+            }
+        }
+        writeln!(f)?;
         write!(f, "Workdays: {}", self.workdays_needed)
     }
 }
 
 #[derive(Debug, Default)]
 pub struct Inventory {
-    pub(crate) items: HashMap<ItemType, Vec<Item>>,
+    pub(crate) items: HashMap<ItemId, Vec<Item>>,
     pub(crate) items_to_sell: Vec<Item>,
 }
 
@@ -68,31 +97,198 @@ pub struct ManufacturerBundle {
     pub manufacturer: Manufacturer,
     pub sell_strategy: SellStrategy,
     pub wallet: Wallet,
+    pub liabilities: Liabilities,
+    pub region: Region,
 }
 
 #[derive(Debug)]
 pub struct ProductionLog {
-    date: usize,
+    pub(crate) date: usize,
+}
+
+/// Part of a business running its own production cycle with its own workers
+#[derive(Debug)]
+pub struct ProductionLine {
+    pub(crate) production_cycle: ProductionCycle,
+    pub(crate) hired_workers: Vec<Entity>,
+    /// Days until the line is set up for its production cycle, nothing is produced meanwhile
+    pub(crate) retooling_days_left: u32,
+    /// Unit cost of the main output in the last finished cycle
+    pub(crate) last_unit_cost: Money,
+    /// Workers the staffing plan aims for, hiring and firing follow it
+    pub(crate) target_workers: usize,
+}
+
+impl ProductionLine {
+    pub fn new(production_cycle: ProductionCycle, hired_workers: Vec<Entity>) -> Self {
+        ProductionLine {
+            production_cycle,
+            target_workers: hired_workers.len(),
+            hired_workers,
+            retooling_days_left: 0,
+            last_unit_cost: Money(0),
+        }
+    }
+
+    pub fn has_enough_input(&self, assets: &Inventory) -> bool {
+        let cycle = &self.production_cycle;
+        for (item_type, count) in cycle.input.iter().chain(cycle.tools.iter()) {
+            if assets.items.get(item_type).unwrap_or(&vec![]).len() < (*count as usize) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Workers per workday needed, 1.0 is a full crew
+    fn staffing(&self) -> f32 {
+        self.hired_workers.len() as f32 / self.production_cycle.workdays_needed.max(1) as f32
+    }
 }
 
 #[derive(Component, Debug)]
 pub struct Manufacturer {
-    pub(crate) production_cycle: ProductionCycle,
+    /// First line is the one the business was founded with, its main output is priced by the
+    /// sell strategy
+    pub(crate) production_lines: Vec<ProductionLine>,
     pub(crate) assets: Inventory,
-    pub(crate) hired_workers: Vec<Entity>,
     pub(crate) days_since_last_staff_change: u32,
     pub(crate) production_log: VecDeque<ProductionLog>,
+    /// Accumulated investment in quality, 1.0 is full investment. Wears off every month.
+    pub(crate) quality_investment: f32,
+    pub(crate) research: Research,
+    /// Cubic meters of warehouse space for inputs, tools and goods waiting to be sold
+    pub(crate) storage_capacity: f32,
     pub owner: Entity,
 }
 
 impl Manufacturer {
-    pub fn has_enough_input(&self) -> bool {
-        for (item_type, count) in &self.production_cycle.input {
-            if self.assets.items.get(item_type).unwrap_or(&vec![]).len() < (*count as usize) {
-                return false;
+    pub fn main_line(&self) -> &ProductionLine {
+        self.production_lines
+            .first()
+            .expect("Manufacturer should have at least one production line")
+    }
+
+    pub fn main_output(&self) -> &ItemId {
+        &self.main_line().production_cycle.main_output
+    }
+
+    /// Whether any of the lines is built around the item, byproducts don't count
+    pub fn produces(&self, item_type: &ItemId) -> bool {
+        self.production_lines
+            .iter()
+            .any(|line| line.production_cycle.main_output == *item_type)
+    }
+
+    pub fn hired_workers(&self) -> impl Iterator<Item = &Entity> {
+        self.production_lines
+            .iter()
+            .flat_map(|line| line.hired_workers.iter())
+    }
+
+    pub fn workers_count(&self) -> usize {
+        self.production_lines
+            .iter()
+            .map(|line| line.hired_workers.len())
+            .sum()
+    }
+
+    /// Monthly fixed costs of all lines
+    pub fn fixed_costs(&self) -> Money {
+        self.production_lines
+            .iter()
+            .map(|line| line.production_cycle.fixed_costs)
+            .sum()
+    }
+
+    /// Tools needed to run every line
+    pub fn tools(&self) -> HashMap<ItemId, u32> {
+        let mut tools = HashMap::new();
+        for line in &self.production_lines {
+            for (item_type, count) in &line.production_cycle.tools {
+                *tools.entry(*item_type).or_insert(0) += count;
             }
         }
-        true
+        tools
+    }
+
+    /// Inputs needed to run one cycle on every line
+    pub fn input(&self) -> HashMap<ItemId, u32> {
+        let mut input = HashMap::new();
+        for line in &self.production_lines {
+            for (item_type, count) in &line.production_cycle.input {
+                *input.entry(*item_type).or_insert(0) += count;
+            }
+        }
+        input
+    }
+
+    /// Workers still missing to fulfill the staffing plan of all lines
+    pub fn planned_openings(&self) -> usize {
+        self.production_lines
+            .iter()
+            .map(|line| line.target_workers.saturating_sub(line.hired_workers.len()))
+            .sum()
+    }
+
+    /// Workers above the staffing plan of all lines
+    pub fn planned_layoffs(&self) -> usize {
+        self.production_lines
+            .iter()
+            .map(|line| line.hired_workers.len().saturating_sub(line.target_workers))
+            .sum()
+    }
+
+    /// Line missing the most workers to its plan, the least staffed one breaks ties
+    fn hiring_line(&self) -> Option<usize> {
+        self.production_lines
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| {
+                let missing = |line: &ProductionLine| {
+                    line.hired_workers.len() as isize - line.target_workers as isize
+                };
+                missing(a)
+                    .cmp(&missing(b))
+                    .then(a.staffing().total_cmp(&b.staffing()))
+            })
+            .map(|(index, _)| index)
+    }
+
+    /// Industry of the line the next hired worker will work at
+    pub fn hiring_industry(&self) -> Option<&ItemId> {
+        self.hiring_line()
+            .map(|index| &self.production_lines[index].production_cycle.main_output)
+    }
+
+    /// New worker joins the line missing the most workers
+    pub fn hire(&mut self, worker: Entity) {
+        if let Some(index) = self.hiring_line() {
+            self.production_lines[index].hired_workers.push(worker);
+        }
+    }
+
+    /// Worker of the line most above its plan who brings the least value for the money is let go
+    pub fn fire(&mut self, value: impl Fn(&Entity, &ItemId) -> f32) -> Option<Entity> {
+        let line = self
+            .production_lines
+            .iter_mut()
+            .filter(|line| !line.hired_workers.is_empty())
+            .max_by(|a, b| {
+                let surplus = |line: &ProductionLine| {
+                    line.hired_workers.len() as isize - line.target_workers as isize
+                };
+                surplus(a)
+                    .cmp(&surplus(b))
+                    .then(a.staffing().total_cmp(&b.staffing()))
+            })?;
+        let industry = &line.production_cycle.main_output;
+        let (index, _) = line
+            .hired_workers
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| value(a, industry).total_cmp(&value(b, industry)))?;
+        Some(line.hired_workers.remove(index))
     }
 }
 
@@ -104,18 +300,23 @@ pub struct Worker {
 
 #[derive(Debug, Clone)]
 pub struct Item {
-    item_type: ItemType,
-    production_cost: Money,
-    buy_cost: Money,
+    pub(crate) item_type: ItemId,
+    pub(crate) production_cost: Money,
+    pub(crate) buy_cost: Money,
+    /// 1.0 is standard quality. Better items satisfy needs more and buyers pay more for them.
+    pub(crate) quality: f32,
+    /// 1.0 is a new item. Tools wear down in every cycle they're used in.
+    pub(crate) condition: f32,
 }
 
 #[derive(Component, Debug, Clone)]
 pub struct SellOrder {
     pub(crate) items: Vec<Item>,
-    pub(crate) item_type: ItemType,
+    pub(crate) item_type: ItemId,
     pub(crate) seller: Entity,
     pub(crate) price: Money,
     pub(crate) base_price: Money,
+    pub(crate) region: Region,
 }
 
 impl PartialEq for SellOrder {
@@ -126,11 +327,7 @@ impl PartialEq for SellOrder {
 
 impl PartialOrd for SellOrder {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(if self.price != other.price {
-            self.price.cmp(&other.price)
-        } else {
-            self.item_type.name.cmp(&other.item_type.name)
-        })
+        Some(self.cmp(other))
     }
 }
 
@@ -141,14 +338,24 @@ impl Ord for SellOrder {
         if self.price != other.price {
             self.price.cmp(&other.price)
         } else {
-            self.item_type.name.cmp(&other.item_type.name)
+            self.item_type.cmp(&other.item_type)
         }
     }
 }
 
+impl SellOrder {
+    /// Quality of the item that will be sold next
+    pub fn quality(&self) -> f32 {
+        self.items.last().map_or(1.0, |item| item.quality)
+    }
+}
+
 #[derive(Component, Copy, Clone, Debug, Serialize, Deserialize, Default)]
 pub struct SellStrategy {
     pub(crate) max_price_change_per_day: f32,
+    /// Set from the template or the config when the business is spawned
+    #[serde(skip)]
+    pub(crate) pricing: Pricing,
     #[serde(skip)]
     pub(crate) current_price: Money,
     #[serde(skip)]
@@ -162,124 +369,431 @@ pub enum OrderType {
 
 #[derive(Component, Debug, Clone)]
 pub struct BuyOrder {
-    pub(crate) item_type: ItemType,
+    pub(crate) item_type: ItemId,
     pub(crate) buyer: Entity,
     pub(crate) order: OrderType,
     pub(crate) expiration: Option<u64>,
+    pub(crate) region: Region,
 }
 
 #[derive(Component, Debug, Clone)]
 pub struct JobOffer {
     pub salary: Money,
     pub employer: Entity,
-    pub taken_by: Option<Entity>,
 }
 
 #[derive(Component, Clone, Default)]
 pub struct BuyStrategy {
-    pub(crate) target_production_cycles: u32,
-    pub(crate) outstanding_orders: HashMap<ItemType, u32>,
+    pub(crate) policy: InventoryPolicy,
+    pub(crate) outstanding_orders: HashMap<ItemId, u32>,
+}
+
+/// What a business knows about one of its inputs when deciding how much of it to buy
+struct InputStock {
+    per_cycle: u32,
+    in_stock: u32,
+    ordered: u32,
+    /// Used per day by the lines at their current staffing
+    daily_usage: f32,
+    /// Needed per day to keep up with recent sales, None before anything was sold
+    forecast_usage: Option<f32>,
+    /// Warehouse rent and capital tied up by one unit for a day
+    holding_cost: f64,
+}
+
+/// Stock level the input is replenished to, None while there is enough of it
+fn order_up_to(policy: InventoryPolicy, stock: &InputStock, config: &Config) -> Option<u32> {
+    let settings = &config.business.inventory;
+    let position = stock.in_stock + stock.ordered;
+    match policy {
+        InventoryPolicy::TargetCycles => {
+            let target = config.business.keep_resources_for_cycles_amount.value * stock.per_cycle;
+            (stock.in_stock < target).then_some(target)
+        }
+        InventoryPolicy::ReorderPoint => {
+            let reorder_point = settings.reorder_point.value * stock.per_cycle;
+            (position <= reorder_point).then_some(settings.order_up_to.value * stock.per_cycle)
+        }
+        InventoryPolicy::EconomicOrderQuantity => {
+            // reorders once less than a cycle is left, even free storage is not filled beyond
+            // what the reorder point policy restocks to
+            if position >= stock.per_cycle {
+                return None;
+            }
+            let max_batch = settings.order_up_to.value.saturating_mul(stock.per_cycle);
+            let batch = if stock.holding_cost > 0.0 {
+                ((2.0 * stock.daily_usage as f64 * settings.order_cost.value.as_f64()
+                    / stock.holding_cost)
+                    .sqrt() as u32)
+                    .min(max_batch)
+            } else {
+                max_batch
+            };
+            Some(position.saturating_add(batch.max(stock.per_cycle - position)))
+        }
+        InventoryPolicy::DemandForecast => {
+            // without any sales yet there is nothing to forecast, one cycle gets production going
+            let forecast = stock.forecast_usage.unwrap_or(0.0)
+                * settings.forecast_days.value as f32
+                * (1.0 + settings.safety_stock.value);
+            let target = (forecast.ceil() as u32).max(stock.per_cycle);
+            (position < target).then_some(target)
+        }
+    }
+}
+
+/// Input used per day by the lines at their current staffing
+fn daily_usage(manufacturer: &Manufacturer, item_type: &ItemId) -> f32 {
+    manufacturer
+        .production_lines
+        .iter()
+        .filter_map(|line| {
+            let cycle = &line.production_cycle;
+            cycle.input.get(item_type).map(|&quantity| {
+                quantity as f32 * line.hired_workers.len() as f32 / cycle.workdays_needed as f32
+            })
+        })
+        .sum()
+}
+
+/// Input needed per day to produce what the lines sold in the last days
+fn forecast_usage(
+    manufacturer: &Manufacturer,
+    wallet: &Wallet,
+    item_type: &ItemId,
+    date: usize,
+    days: u32,
+) -> Option<f32> {
+    let mut usage = 0.0;
+    let mut sold_any = false;
+    for line in manufacturer.production_lines.iter() {
+        let cycle = &line.production_cycle;
+        let Some(&quantity) = cycle.input.get(item_type) else {
+            continue;
+        };
+        let sold = wallet.get_amount_of_sell_transactions(date, &cycle.main_output, days as usize);
+        sold_any |= sold > 0;
+        usage += quantity as f32 * sold as f32
+            / days.max(1) as f32
+            / cycle.main_output_quantity().max(1) as f32;
+    }
+    sold_any.then_some(usage)
 }
 
 #[derive(Debug)]
 pub enum MaxCycleError {
     NoMaterialInInventory(String),
+    NoToolInInventory(String),
     // the String will contain the material name
     NotEnoughMaterialsOrWorkers,
     CantPayWorkers,
 }
 
+#[allow(clippy::too_many_arguments)]
 #[measured]
 pub fn produce(
-    mut manufacturers: Query<(&Wallet, &mut Manufacturer)>,
-    workers_query: Query<&Worker>,
+    mut manufacturers: Query<(&Wallet, &mut Manufacturer, &Region), Without<Liquidation>>,
+    workers_query: Query<(&Worker, &Person)>,
+    price_history: Res<PriceHistory>,
+    tools: Res<Tools>,
+    items: Res<ItemRegistry>,
     date: Res<Days>,
+    config: Res<Config>,
 ) {
-    for (wallet, mut manufacturer) in manufacturers.iter_mut() {
+    for (wallet, mut manufacturer, region) in manufacturers.iter_mut() {
+        tools.repair(
+            &mut manufacturer.assets,
+            config.business.tools.repair_below.value,
+        );
         // fill production cycle
         // produce_for_manufacturer(&mut b, commands, &production_cost);
-        execute_production_cycle(&mut manufacturer, wallet, &workers_query, &date)
+        execute_production_cycle(
+            &mut manufacturer,
+            wallet,
+            &workers_query,
+            &date,
+            &config.business.quality,
+            &config.business.skills,
+            config.business.research.staff_share.value,
+            &tools,
+            &items,
+            config.business.byproducts.cost_allocation.value,
+            |item_type| price_history.last_median(region, item_type),
+        )
     }
 }
 
+/// Quality of produced items. Own work quality grows with staffing (workers vs workdays needed,
+/// a full crew is standard) and investment, the result is averaged with quality of the inputs.
+fn output_quality(
+    line: &ProductionLine,
+    quality_investment: f32,
+    input_qualities: &[f32],
+    config: &Quality,
+) -> f32 {
+    let staffing = line.staffing().min(1.0);
+    let own_quality = 1.0 - config.understaffing_penalty.value * (1.0 - staffing)
+        + config.max_investment_bonus.value * quality_investment;
+    let quality = if input_qualities.is_empty() {
+        own_quality
+    } else {
+        let input_quality = input_qualities.iter().sum::<f32>() / input_qualities.len() as f32;
+        let weight = config.input_weight.value;
+        input_quality * weight + own_quality * (1.0 - weight)
+    };
+    quality.max(0.1)
+}
+
+/// Unit cost of every output of the cycle, total cost is split between outputs by the allocation rule
+fn allocate_production_cost(
+    cycle: &ProductionCycle,
+    total_cost: Money,
+    allocation: CostAllocation,
+    price_of: impl Fn(&ItemId) -> Option<Money>,
+) -> HashMap<ItemId, Money> {
+    let quantity_weights = || {
+        cycle
+            .output
+            .iter()
+            .map(|(item_type, &quantity)| (*item_type, quantity as f32))
+            .collect::<HashMap<_, _>>()
+    };
+    let weights = match allocation {
+        CostAllocation::Quantity => quantity_weights(),
+        CostAllocation::MarketValue => cycle
+            .output
+            .iter()
+            .map(|(item_type, &quantity)| {
+                price_of(item_type)
+                    .map(|price| (*item_type, quantity as f32 * price.as_f64() as f32))
+            })
+            .collect::<Option<HashMap<_, _>>>()
+            // not every output has a market price yet
+            .unwrap_or_else(quantity_weights),
+        CostAllocation::MainOutput => cycle
+            .output
+            .iter()
+            .map(|(item_type, &quantity)| {
+                let weight = if *item_type == cycle.main_output {
+                    quantity as f32
+                } else {
+                    0.0
+                };
+                (*item_type, weight)
+            })
+            .collect(),
+    };
+    let total_weight = weights.values().sum::<f32>();
+    cycle
+        .output
+        .iter()
+        .map(|(item_type, &quantity)| {
+            let share = if total_weight > 0.0 {
+                weights[item_type] / total_weight
+            } else {
+                1.0 / cycle.output.len() as f32
+            };
+            (*item_type, total_cost * share / quantity.max(1))
+        })
+        .collect()
+}
+
+#[allow(clippy::too_many_arguments)]
 fn execute_production_cycle(
     manufacturer: &mut Mut<Manufacturer>,
     wallet: &Wallet,
-    workers_query: &Query<&Worker>,
+    workers_query: &Query<(&Worker, &Person)>,
     date: &Res<Days>,
+    quality_config: &Quality,
+    skills_config: &Skills,
+    research_share: f32,
+    tools: &Tools,
+    items: &ItemRegistry,
+    allocation: CostAllocation,
+    price_of: impl Fn(&ItemId) -> Option<Money>,
 ) {
-    match work_on_cycle_possible(wallet, manufacturer, workers_query) {
-        Ok(cost_per_day) => {
-            if manufacturer.production_cycle.workdays_left > manufacturer.hired_workers.len() as u32
-            {
-                // Continue the existing cycle
-                manufacturer.production_cycle.workdays_left -=
-                    manufacturer.hired_workers.len() as u32;
-            } else {
-                // Start a new cycle
-                let input = manufacturer.production_cycle.input.clone();
-                let mut buy_costs = Money(0);
-                for (input_material, quantity_needed) in input.iter() {
-                    // drain the quantity needed from the inventory and sum up costs
-                    let item_costs: Money = manufacturer
-                        .assets
-                        .items
-                        .get_mut(input_material)
-                        .unwrap()
-                        .drain(..*quantity_needed as usize)
-                        .map(|item| item.buy_cost)
-                        .sum::<Money>();
-                    buy_costs += item_costs;
-                }
-                let (output_material, quantity_produced) =
-                    &manufacturer.production_cycle.output.clone();
-                let unit_cost = buy_costs / (*quantity_produced)
-                    + cost_per_day * manufacturer.production_cycle.workdays_needed
-                        / (*quantity_produced);
-                for _ in 0..*quantity_produced {
-                    let output_item = Item {
-                        item_type: output_material.clone(),
-                        production_cost: unit_cost,
-                        buy_cost: Money(0),
-                    };
-                    debug!("Produced {:?}", output_item);
-                    manufacturer.assets.items_to_sell.push(output_item);
-                    manufacturer
-                        .production_log
-                        .push_front(ProductionLog { date: date.days });
+    let main_output = *manufacturer.main_output();
+    let Manufacturer {
+        production_lines,
+        assets,
+        production_log,
+        quality_investment,
+        research,
+        storage_capacity,
+        ..
+    } = &mut **manufacturer;
+    for line in production_lines.iter_mut() {
+        if line.retooling_days_left > 0 {
+            debug!(
+                "Line is retooling for {}, {} days left",
+                line.production_cycle.main_output, line.retooling_days_left
+            );
+            line.retooling_days_left -= 1;
+            continue;
+        }
+        match work_on_cycle_possible(wallet, line, assets, workers_query) {
+            Ok(cost_per_day) => {
+                let industry = &line.production_cycle.main_output;
+                let work_per_day: f32 = line
+                    .hired_workers
+                    .iter()
+                    .filter_map(|worker| workers_query.get(*worker).ok())
+                    .map(|(_, person)| person.productivity(industry, skills_config))
+                    .sum();
+                let work_per_day = if let Some(project) = research.project.as_mut() {
+                    // part of the staff works on research instead
+                    let research_work = work_per_day * research_share;
+                    project.workdays_left -= research_work;
+                    work_per_day - research_work
+                } else {
+                    work_per_day
+                };
+                if line.production_cycle.workdays_left > work_per_day {
+                    // Continue the existing cycle
+                    line.production_cycle.workdays_left -= work_per_day;
+                } else {
+                    let freed_volume = volume_of(&line.production_cycle.input, items);
+                    let added_volume = volume_of(&line.production_cycle.output, items);
+                    if items.stored_volume(assets) - freed_volume + added_volume > *storage_capacity
+                    {
+                        debug!(
+                            "Warehouse is full, can't store {}",
+                            line.production_cycle.main_output
+                        );
+                        continue;
+                    }
+                    // Start a new cycle
+                    let mut buy_costs = Money(0);
+                    let mut input_qualities = vec![];
+                    for (input_material, quantity_needed) in line.production_cycle.input.iter() {
+                        // drain the quantity needed from the inventory and sum up costs
+                        let item_costs: Money = assets
+                            .items
+                            .get_mut(input_material)
+                            .unwrap()
+                            .drain(..*quantity_needed as usize)
+                            .map(|item| {
+                                input_qualities.push(item.quality);
+                                item.buy_cost
+                            })
+                            .sum::<Money>();
+                        buy_costs += item_costs;
+                    }
+                    let depreciation = wear_tools(&line.production_cycle, assets, tools);
+                    // the line carries the fixed costs for as many days as the cycle takes
+                    let cycle_days =
+                        line.production_cycle.workdays_needed as f32 / work_per_day.max(0.01);
+                    let fixed_costs =
+                        line.production_cycle.fixed_costs * (cycle_days / DAYS_IN_MONTH as f32);
+                    let total_cost = buy_costs
+                        + depreciation
+                        + fixed_costs
+                        + cost_per_day * line.production_cycle.workdays_needed;
+                    let unit_costs = allocate_production_cost(
+                        &line.production_cycle,
+                        total_cost,
+                        allocation,
+                        &price_of,
+                    );
+                    let quality =
+                        output_quality(line, *quality_investment, &input_qualities, quality_config);
+                    for (output_material, quantity_produced) in line.production_cycle.output.iter()
+                    {
+                        for _ in 0..*quantity_produced {
+                            let output_item = Item {
+                                item_type: *output_material,
+                                production_cost: unit_costs[output_material],
+                                buy_cost: Money(0),
+                                quality,
+                                condition: 1.0,
+                            };
+                            debug!("Produced {:?}", output_item);
+                            assets.items_to_sell.push(output_item);
+                            // selling strategy is driven by the main output only
+                            if *output_material == main_output {
+                                production_log.push_front(ProductionLog { date: date.days });
+                            }
+                        }
+                    }
+                    line.last_unit_cost = unit_costs[&line.production_cycle.main_output];
+                    line.production_cycle.workdays_left =
+                        line.production_cycle.workdays_needed as f32;
                 }
-                manufacturer.production_cycle.workdays_left =
-                    manufacturer.production_cycle.workdays_needed;
             }
+            Err(e) => match e {
+                MaxCycleError::NoMaterialInInventory(material) => {
+                    debug!("No material {} in inventory, can't work on cycle", material);
+                }
+                MaxCycleError::NoToolInInventory(tool) => {
+                    debug!("No tool {} in inventory, can't work on cycle", tool);
+                }
+                MaxCycleError::NotEnoughMaterialsOrWorkers => {
+                    debug!(
+                        "Not enough materials or workers to run a cycle, nothing will be produced"
+                    );
+                }
+                MaxCycleError::CantPayWorkers => {
+                    debug!("Dear Lord, we can't even pay our workers, we're doomed!");
+                }
+            },
         }
-        Err(e) => match e {
-            MaxCycleError::NoMaterialInInventory(material) => {
-                debug!("No material {} in inventory, can't work on cycle", material);
-            }
-            MaxCycleError::NotEnoughMaterialsOrWorkers => {
-                debug!("Not enough materials or workers to run a cycle, nothing will be produced");
-            }
-            MaxCycleError::CantPayWorkers => {
-                debug!("Dear Lord, we can't even pay our workers, we're doomed!");
+    }
+}
+
+/// Wears down the tools used by the cycle and returns their depreciation. Tools in the best
+/// condition are used first, worn out tools are scrapped.
+fn wear_tools(cycle: &ProductionCycle, assets: &mut Inventory, tools: &Tools) -> Money {
+    let mut depreciation = Money(0);
+    for (tool_type, &quantity_needed) in cycle.tools.iter() {
+        let wear = tools
+            .tools
+            .get(tool_type)
+            .map_or(0.0, |tool| tool.wear_per_cycle);
+        let Some(items) = assets.items.get_mut(tool_type) else {
+            continue;
+        };
+        items.sort_by(|a, b| b.condition.total_cmp(&a.condition));
+        for tool in items.iter_mut().take(quantity_needed as usize) {
+            let lost = wear.min(tool.condition);
+            // buy cost of a tool is its book value, spread over the remaining condition
+            let value_lost = tool.buy_cost * (lost / tool.condition);
+            depreciation += value_lost;
+            tool.buy_cost -= value_lost;
+            tool.condition -= lost;
+        }
+        items.retain(|tool| {
+            let scrapped = tool.condition <= 0.0;
+            if scrapped {
+                debug!("{} is worn out and scrapped", tool_type);
             }
-        },
+            !scrapped
+        });
     }
+    depreciation
+}
+
+fn volume_of(counts: &HashMap<ItemId, u32>, items: &ItemRegistry) -> f32 {
+    counts
+        .iter()
+        .map(|(item_type, &count)| items.volume(item_type) * count as f32)
+        .sum()
 }
 
 fn work_on_cycle_possible(
     wallet: &Wallet,
-    manufacturer: &Mut<Manufacturer>,
-    workers_query: &Query<&Worker>,
+    line: &ProductionLine,
+    assets: &Inventory,
+    workers_query: &Query<(&Worker, &Person)>,
 ) -> Result<Money, MaxCycleError> {
-    for (input_material, &quantity_needed) in manufacturer.production_cycle.input.iter() {
-        if let Some(items_in_inventory) = manufacturer.assets.items.get(input_material) {
+    for (input_material, &quantity_needed) in line.production_cycle.input.iter() {
+        if let Some(items_in_inventory) = assets.items.get(input_material) {
             if items_in_inventory.len() < quantity_needed as usize {
                 debug!(
                     "Not enough material {:?} in inventory, work on cycle not possible",
                     input_material
                 );
                 return Err(MaxCycleError::NoMaterialInInventory(
-                    input_material.name.to_string(),
+                    input_material.name().to_string(),
                 ));
             }
         } else {
@@ -288,20 +802,32 @@ fn work_on_cycle_possible(
                 input_material
             );
             return Err(MaxCycleError::NoMaterialInInventory(
-                input_material.name.to_string(),
+                input_material.name().to_string(),
             ));
         }
     }
 
-    if manufacturer.hired_workers.is_empty() {
+    for (tool, &quantity_needed) in line.production_cycle.tools.iter() {
+        if assets.items.get(tool).map_or(0, |items| items.len()) < quantity_needed as usize {
+            debug!(
+                "Not enough tools {:?} in inventory, work on cycle not possible",
+                tool
+            );
+            return Err(MaxCycleError::NoToolInInventory(tool.name().to_string()));
+        }
+    }
+
+    if line.hired_workers.is_empty() {
         debug!("Not enough workers to work on a cycle, nothing will be produced");
         return Err(MaxCycleError::NotEnoughMaterialsOrWorkers);
     }
 
     // Calculate the cost for one day of work
     let mut cost_per_day = Money(0);
-    for worker in manufacturer.hired_workers.iter() {
-        cost_per_day += workers_query.get(*worker).map_or(Money(0), |w| w.salary);
+    for worker in line.hired_workers.iter() {
+        cost_per_day += workers_query
+            .get(*worker)
+            .map_or(Money(0), |(w, _)| w.salary);
     }
     debug!("Salaries cost per day: {}", cost_per_day);
 
@@ -316,13 +842,20 @@ fn work_on_cycle_possible(
 #[measured]
 pub fn create_sell_orders(
     mut commands: Commands,
-    mut manufacturers: Query<(Entity, &mut Manufacturer, &mut SellStrategy)>,
+    mut manufacturers: Query<(Entity, &mut Manufacturer, &mut SellStrategy, &Region)>,
     mut logs: EventWriter<LogEvent>,
 ) {
-    for (seller, mut manufacturer, mut strategy) in manufacturers.iter_mut() {
-        let amount_to_sell = (manufacturer.assets.items_to_sell.len()
-            * manufacturer.hired_workers.len())
-            / manufacturer.production_cycle.workdays_needed as usize;
+    for (seller, mut manufacturer, mut strategy, region) in manufacturers.iter_mut() {
+        let waiting = manufacturer.assets.items_to_sell.len();
+        let amount_to_sell = manufacturer
+            .production_lines
+            .iter()
+            .map(|line| {
+                (waiting * line.hired_workers.len())
+                    / line.production_cycle.workdays_needed as usize
+            })
+            .sum::<usize>()
+            .min(waiting);
         debug!(
             "Creating sell orders for {} items from {}",
             amount_to_sell,
@@ -338,26 +871,41 @@ pub fn create_sell_orders(
             items_to_sell.len(),
             manufacturer.assets.items_to_sell.len()
         );
-        if let Some(first_item) = items_to_sell.get(0) {
-            let item_name = first_item.item_type.name.clone();
+        // byproducts and products of other lines are listed in separate orders
+        let mut items_by_type: HashMap<ItemId, Vec<Item>> = HashMap::new();
+        for item in items_to_sell {
+            items_by_type.entry(item.item_type).or_default().push(item);
+        }
+        let main_output = *manufacturer.main_output();
+        if let Some(first_item) = items_by_type.get(&main_output).and_then(|i| i.first()) {
             strategy.base_price = first_item.production_cost;
             if strategy.current_price == Money(0) {
                 strategy.current_price = first_item.production_cost;
                 logs.send(LogEvent::Generic {
                     text: format!(
                         "I'm just starting, setting the price for {} to production cost: {}",
-                        first_item.item_type.name.as_str(),
+                        first_item.item_type.name(),
                         strategy.current_price
                     ),
                     entity: seller,
                 });
             }
+        }
+        for (item_type, items) in items_by_type {
+            let base_price = items.first().unwrap().production_cost;
+            let price = if item_type == main_output {
+                strategy.current_price
+            } else {
+                byproduct_price(&strategy, base_price)
+            };
+            let item_name = item_type.name();
             let sell_order = SellOrder {
-                items: items_to_sell.to_vec(),
-                item_type: first_item.item_type.clone(),
+                items,
+                item_type,
                 seller,
-                price: strategy.current_price,
-                base_price: strategy.base_price,
+                price,
+                base_price,
+                region: region.clone(),
             };
             debug!(
                 "Created sell order {:?} for {} with total {} items",
@@ -375,19 +923,34 @@ pub fn create_sell_orders(
     }
 }
 
+/// Byproducts are sold with the same margin over their allocated cost as the main output
+pub(crate) fn byproduct_price(strategy: &SellStrategy, byproduct_cost: Money) -> Money {
+    if strategy.base_price == Money(0) {
+        return byproduct_cost;
+    }
+    let margin = strategy.current_price.as_f64() / strategy.base_price.as_f64();
+    (byproduct_cost * margin as f32).max(Money(1))
+}
+
 pub fn merge_sell_orders(mut sell_orders: Query<(Entity, &mut SellOrder)>) {
-    // Map from seller to (first_order_entity, accumulated_items)
-    let mut order_map: HashMap<Entity, (Entity, Vec<Item>)> = HashMap::new();
+    // Map from seller, item type and region to (first_order_entity, accumulated_items).
+    // A seller can have orders of different types, e.g. an owner of several bankrupt businesses.
+    let mut order_map: HashMap<(Entity, ItemId, Region), (Entity, Vec<Item>)> = HashMap::new();
 
     for (order_entity, mut sell_order) in sell_orders.iter_mut() {
-        match order_map.get_mut(&sell_order.seller) {
+        let key = (
+            sell_order.seller,
+            sell_order.item_type,
+            sell_order.region.clone(),
+        );
+        match order_map.get_mut(&key) {
             Some((_, first_order_items)) => {
                 // Accumulate items and despawn if not the first order.
                 first_order_items.append(&mut sell_order.items);
             }
             None => {
                 // This is the first order from this seller, remember it.
-                order_map.insert(sell_order.seller, (order_entity, sell_order.items.clone()));
+                order_map.insert(key, (order_entity, sell_order.items.clone()));
             }
         }
     }
@@ -415,18 +978,25 @@ pub fn delete_empty_sell_orders(mut commands: Commands, sell_orders: Query<(Enti
 #[measured]
 pub fn update_sell_order_prices(
     mut sell_orders: Query<(Entity, &Name, &mut SellOrder)>,
-    sell_strategies: Query<&SellStrategy>,
+    sell_strategies: Query<(&SellStrategy, Option<&Manufacturer>)>,
 ) {
     for (_, name, mut sell_order) in sell_orders.iter_mut() {
         // startegy many not exist anymore when the business went bankrupt, he sells at the base price
-        if let Ok(sell_strategy) = sell_strategies.get(sell_order.seller) {
-            sell_order.price = sell_strategy.current_price;
+        if let Ok((sell_strategy, manufacturer)) = sell_strategies.get(sell_order.seller) {
+            // other lines are priced like byproducts, only the main output has a strategy
+            let is_byproduct =
+                manufacturer.is_some_and(|m| *m.main_output() != sell_order.item_type);
+            sell_order.price = if is_byproduct {
+                byproduct_price(sell_strategy, sell_order.base_price)
+            } else {
+                sell_strategy.current_price
+            };
             debug!(
                 "Updated {} sell order price to {}",
                 name.as_str(),
                 sell_order.price
             );
-            if sell_strategy.current_price < sell_order.base_price {
+            if sell_order.price < sell_order.base_price {
                 debug!("Oh my god, we're selling {} at a loss!", name.as_str());
             }
         }
@@ -434,89 +1004,74 @@ pub fn update_sell_order_prices(
 }
 
 pub fn update_sell_strategy_margin(
-    mut manufacturers: Query<(Entity, &mut SellStrategy, &Wallet, &Manufacturer)>,
+    mut manufacturers: Query<(Entity, &mut SellStrategy, &Wallet, &Manufacturer, &Region)>,
+    sell_orders: Query<&SellOrder>,
+    price_history: Res<PriceHistory>,
     mut logs: EventWriter<LogEvent>,
     date: Res<Days>,
 ) {
-    let days_to_look_at = 30;
-    for (seller, mut sell_strategy, wallet, manufacturer) in manufacturers.iter_mut() {
-        let sold_items = wallet.get_amount_of_sell_transactions(
-            date.days,
-            &manufacturer.production_cycle.output.0,
-            days_to_look_at,
-        );
-        let produced_items = manufacturer
-            .production_log
+    let mut listed: HashMap<(Entity, ItemId), usize> = HashMap::new();
+    for sell_order in sell_orders.iter() {
+        *listed
+            .entry((sell_order.seller, sell_order.item_type))
+            .or_default() += sell_order.items.len();
+    }
+    for (seller, mut sell_strategy, wallet, manufacturer, region) in manufacturers.iter_mut() {
+        let main_output = *manufacturer.main_output();
+        let waiting = manufacturer
+            .assets
+            .items_to_sell
             .iter()
-            .take_while(|log| date.days - log.date <= days_to_look_at)
+            .filter(|item| item.item_type == main_output)
             .count();
-        logs.send(LogEvent::Generic {
-            text: format!(
-                "I'm thinking about my selling strategy, I've sold {} items and produced {} items.",
-                sold_items, produced_items
-            ),
-            entity: seller,
-        });
-        if produced_items == 0 {
-            continue;
-        }
-        let lower_bound = 0.5;
-        let upper_bound = 0.8;
-        let selling_ratio = sold_items as f32 / produced_items as f32;
-        let change = if selling_ratio < lower_bound {
-            let change =
-                1.0 - (lower_bound - selling_ratio) * sell_strategy.max_price_change_per_day;
-            logs.send(LogEvent::Generic { text: format!("I'm selling too slow! Time to decrease price to {} (ratio {:.2}, change {:.2}%)", sell_strategy.current_price, selling_ratio, 100.0 * change), entity: seller });
-            change
-        } else if selling_ratio > upper_bound {
-            let mut change =
-                (selling_ratio - upper_bound).min(1.0) * sell_strategy.max_price_change_per_day;
-            if sell_strategy.current_price < sell_strategy.base_price {
-                change *= 10.0;
-            }
-            change += 1.0;
-            logs.send(LogEvent::Generic { text: format!("I'm selling too fast! Time to increase price to {} (ratio {:.2}, change {:.2}%)", sell_strategy.current_price, selling_ratio, 100.0 * change), entity: seller });
-            change
-            // sell_strategy.current_price -= change;
-            // if sell_strategy.current_price < 0.3 {
-            //     sell_strategy.current_price = 0.3;
-            // } else {
-            // }
-        } else {
-            logs.send(LogEvent::Generic {
-                text: format!(
-                    "I'm selling at a right price! {} (ratio {:.2}, change {:.2}%)",
-                    sell_strategy.current_price, selling_ratio, 100.0
-                ),
-                entity: seller,
-            });
-            1.0
+        let context = PricingContext {
+            strategy: &sell_strategy,
+            manufacturer,
+            wallet,
+            in_stock: waiting + listed.get(&(seller, main_output)).unwrap_or(&0),
+            market_price: price_history.last_median(region, &main_output),
+            date: date.days,
         };
+        let PriceChange { factor, reason } =
+            sell_strategy.pricing.strategy().price_change(&context);
         let old_price = sell_strategy.current_price;
-        sell_strategy.current_price *= change;
+        sell_strategy.current_price *= factor;
         // ensure there is at least a little change in price
-        if sell_strategy.current_price == old_price && change > 1.0 {
+        if sell_strategy.current_price == old_price && factor > 1.0 {
             sell_strategy.current_price += Money(1);
         }
         if sell_strategy.current_price == old_price
-            && change < 1.0
+            && factor < 1.0
             && sell_strategy.current_price > Money(1)
         {
             sell_strategy.current_price -= Money(1);
         }
+        logs.send(LogEvent::Generic {
+            text: format!(
+                "{} Pricing by {}, price {} -> {} (change {:.2}%)",
+                reason,
+                sell_strategy.pricing.name(),
+                old_price,
+                sell_strategy.current_price,
+                100.0 * factor
+            ),
+            entity: seller,
+        });
     }
 }
 
 #[allow(clippy::too_many_arguments)]
 pub fn create_business(
-    mut people: Query<(Entity, &mut Person)>,
+    mut people: Query<(Entity, &mut Person, &Region)>,
     mut wallets: Query<&mut Wallet>,
     workers: Query<&Worker>,
     templates: Res<Templates>,
+    technologies: Res<Technologies>,
     govenrments: Query<(Entity, &Government)>,
     business_permits: Query<(Entity, &BusinessPermit)>,
     manufacturers: Query<(Entity, &Manufacturer)>,
     buy_orders: Query<&BuyOrder>,
+    price_history: Res<PriceHistory>,
     mut commands: Commands,
     mut logs: EventWriter<LogEvent>,
     date: Res<Days>,
@@ -526,78 +1081,87 @@ pub fn create_business(
         .iter()
         .next()
         .expect("There should be a government");
-    let demand = buy_orders
-        .iter()
-        .fold(HashMap::new(), |mut acc, buy_order| {
-            *acc.entry(buy_order.item_type.clone()).or_insert(0) += 1;
-            acc
-        });
+    let demand = demand_by_item_type(&buy_orders);
     let unemployed = people
         .iter_mut()
-        .filter(|(person, _)| workers.get(*person).is_err())
+        .filter(|(person, _, _)| workers.get(*person).is_err())
         .count();
     if unemployed == 0 {
         return;
     }
     let last_days = config.business.prices.sell_history_to_consider.value;
     let sells_in_last_days =
-        manufacturers
-            .iter()
-            .fold(HashMap::new(), |mut acc, (entity, manufacturer)| {
-                let manufacturer_wallet = wallets.get(entity).unwrap();
-                let sells = manufacturer_wallet.get_amount_of_sell_transactions(
-                    date.days,
-                    &manufacturer.production_cycle.output.0,
-                    last_days,
-                );
-                *acc.entry(&manufacturer.production_cycle.output.0)
-                    .or_insert(0) += sells;
-                acc
-            });
+        sells_by_item_type(manufacturers.iter(), &wallets, date.days, last_days);
+    let mut producers = producers_by_item_type(manufacturers.iter().map(|(_, m)| m));
+    // founders start with common knowledge only, locked cycles need research first
+    let cycles = technologies.available_cycles(&templates.production_cycles, &HashSet::new());
+    let settings = &config.business.entrepreneurs;
+    let model = business_model(settings);
+    let min_money = settings
+        .min_money
+        .value
+        .max(config.business.money_to_create_business.value);
     for (permit, _) in business_permits.iter() {
-        for (entity, _) in people.iter_mut() {
+        for (entity, person, region) in people.iter_mut() {
             let mut wallet = wallets.get_mut(entity).unwrap();
-            if wallet.money() > Money::from_str("100k").unwrap() {
-                if let Some(cycle) = choose_best_business(
-                    &demand,
-                    &sells_in_last_days,
-                    &manufacturers,
-                    &templates.production_cycles,
-                ) {
+            if wallet.money() > min_money {
+                let market = MarketOverview {
+                    demand: &demand,
+                    sells: &sells_in_last_days,
+                    producers: &producers,
+                    sell_history_days: last_days,
+                    prices: &price_history,
+                    region,
+                    recipes: &templates.recipes,
+                    salary: config.business.new_worker_salary.value,
+                };
+                if let Some((cycle, (_, production_cycle))) =
+                    choose_business(model.as_ref(), &cycles, &market, person.risk_tolerance)
+                        .and_then(|cycle| Some((cycle, cycle.to_production_cycle()?)))
+                {
+                    let main_output = production_cycle.main_output;
                     logs.send(LogEvent::Generic {
-                        text: format!("I'm creating a business for {}", cycle.output.0.as_str()),
+                        text: format!(
+                            "I'm creating a business for {}",
+                            cycle.main_output().as_str()
+                        ),
                         entity,
                     });
                     let mut new_wallet = Wallet::default();
                     let business_id = commands
                         .spawn((
                             Manufacturer {
-                                production_cycle: cycle.to_production_cycle().1,
-                                hired_workers: vec![],
+                                production_lines: vec![ProductionLine::new(
+                                    production_cycle,
+                                    vec![],
+                                )],
                                 assets: Inventory::default(),
                                 production_log: VecDeque::new(),
                                 days_since_last_staff_change: 0,
+                                quality_investment: 0.0,
+                                research: Research::default(),
+                                storage_capacity: config.business.storage.initial_capacity.value,
                                 owner: entity,
                             },
-                            Name::new(format!("{} factory", cycle.output.0.as_str())),
+                            Name::new(format!("{} factory", cycle.main_output().as_str())),
                             SellStrategy {
                                 max_price_change_per_day: config
                                     .business
                                     .prices
                                     .max_change_per_day
                                     .value,
+                                pricing: config.business.pricing.value,
                                 ..Default::default()
                             },
                             BuyStrategy {
-                                target_production_cycles: config
-                                    .business
-                                    .keep_resources_for_cycles_amount
-                                    .value,
+                                policy: config.business.inventory.policy.value,
                                 ..Default::default()
                             },
                             TaxAuthority {
                                 authority: governor,
                             },
+                            Liabilities::default(),
+                            region.clone(),
                         ))
                         .id();
                     wallet
@@ -615,6 +1179,8 @@ pub fn create_business(
                         .unwrap(); // this must work as we check for money above
                     commands.entity(business_id).insert(new_wallet);
                     commands.entity(permit).despawn();
+                    // next founders see the new business as competition
+                    *producers.entry(main_output).or_insert(0) += 1;
                     break;
                 }
             }
@@ -622,162 +1188,514 @@ pub fn create_business(
     }
 }
 
-fn choose_best_business<'a>(
-    demand: &HashMap<ItemType, usize>,
-    sells: &HashMap<&ItemType, usize>,
-    manufacturers: &Query<(Entity, &Manufacturer)>,
-    cycles: &'a Vec<ProductionCycleTemplate>,
-) -> Option<&'a ProductionCycleTemplate> {
-    let demand_count_by_item_type = demand.iter().fold(
-        HashMap::new(),
-        |mut acc: HashMap<ItemType, usize>, (item_type, count)| {
-            *acc.entry(item_type.clone()).or_insert(0) += count;
-            acc
-        },
-    );
-    debug!("{:?}", demand_count_by_item_type);
-    let manufacturers_count_by_item_type = manufacturers.iter().fold(
-        HashMap::new(),
-        |mut acc: HashMap<ItemType, usize>, (_, manufacturer)| {
-            *acc.entry(manufacturer.production_cycle.output.0.clone())
-                .or_insert(0) += 1;
+fn demand_by_item_type(buy_orders: &Query<&BuyOrder>) -> HashMap<ItemId, usize> {
+    buy_orders
+        .iter()
+        .fold(HashMap::new(), |mut acc, buy_order| {
+            *acc.entry(buy_order.item_type).or_insert(0) += 1;
             acc
-        },
-    );
-    cycles.iter().map(
-        |cycle| {
-            let demand_exists = demand_count_by_item_type
-                .get(&ItemType { name: cycle.output.0.clone() })
-                .unwrap_or(&0).min(&(1_usize));
-            let sells = sells.get(&ItemType { name: cycle.output.0.clone() }).unwrap_or(&0);
-            let extreme_demand = demand_count_by_item_type
-                .get(&ItemType { name: cycle.output.0.clone() })
-                .unwrap_or(&0) > sells && sells > &0_usize;
-            let count_by_manufacturers = manufacturers_count_by_item_type
-                .get(&ItemType { name: cycle.output.0.clone() })
-                .unwrap_or(&0);
-            let extreme_demand_bonus = if extreme_demand { 5 } else { 0 };
-            let process_complexity = find_required_inputs(&cycle.output.0, cycles);
-            let complexity_risk = 0;//process_complexity.len();
-            let missing_input_risk = process_complexity.iter().fold(0, |acc, input| {
-                if manufacturers_count_by_item_type.contains_key(&ItemType { name: input.clone() }) {
-                    acc
-                } else {
-                    acc + 1
-                }
-            });
+        })
+}
 
-            let risk = extreme_demand_bonus + *demand_exists as i32 - *count_by_manufacturers as i32 - complexity_risk - missing_input_risk;
-            debug!("Risk calculation for {} = {}: extreme_demand: {}, demand exists: {} competition size: {} process_complexity: {} missing input: {}", cycle.output.0.as_str(), risk, extreme_demand, demand_exists, count_by_manufacturers, complexity_risk, missing_input_risk);
-            (cycle, risk)
-        }).max_by_key(|(_, count)| *count).map(|(cycle, _)| cycle)
+/// Items sold in the last days by manufacturers, counted for main outputs of all their lines
+fn sells_by_item_type<'a>(
+    manufacturers: impl Iterator<Item = (Entity, &'a Manufacturer)>,
+    wallets: &Query<&mut Wallet>,
+    today: usize,
+    last_days: usize,
+) -> HashMap<ItemId, usize> {
+    manufacturers.fold(HashMap::new(), |mut acc, (entity, manufacturer)| {
+        let Ok(manufacturer_wallet) = wallets.get(entity) else {
+            return acc;
+        };
+        for line in &manufacturer.production_lines {
+            let item_type = &line.production_cycle.main_output;
+            let sells =
+                manufacturer_wallet.get_amount_of_sell_transactions(today, item_type, last_days);
+            *acc.entry(*item_type).or_insert(0) += sells;
+        }
+        acc
+    })
 }
 
-#[allow(clippy::too_many_arguments)]
-#[measured]
-pub fn bankruption(
-    manufacturers: Query<(Entity, &Name, &Manufacturer)>,
+/// Number of production lines built around each item
+fn producers_by_item_type<'a>(
+    manufacturers: impl Iterator<Item = &'a Manufacturer>,
+) -> HashMap<ItemId, usize> {
+    manufacturers.fold(HashMap::new(), |mut acc, manufacturer| {
+        for line in &manufacturer.production_lines {
+            *acc.entry(line.production_cycle.main_output).or_insert(0) += 1;
+        }
+        acc
+    })
+}
+
+/// Business an established owner expands into, scored without regard to prices
+fn choose_best_business<'a>(
+    demand: &HashMap<ItemId, usize>,
+    sells: &HashMap<ItemId, usize>,
+    producers: &HashMap<ItemId, usize>,
+    cycles: &'a [ProductionCycleTemplate],
+    recipes: &RecipeGraph,
+    prices: &PriceHistory,
+    region: &Region,
+) -> Option<&'a ProductionCycleTemplate> {
+    let market = MarketOverview {
+        demand,
+        sells,
+        producers,
+        sell_history_days: 0,
+        prices,
+        region,
+        recipes,
+        salary: Money(0),
+    };
+    choose_business(&RiskScore, cycles, &market, 0.0)
+}
+
+/// Share of last month's profit goes to the owner, lenders are paid back from it first
+pub fn payout_dividends(
+    mut manufacturers: Query<(Entity, &Manufacturer, &mut Liabilities), Without<Liquidation>>,
+    // people: Query<(Entity, &Name, &Person)>,
     mut wallets: Query<&mut Wallet>,
-    mut sell_orders: Query<&mut SellOrder>,
-    buy_orders: Query<(Entity, &BuyOrder)>,
     mut logs: EventWriter<LogEvent>,
-    mut commands: Commands,
     date: Res<Days>,
     config: Res<Config>,
 ) {
-    for (entity, name, manufacturer) in manufacturers.iter() {
-        // TODO change to something better after implementing better job market system
-        let [mut manufacturer_wallet, mut owner_wallet] =
-            wallets.get_many_mut([entity, manufacturer.owner]).unwrap();
-        if manufacturer_wallet.money() < config.business.new_worker_salary.value {
-            info!("{} is bankrupt", name.as_str());
-            sell_orders
-                .iter_mut()
-                .filter(|sell_order| sell_order.seller == entity)
-                .for_each(|mut sell_order| {
-                    sell_order.seller = manufacturer.owner;
-                    sell_order.price = sell_order.base_price;
-                });
-            let amount = manufacturer_wallet.money();
+    let dividend = config.business.monthly_dividend.value;
+    for (owned_business, manufacturer, mut liabilities) in manufacturers.iter_mut() {
+        let Either::Right(profit) = wallets
+            .get(owned_business)
+            .unwrap()
+            .calculate_total_change(date.days, 30)
+        else {
+            continue;
+        };
+        let mut money = profit * dividend;
+        for (&lender, loan) in liabilities.loans.iter_mut() {
+            let Ok([mut manufacturer_wallet, mut lender_wallet]) =
+                wallets.get_many_mut([owned_business, lender])
+            else {
+                continue;
+            };
+            let amount = (*loan).min(money);
+            if amount > Money(0)
+                && manufacturer_wallet
+                    .transaction(
+                        &mut lender_wallet,
+                        &Transaction::Transfer {
+                            side: TradeSide::Pay,
+                            sender: owned_business,
+                            receiver: lender,
+                            amount,
+                            date: date.days,
+                        },
+                        &mut logs,
+                    )
+                    .is_ok()
+            {
+                *loan -= amount;
+                money -= amount;
+            }
+        }
+        liabilities.loans.retain(|_, loan| *loan > Money(0));
+        let [mut manufacturer_wallet, mut owner_wallet] = wallets
+            .get_many_mut([owned_business, manufacturer.owner])
+            .unwrap();
+        if money > Money(0) && manufacturer_wallet.money() > money {
+            // let (_, owner_name, owner) = people.get(manufacturer.owner).unwrap();
             manufacturer_wallet
                 .transaction(
                     &mut owner_wallet,
                     &Transaction::Transfer {
                         side: TradeSide::Pay,
-                        sender: entity,
+                        sender: owned_business,
                         receiver: manufacturer.owner,
-                        amount,
+                        amount: money,
                         date: date.days,
                     },
                     &mut logs,
                 )
                 .unwrap();
+        }
+    }
+}
+
+/// Manufacturers reinvest a share of last month's profit in quality, paid to the government
+/// as the supplier of equipment and know-how. Previous investment wears off over time.
+pub fn invest_in_quality(
+    mut manufacturers: Query<(Entity, &mut Manufacturer), Without<Liquidation>>,
+    governments: Query<Entity, With<Government>>,
+    mut wallets: Query<&mut Wallet>,
+    mut logs: EventWriter<LogEvent>,
+    date: Res<Days>,
+    config: Res<Config>,
+) {
+    let government = governments
+        .iter()
+        .next()
+        .expect("There should be a government");
+    let quality = &config.business.quality;
+    for (entity, mut manufacturer) in manufacturers.iter_mut() {
+        manufacturer.quality_investment *= 1.0 - quality.depreciation.value;
+        let Ok([mut manufacturer_wallet, mut government_wallet]) =
+            wallets.get_many_mut([entity, government])
+        else {
+            continue;
+        };
+        let Right(profit) = manufacturer_wallet.calculate_total_change(date.days, 30) else {
+            continue;
+        };
+        let room = (1.0 - manufacturer.quality_investment).max(0.0);
+        let amount =
+            (profit * quality.investment_share.value).min(quality.full_investment.value * room);
+        if amount == Money(0) || manufacturer_wallet.money() < amount {
+            continue;
+        }
+        manufacturer_wallet
+            .transaction(
+                &mut government_wallet,
+                &Transaction::Transfer {
+                    side: TradeSide::Pay,
+                    sender: entity,
+                    receiver: government,
+                    amount,
+                    date: date.days,
+                },
+                &mut logs,
+            )
+            .unwrap();
+        manufacturer.quality_investment +=
+            (amount.as_f64() / quality.full_investment.value.as_f64()) as f32;
+        logs.send(LogEvent::Generic {
+            text: format!(
+                "I invested {} in quality, investment level is now {:.0}%",
+                amount,
+                manufacturer.quality_investment * 100.0
+            ),
+            entity,
+        });
+    }
+}
+
+/// Once a month lines whose main output sells below its unit cost are retooled to the most
+/// promising production cycle, and profitable businesses with fully staffed lines open another
+/// line. Equipment is bought from the government and the line produces nothing while set up.
+#[allow(clippy::too_many_arguments)]
+pub fn manage_production_lines(
+    mut manufacturers: Query<(
+        Entity,
+        &mut Manufacturer,
+        &mut SellStrategy,
+        &Region,
+        Option<&BuyStrategy>,
+    )>,
+    governments: Query<Entity, With<Government>>,
+    buy_orders: Query<&BuyOrder>,
+    mut wallets: Query<&mut Wallet>,
+    templates: Res<Templates>,
+    technologies: Res<Technologies>,
+    price_history: Res<PriceHistory>,
+    mut logs: EventWriter<LogEvent>,
+    mut commands: Commands,
+    date: Res<Days>,
+    config: Res<Config>,
+) {
+    let government = governments
+        .iter()
+        .next()
+        .expect("There should be a government");
+    let settings = &config.business.production_lines;
+    let demand = demand_by_item_type(&buy_orders);
+    let last_days = config.business.prices.sell_history_to_consider.value;
+    let sells = sells_by_item_type(
+        manufacturers
+            .iter()
+            .map(|(entity, manufacturer, ..)| (entity, manufacturer)),
+        &wallets,
+        date.days,
+        last_days,
+    );
+    let mut producers = producers_by_item_type(
+        manufacturers
+            .iter()
+            .map(|(_, manufacturer, ..)| manufacturer),
+    );
+    for (entity, mut manufacturer, mut sell_strategy, region, buy_strategy) in
+        manufacturers.iter_mut()
+    {
+        let Ok([mut wallet, mut government_wallet]) = wallets.get_many_mut([entity, government])
+        else {
+            continue;
+        };
+        let known = manufacturer.research.known.clone();
+        let cycles = technologies.available_cycles(&templates.production_cycles, &known);
+        for index in 0..manufacturer.production_lines.len() {
+            let line = &manufacturer.production_lines[index];
+            let old_output = line.production_cycle.main_output;
+            let unprofitable = line.retooling_days_left == 0
+                && line.last_unit_cost > Money(0)
+                && price_history
+                    .last_median(region, &old_output)
+                    .is_some_and(|price| price < line.last_unit_cost);
+            if !unprofitable || wallet.money() < settings.retooling_cost.value {
+                continue;
+            }
+            let Some(new_cycle) = choose_best_business(
+                &demand,
+                &sells,
+                &producers,
+                &cycles,
+                &templates.recipes,
+                &price_history,
+                region,
+            )
+            .and_then(|cycle| technologies.production_cycle(cycle, &known)) else {
+                continue;
+            };
+            // staying is the best option or another line already makes it
+            if manufacturer.produces(&new_cycle.main_output) {
+                continue;
+            }
+            buy_equipment(
+                &mut wallet,
+                &mut government_wallet,
+                entity,
+                government,
+                settings.retooling_cost.value,
+                &mut logs,
+                date.days,
+            );
             logs.send(LogEvent::Generic {
                 text: format!(
-                    "My business {} is bankrupt! I'll sell all stuff by production price.",
-                    name.as_str()
+                    "{} sells below its cost, I'm retooling the line for {}",
+                    old_output, new_cycle.main_output
                 ),
                 entity,
             });
-            buy_orders
-                .iter()
-                .filter(|(_, buy_order)| buy_order.buyer == entity)
-                .for_each(|(order_entity, _)| {
-                    commands.entity(order_entity).despawn_recursive();
-                });
-            commands.entity(entity).despawn_recursive();
+            *producers.entry(old_output).or_insert(1) -= 1;
+            *producers.entry(new_cycle.main_output).or_insert(0) += 1;
+            let line = &mut manufacturer.production_lines[index];
+            line.production_cycle = new_cycle;
+            line.retooling_days_left = settings.retooling_days.value;
+            line.last_unit_cost = Money(0);
+            if index == 0 {
+                // the sell strategy follows the main line, its price history is of no use anymore
+                *sell_strategy = SellStrategy {
+                    max_price_change_per_day: sell_strategy.max_price_change_per_day,
+                    pricing: sell_strategy.pricing,
+                    ..Default::default()
+                };
+                manufacturer.production_log.clear();
+            }
+        }
+        let fully_staffed = manufacturer
+            .production_lines
+            .iter()
+            .all(|line| line.staffing() >= 1.0);
+        let profitable = matches!(
+            wallet.calculate_total_change(date.days, 30),
+            Right(profit) if profit > Money(0)
+        );
+        if manufacturer.production_lines.len() < settings.max_lines.value
+            && fully_staffed
+            && profitable
+            && wallet.money() >= settings.new_line_cost.value
+        {
+            if let Some(new_cycle) = choose_best_business(
+                &demand,
+                &sells,
+                &producers,
+                &cycles,
+                &templates.recipes,
+                &price_history,
+                region,
+            )
+            .and_then(|cycle| technologies.production_cycle(cycle, &known))
+            {
+                if !manufacturer.produces(&new_cycle.main_output) {
+                    buy_equipment(
+                        &mut wallet,
+                        &mut government_wallet,
+                        entity,
+                        government,
+                        settings.new_line_cost.value,
+                        &mut logs,
+                        date.days,
+                    );
+                    logs.send(LogEvent::Generic {
+                        text: format!(
+                            "Business is going well, I'm opening a new line for {}",
+                            new_cycle.main_output
+                        ),
+                        entity,
+                    });
+                    *producers.entry(new_cycle.main_output).or_insert(0) += 1;
+                    let mut line = ProductionLine::new(new_cycle, vec![]);
+                    line.retooling_days_left = settings.retooling_days.value;
+                    manufacturer.production_lines.push(line);
+                }
+            }
+        }
+        if buy_strategy.is_none()
+            && !(manufacturer.input().is_empty() && manufacturer.tools().is_empty())
+        {
+            commands.entity(entity).insert(BuyStrategy {
+                policy: config.business.inventory.policy.value,
+                ..Default::default()
+            });
         }
     }
 }
 
-pub fn payout_dividends(
-    manufacturers: Query<(Entity, &Manufacturer)>,
-    // people: Query<(Entity, &Name, &Person)>,
+/// Once a month profitable businesses whose warehouse is nearly full buy more space from the
+/// government, they pay rent for it from then on
+pub fn expand_storage(
+    mut manufacturers: Query<(Entity, &mut Manufacturer), Without<Liquidation>>,
+    governments: Query<Entity, With<Government>>,
     mut wallets: Query<&mut Wallet>,
+    items: Res<ItemRegistry>,
     mut logs: EventWriter<LogEvent>,
     date: Res<Days>,
     config: Res<Config>,
 ) {
-    let dividend = config.business.monthly_dividend.value;
-    for (owned_business, manufacturer) in manufacturers.iter() {
-        let [mut manufacturer_wallet, mut owner_wallet] = wallets
-            .get_many_mut([owned_business, manufacturer.owner])
-            .unwrap();
-        if let Either::Right(money) = manufacturer_wallet.calculate_total_change(date.days, 30) {
-            if manufacturer_wallet.money() > money * dividend {
-                // let (_, owner_name, owner) = people.get(manufacturer.owner).unwrap();
-                manufacturer_wallet
-                    .transaction(
-                        &mut owner_wallet,
-                        &Transaction::Transfer {
-                            side: TradeSide::Pay,
-                            sender: owned_business,
-                            receiver: manufacturer.owner,
-                            amount: money * dividend,
-                            date: date.days,
-                        },
-                        &mut logs,
-                    )
-                    .unwrap();
-            }
+    let government = governments
+        .iter()
+        .next()
+        .expect("There should be a government");
+    let storage = &config.business.storage;
+    let cost = storage.expansion_cost_per_volume.value * storage.expansion_size.value;
+    for (entity, mut manufacturer) in manufacturers.iter_mut() {
+        let usage = items.stored_volume(&manufacturer.assets) / manufacturer.storage_capacity;
+        if usage < storage.expand_above.value {
+            continue;
+        }
+        let Ok([mut wallet, mut government_wallet]) = wallets.get_many_mut([entity, government])
+        else {
+            continue;
+        };
+        let profitable = matches!(
+            wallet.calculate_total_change(date.days, 30),
+            Right(profit) if profit > Money(0)
+        );
+        if !profitable || wallet.money() < cost {
+            continue;
+        }
+        buy_equipment(
+            &mut wallet,
+            &mut government_wallet,
+            entity,
+            government,
+            cost,
+            &mut logs,
+            date.days,
+        );
+        manufacturer.storage_capacity += storage.expansion_size.value;
+        logs.send(LogEvent::Generic {
+            text: format!(
+                "My warehouse is {:.0}% full, I paid {} to expand it to {:.0} m³",
+                usage * 100.0,
+                cost,
+                manufacturer.storage_capacity
+            ),
+            entity,
+        });
+    }
+}
+
+/// Funds must be checked by the caller
+fn buy_equipment(
+    wallet: &mut Wallet,
+    government_wallet: &mut Wallet,
+    buyer: Entity,
+    government: Entity,
+    amount: Money,
+    logs: &mut EventWriter<LogEvent>,
+    date: usize,
+) {
+    wallet
+        .transaction(
+            government_wallet,
+            &Transaction::Transfer {
+                side: TradeSide::Pay,
+                sender: buyer,
+                receiver: government,
+                amount,
+                date,
+            },
+            logs,
+        )
+        .unwrap();
+}
+
+/// Manufacturers pay rent and utilities of their lines and warehouse to the government every
+/// month. A business that can't afford them pays what it has left and owes the rest, which makes
+/// it insolvent.
+pub fn pay_fixed_costs(
+    mut manufacturers: Query<(Entity, &Manufacturer, &mut Liabilities)>,
+    governments: Query<Entity, With<Government>>,
+    mut wallets: Query<&mut Wallet>,
+    mut logs: EventWriter<LogEvent>,
+    date: Res<Days>,
+    config: Res<Config>,
+) {
+    let government = governments
+        .iter()
+        .next()
+        .expect("There should be a government");
+    for (entity, manufacturer, mut liabilities) in manufacturers.iter_mut() {
+        let Ok([mut manufacturer_wallet, mut government_wallet]) =
+            wallets.get_many_mut([entity, government])
+        else {
+            continue;
+        };
+        let rent = config.business.storage.rent_per_volume.value * manufacturer.storage_capacity;
+        let owed = liabilities.rent.remove(&government).unwrap_or(Money(0));
+        let due = manufacturer.fixed_costs() + rent + owed;
+        let amount = due.min(manufacturer_wallet.money());
+        if due > amount {
+            liabilities.rent.insert(government, due - amount);
+        }
+        if amount == Money(0) {
+            continue;
         }
+        manufacturer_wallet
+            .transaction(
+                &mut government_wallet,
+                &Transaction::FixedCost {
+                    side: TradeSide::Pay,
+                    payer: entity,
+                    landlord: government,
+                    amount,
+                    date: date.days,
+                },
+                &mut logs,
+            )
+            .unwrap();
     }
 }
 
+/// Tax on last month's profit, what the business can't pay is owed until it can
 pub fn pay_cit(
-    manufacturers: Query<(Entity, &Manufacturer)>,
+    mut manufacturers: Query<(Entity, &mut Liabilities), With<Manufacturer>>,
     tax_authorities: Query<&TaxAuthority>,
     mut wallets: Query<&mut Wallet>,
     mut logs: EventWriter<LogEvent>,
     date: Res<Days>,
     config: Res<Config>,
 ) {
-    for (entity, _) in manufacturers.iter() {
+    for (entity, mut liabilities) in manufacturers.iter_mut() {
         let tax_authority = tax_authorities.get(entity).unwrap();
         if let Ok([mut manufacturer_wallet, mut tax_authority_wallet]) =
             wallets.get_many_mut([entity, tax_authority.authority])
         {
-            if let Right(gain) = manufacturer_wallet.calculate_total_change(date.days, 30) {
+            let tax = match manufacturer_wallet.calculate_total_change(date.days, 30) {
+                Right(gain) => gain * config.government.taxes.cit.value,
+                _ => Money(0),
+            };
+            let due = tax + liabilities.taxes;
+            let amount = due.min(manufacturer_wallet.money());
+            if amount > Money(0) {
                 manufacturer_wallet
                     .transaction(
                         &mut tax_authority_wallet,
@@ -785,79 +1703,161 @@ pub fn pay_cit(
                             side: TradeSide::Pay,
                             sender: entity,
                             receiver: tax_authority.authority,
-                            amount: gain * config.government.taxes.cit.value,
+                            amount,
                             date: date.days,
                         },
                         &mut logs,
                     )
                     .unwrap();
             }
+            liabilities.taxes = due - amount;
         }
     }
 }
 
-fn find_required_inputs(
-    cycle_output: &String,
-    global_cycles: &Vec<ProductionCycleTemplate>,
-) -> HashSet<String> {
-    let mut required_inputs = HashSet::new();
-
-    for cycle in global_cycles {
-        if cycle.output.0 == *cycle_output {
-            for input_item in cycle.input.keys() {
-                required_inputs.insert(input_item.clone());
-
-                // If there is a cycle for this input, recursively find its inputs
-                let sub_inputs = find_required_inputs(input_item, global_cycles);
-                for sub_input in sub_inputs {
-                    required_inputs.insert(sub_input);
-                }
+/// Sets the workers each line aims for from the output it is expected to sell. The forecast
+/// follows recent sales or open buy orders, whichever is higher, and corrects the stock of unsold
+/// items towards the goal within the forecast days.
+pub fn plan_staffing(
+    mut manufacturers: Query<(Entity, &Wallet, &mut Manufacturer), Without<Liquidation>>,
+    people: Query<&Person>,
+    sell_orders: Query<&SellOrder>,
+    buy_orders: Query<&BuyOrder>,
+    mut logs: EventWriter<LogEvent>,
+    date: Res<Days>,
+    config: Res<Config>,
+) {
+    let days = config.business.staffing.forecast_days.value.max(1);
+    let skills = &config.business.skills;
+    let demand = demand_by_item_type(&buy_orders);
+    // open buy orders are shared by every line making the item, wherever it is
+    let producers = manufacturers
+        .iter()
+        .flat_map(|(_, _, manufacturer)| manufacturer.production_lines.iter())
+        .fold(HashMap::new(), |mut acc, line| {
+            *acc.entry(line.production_cycle.main_output).or_insert(0) += 1;
+            acc
+        });
+    let stock = sell_orders
+        .iter()
+        .fold(HashMap::new(), |mut acc, sell_order| {
+            *acc.entry((sell_order.seller, sell_order.item_type))
+                .or_insert(0) += sell_order.items.len();
+            acc
+        });
+    for (entity, wallet, mut manufacturer) in manufacturers.iter_mut() {
+        let Manufacturer {
+            production_lines,
+            assets,
+            ..
+        } = &mut *manufacturer;
+        for line in production_lines.iter_mut() {
+            // nobody is hired to wait for inputs, the first worker comes when there is work
+            if line.hired_workers.is_empty() && !line.has_enough_input(assets) {
+                line.target_workers = 0;
+                continue;
+            }
+            let cycle = &line.production_cycle;
+            let item_type = cycle.main_output;
+            let sold_per_day =
+                wallet.get_amount_of_sell_transactions(date.days, &item_type, days as usize) as f32
+                    / days as f32;
+            let ordered_per_day = *demand.get(&item_type).unwrap_or(&0) as f32
+                / *producers.get(&item_type).unwrap_or(&1) as f32
+                / days as f32;
+            let not_listed = assets
+                .items_to_sell
+                .iter()
+                .filter(|item| item.item_type == item_type)
+                .count();
+            let in_stock = (*stock.get(&(entity, item_type)).unwrap_or(&0) + not_listed) as f32;
+            let goal_stock = (cycle.main_output_quantity()
+                * config.business.goal_produced_cycles_count.value)
+                as f32;
+            let target_output = (sold_per_day.max(ordered_per_day)
+                + (goal_stock - in_stock) / days as f32)
+                .max(0.0);
+            // a line finishes at most one cycle a day, more workers would idle
+            let workdays_needed = cycle.workdays_needed.max(1);
+            let output_per_workday = cycle.main_output_quantity() as f32 / workdays_needed as f32;
+            let productivity = if line.hired_workers.is_empty() {
+                skills.novice_productivity.value
+            } else {
+                line.hired_workers
+                    .iter()
+                    .filter_map(|worker| people.get(*worker).ok())
+                    .map(|person| person.productivity(&item_type, skills))
+                    .sum::<f32>()
+                    / line.hired_workers.len() as f32
+            };
+            let target_workers =
+                ((target_output / output_per_workday.max(f32::EPSILON) / productivity.max(0.01))
+                    .ceil() as usize)
+                    .clamp(1, workdays_needed as usize);
+            if target_workers != line.target_workers {
+                logs.send(LogEvent::Generic {
+                    text: format!(
+                        "I expect to sell {:.1} {} a day and have {} in stock, aiming for {}. Planning {} workers instead of {}",
+                        sold_per_day.max(ordered_per_day),
+                        item_type,
+                        in_stock,
+                        goal_stock,
+                        target_workers,
+                        line.target_workers
+                    ),
+                    entity,
+                });
+                line.target_workers = target_workers;
             }
         }
     }
-
-    required_inputs
 }
 
 pub fn create_job_offers(
-    mut manufacturers: Query<(Entity, &mut Manufacturer, &SellStrategy)>,
+    manufacturers: Query<(Entity, &Manufacturer), Without<Liquidation>>,
     jobs: Query<&JobOffer>,
     mut logs: EventWriter<LogEvent>,
     mut commands: Commands,
     config: Res<Config>,
 ) {
-    for (manufacturer, manufacturer_data, sell_strategy) in manufacturers.iter_mut() {
+    for (manufacturer, manufacturer_data) in manufacturers.iter() {
+        if manufacturer_data.days_since_last_staff_change > 0 {
+            continue;
+        }
         let total_offers = jobs
             .iter()
             .filter(|job| job.employer == manufacturer)
             .count();
-        if ((manufacturer_data.hired_workers.len()
-            < manufacturer_data.production_cycle.workdays_needed as usize
-            && sell_strategy.current_price > sell_strategy.base_price * 2)
-            || (manufacturer_data.hired_workers.is_empty() && manufacturer_data.has_enough_input()))
-            && total_offers == 0
-            && manufacturer_data.days_since_last_staff_change == 0
-        {
-            let salary = config.business.new_worker_salary.value;
+        let openings = manufacturer_data
+            .planned_openings()
+            .saturating_sub(total_offers)
+            .min(config.business.staffing.max_change.value as usize);
+        if openings == 0 {
+            continue;
+        }
+        let salary = config.business.new_worker_salary.value;
+        for _ in 0..openings {
             commands.spawn(JobOffer {
                 salary,
                 employer: manufacturer,
-                taken_by: None,
             });
-            logs.send(LogEvent::Generic {
-                text: format!(
-                    "I'm creating a job offer for {}. My current workers: {}",
-                    salary,
-                    manufacturer_data.hired_workers.len()
-                ),
-                entity: manufacturer,
-            });
-            warn!(
-                "I'm creating a job offer for {}. My current workers: {}",
-                salary,
-                manufacturer_data.hired_workers.len()
-            );
         }
+        logs.send(LogEvent::Generic {
+            text: format!(
+                "I'm creating {} job offers for {}. My current workers: {}, missing to my plan: {}",
+                openings,
+                salary,
+                manufacturer_data.workers_count(),
+                manufacturer_data.planned_openings()
+            ),
+            entity: manufacturer,
+        });
+        warn!(
+            "I'm creating {} job offers for {}. My current workers: {}",
+            openings,
+            salary,
+            manufacturer_data.workers_count()
+        );
     }
 }
 
@@ -865,23 +1865,37 @@ pub fn take_job_offers(
     jobs: Query<(Entity, &JobOffer)>,
     unemployed: Query<(Entity, &Person), Without<Worker>>,
     names: Query<&Name>,
-    mut manufacturers: Query<(Entity, &mut Manufacturer)>,
+    mut manufacturers: Query<(Entity, &mut Manufacturer), Without<Liquidation>>,
     mut logs: EventWriter<LogEvent>,
     mut commands: Commands,
     config: Res<Config>,
 ) {
+    let skills = &config.business.skills;
     let mut unemployed: Vec<(Entity, &Person)> = unemployed.iter().collect();
     for (job, offer) in jobs.iter() {
         if let Ok((manufacturer_entity, mut manufacturer)) = manufacturers.get_mut(offer.employer) {
-            if let Some((person, _)) = unemployed.pop() {
+            let Some(industry) = manufacturer.hiring_industry().cloned() else {
+                commands.entity(job).despawn();
+                continue;
+            };
+            // the most experienced candidate gets the job and asks for a salary matching the skill
+            let best = unemployed
+                .iter()
+                .enumerate()
+                .max_by(|(_, (_, a)), (_, (_, b))| {
+                    a.productivity(&industry, skills)
+                        .total_cmp(&b.productivity(&industry, skills))
+                })
+                .map(|(index, _)| index);
+            if let Some((person, person_data)) = best.map(|index| unemployed.swap_remove(index)) {
                 // somehow people are hired multiple times
                 let worker_name = names.get(person).unwrap();
                 let manufacturer_name = names.get(manufacturer_entity).unwrap();
-                manufacturer.hired_workers.push(person);
+                manufacturer.hire(person);
                 manufacturer.days_since_last_staff_change =
                     config.business.min_days_between_staff_change.value;
                 commands.entity(person).insert(Worker {
-                    salary: offer.salary,
+                    salary: person_data.expected_salary(&industry, offer.salary, skills),
                     employed_at: Some(offer.employer),
                 });
                 logs.send(LogEvent::Generic {
@@ -905,6 +1919,28 @@ pub fn take_job_offers(
     }
 }
 
+/// Every day of work adds experience in the industry of the worker's production line
+pub fn gain_experience(
+    manufacturers: Query<&Manufacturer>,
+    mut people: Query<&mut Person, With<Worker>>,
+) {
+    for manufacturer in manufacturers.iter() {
+        for line in manufacturer.production_lines.iter() {
+            if line.retooling_days_left > 0 {
+                continue;
+            }
+            for worker in line.hired_workers.iter() {
+                if let Ok(mut person) = people.get_mut(*worker) {
+                    *person
+                        .experience
+                        .entry(line.production_cycle.main_output)
+                        .or_insert(0) += 1;
+                }
+            }
+        }
+    }
+}
+
 pub fn reduce_days_since_last_staff_change(mut manufacturers: Query<&mut Manufacturer>) {
     for mut manufacturer in manufacturers.iter_mut() {
         if manufacturer.days_since_last_staff_change > 0 {
@@ -914,67 +1950,75 @@ pub fn reduce_days_since_last_staff_change(mut manufacturers: Query<&mut Manufac
 }
 
 pub fn fire_staff(
-    mut manufacturers: Query<(Entity, &Wallet, &mut Manufacturer, &SellStrategy)>,
-    workers: Query<(Entity, &Worker)>,
-    sell_orders: Query<&SellOrder>,
+    mut manufacturers: Query<(Entity, &Wallet, &mut Manufacturer)>,
+    workers: Query<(Entity, &Worker, &Person)>,
     names: Query<&Name>,
     mut logs: EventWriter<LogEvent>,
     mut commands: Commands,
     config: Res<Config>,
 ) {
-    let sell_orders_count_grouped_by_manufacturer = sell_orders
-        .iter()
-        .map(|sell_order| sell_order.seller)
-        .fold(HashMap::new(), |mut acc, employer| {
-            *acc.entry(employer).or_insert(0) += 1;
-            acc
-        });
-    for (manufacturer, wallet, mut manufacturer_data, sell_strategy) in manufacturers.iter_mut() {
-        if manufacturer_data.days_since_last_staff_change == 0
-            && manufacturer_data.hired_workers.len() > 1
-            && (sell_strategy.current_price < sell_strategy.base_price * 0.8
-                || (sell_orders_count_grouped_by_manufacturer
-                    .get(&manufacturer)
-                    .unwrap_or(&0)
-                    > &(manufacturer_data.production_cycle.output.1
-                        * config.business.goal_produced_cycles_count.value)))
-        {
-            let worker = manufacturer_data.hired_workers.pop();
+    // workdays contributed for every credit of salary
+    let value_for_money = |worker: &Entity, industry: &ItemId| {
+        workers.get(*worker).map_or(0.0, |(_, worker, person)| {
+            person.productivity(industry, &config.business.skills)
+                / worker.salary.as_f64().max(1.0) as f32
+        })
+    };
+    for (manufacturer, wallet, mut manufacturer_data) in manufacturers.iter_mut() {
+        let layoffs = if manufacturer_data.days_since_last_staff_change == 0 {
+            // the last worker stays as long as the business can pay him
+            manufacturer_data
+                .planned_layoffs()
+                .min(manufacturer_data.workers_count().saturating_sub(1))
+                .min(config.business.staffing.max_change.value as usize)
+        } else {
+            0
+        };
+        let mut fired = 0;
+        for _ in 0..layoffs {
+            let worker = manufacturer_data.fire(value_for_money);
             if let Some(worker) = worker {
                 let worker_name = names.get(worker).unwrap();
                 let manufacturer_name = names.get(manufacturer).unwrap();
                 manufacturer_data.days_since_last_staff_change =
                     config.business.min_days_between_staff_change.value;
+                fired += 1;
                 logs.send(LogEvent::Generic {
-                    text: format!("I fired a worker {}!", worker_name),
+                    text: format!(
+                        "I fired a worker {} to follow my staffing plan",
+                        worker_name
+                    ),
                     entity: manufacturer,
                 });
                 logs.send(LogEvent::Generic {
                     text: format!("I was fired from {}!", manufacturer_name),
                     entity: worker,
                 });
-                // let (worker, mut worker_data) = workers.get_mut(worker).unwrap();
-                // worker_data.fire();
                 warn!(
                     "Firing worker {}, my current workers: {}",
                     worker_name,
-                    manufacturer_data.hired_workers.len()
+                    manufacturer_data.workers_count()
                 );
                 commands.entity(worker).remove::<Worker>();
             }
         }
+        if fired > 0 {
+            logs.send(LogEvent::Generic {
+                text: format!("My staffing plan needs {} workers less!", fired),
+                entity: manufacturer,
+            });
+        }
         if wallet.money()
             < manufacturer_data
-                .hired_workers
-                .iter()
+                .hired_workers()
                 .map(|&worker| {
                     workers
                         .get(worker)
-                        .map_or(Money(0), |(_, worker)| worker.salary)
+                        .map_or(Money(0), |(_, worker, _)| worker.salary)
                 })
                 .sum::<Money>()
         {
-            let worker = manufacturer_data.hired_workers.pop();
+            let worker = manufacturer_data.fire(value_for_money);
             if let Some(worker) = worker {
                 let name = names.get(worker).unwrap();
                 logs.send(LogEvent::Generic {
@@ -994,7 +2038,7 @@ pub fn fire_staff(
                 warn!(
                     "I fired a worker {} because I can't afford to pay him! My current workers: {}",
                     name,
-                    manufacturer_data.hired_workers.len()
+                    manufacturer_data.workers_count()
                 );
                 commands.entity(worker).remove::<Worker>();
             }
@@ -1002,83 +2046,202 @@ pub fn fire_staff(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 #[measured]
 pub fn create_buy_orders(
     mut commands: Commands,
-    mut manufacturers: Query<(Entity, &Name, &Manufacturer, &mut BuyStrategy)>,
+    mut manufacturers: Query<(
+        Entity,
+        &Name,
+        &Manufacturer,
+        &Wallet,
+        &mut BuyStrategy,
+        &Region,
+    )>,
+    contracts: Query<&SupplyContract>,
+    shipments: Query<&Shipment>,
+    tools: Res<Tools>,
+    items: Res<ItemRegistry>,
+    price_history: Res<PriceHistory>,
+    date: Res<Days>,
+    config: Res<Config>,
 ) {
     debug!(
         "Creating buy orders for {} buyers",
         manufacturers.iter_mut().count()
     );
-    for (buyer, name, manufacturer, mut strategy) in manufacturers.iter_mut() {
-        let needed_materials = &manufacturer.production_cycle.input;
+    let settings = &config.business.inventory;
+    // deliveries under supply contracts, due or on their way, count as ordered
+    let mut contracted: HashMap<Entity, HashMap<ItemId, u32>> = HashMap::new();
+    for contract in contracts
+        .iter()
+        .filter(|contract| contract.next_delivery <= contract.end)
+    {
+        *contracted
+            .entry(contract.buyer)
+            .or_default()
+            .entry(contract.item_type)
+            .or_insert(0) += contract.quantity;
+    }
+    for shipment in shipments.iter().filter(|shipment| !shipment.from_buy_order) {
+        *contracted
+            .entry(shipment.receiver)
+            .or_default()
+            .entry(shipment.item.item_type)
+            .or_insert(0) += 1;
+    }
+    for (buyer, name, manufacturer, wallet, mut strategy, region) in manufacturers.iter_mut() {
+        let contracted = contracted.remove(&buyer).unwrap_or_default();
+        let needed_materials = &manufacturer.input();
         let inventory = &manufacturer.assets.items;
         debug!(
             "{}: Needed materials: {:?}",
             name.as_str(),
             needed_materials
         );
+        // keep only as many cycles in stock as fit into the part of the warehouse for inputs
+        let cycle_volume = volume_of(needed_materials, &items);
+        let max_cycles = if cycle_volume > 0.0 {
+            let fitting_cycles = manufacturer.storage_capacity
+                * config.business.storage.input_share.value
+                / cycle_volume;
+            (fitting_cycles as u32).max(1)
+        } else {
+            u32::MAX
+        };
+        // what is stored and what is already ordered must fit into the warehouse
+        let mut free_volume = manufacturer.storage_capacity
+            - items.stored_volume(&manufacturer.assets)
+            - volume_of(&strategy.outstanding_orders, &items)
+            - volume_of(&contracted, &items);
+        let mut fitting = |material: &ItemId, quantity: u32| {
+            let volume = items.volume(material);
+            let quantity = if volume > 0.0 {
+                quantity.min((free_volume / volume).max(0.0) as u32)
+            } else {
+                quantity
+            };
+            free_volume -= volume * quantity as f32;
+            quantity
+        };
 
         for (material, &quantity_needed) in needed_materials.iter() {
-            let inventory_quantity = inventory
-                .get(material)
-                .map_or(0, |items| items.len() as u32);
-
-            let cycles_possible_with_current_inventory = inventory_quantity / quantity_needed;
+            let price = price_history
+                .last_median(region, material)
+                .unwrap_or(Money(0));
+            let stock = InputStock {
+                per_cycle: quantity_needed,
+                in_stock: inventory
+                    .get(material)
+                    .map_or(0, |items| items.len() as u32),
+                ordered: *strategy.outstanding_orders.get(material).unwrap_or(&0)
+                    + contracted.get(material).unwrap_or(&0),
+                daily_usage: daily_usage(manufacturer, material),
+                forecast_usage: forecast_usage(
+                    manufacturer,
+                    wallet,
+                    material,
+                    date.days,
+                    settings.forecast_days.value,
+                ),
+                holding_cost: (config.business.storage.rent_per_volume.value.as_f64()
+                    * items.volume(material) as f64
+                    + settings.capital_cost.value as f64 * price.as_f64())
+                    / DAYS_IN_MONTH as f64,
+            };
+            let Some(target) = order_up_to(strategy.policy, &stock, &config) else {
+                continue;
+            };
+            let target = target.min(max_cycles.saturating_mul(quantity_needed));
             debug!(
-                "{}: Cycles possible with current inventory: {}",
-                name, cycles_possible_with_current_inventory
+                "{}: I want to have {} {} following the {} policy. I already have {} and {} in orders",
+                name,
+                target,
+                material.name(),
+                strategy.policy,
+                stock.in_stock,
+                stock.ordered
             );
-            if cycles_possible_with_current_inventory < strategy.target_production_cycles {
-                let current_orders = *strategy.outstanding_orders.get(material).unwrap_or(&0);
+            let quantity_to_buy = fitting(
+                material,
+                target.saturating_sub(stock.in_stock + stock.ordered),
+            );
+            if quantity_to_buy == 0 {
                 debug!(
-                    "{}: I need to buy {} for {} more production cycles ({} in total). I already have {} and {:?} in orders",
+                    "{}: No need to buy any more {}, I already have {} and {} in orders",
                     name,
-                    material.name,
-                    strategy.target_production_cycles - cycles_possible_with_current_inventory,
-                    (strategy.target_production_cycles - cycles_possible_with_current_inventory) * quantity_needed,
-                    inventory_quantity,
-                    current_orders
+                    material.name(),
+                    stock.in_stock,
+                    stock.ordered
                 );
-                let quantity_to_buy = ((strategy.target_production_cycles
-                    - cycles_possible_with_current_inventory)
-                    * quantity_needed) as i32
-                    - current_orders as i32;
-                if quantity_to_buy <= 0 {
-                    debug!(
-                        "{}: No need to buy any more {}, I already have {} and {} in orders",
-                        name,
-                        material.name,
-                        inventory_quantity,
-                        strategy.outstanding_orders.get(material).unwrap_or(&0)
-                    );
-                    continue;
-                } else {
-                    strategy
-                        .outstanding_orders
-                        .insert(material.clone(), current_orders + quantity_to_buy as u32);
-                }
+                continue;
+            }
+            *strategy.outstanding_orders.entry(*material).or_insert(0) += quantity_to_buy;
+
+            let buy_order = BuyOrder {
+                item_type: *material,
+                buyer,
+                expiration: None,
+                order: OrderType::Market, // Always buying at market price
+                region: region.clone(),
+            };
 
-                let buy_order = BuyOrder {
-                    item_type: material.clone(), // assuming ItemType implements Copy
-                    buyer,
-                    expiration: None,
-                    order: OrderType::Market, // Always buying at market price
-                };
+            debug!(
+                "{}: Created buy order {:?} for {}",
+                name, buy_order, quantity_to_buy
+            );
 
-                debug!(
-                    "{}: Created buy order {:?} for {}",
-                    name, buy_order, quantity_to_buy
-                );
+            for _ in 0..quantity_to_buy {
+                commands.spawn((
+                    buy_order.clone(),
+                    Name::new(format!("{} buy order @Market", material.name())),
+                ));
+            }
+        }
 
-                // Assuming we have a way to track the quantity in BuyOrder
-                for _ in 0..quantity_to_buy {
-                    commands.spawn((
-                        buy_order.clone(),
-                        Name::new(format!("{} buy order @Market", material.name)),
-                    ));
-                }
+        // tools and items to repair them are not used up, only the missing ones are bought
+        let mut needed_equipment = manufacturer.tools();
+        for (item_type, count) in tools.repair_demand(
+            &manufacturer.assets,
+            config.business.tools.repair_below.value,
+        ) {
+            *needed_equipment.entry(item_type).or_insert(0) += count;
+        }
+        for (material, &quantity_needed) in needed_equipment.iter() {
+            let inventory_quantity = inventory
+                .get(material)
+                .map_or(0, |items| items.len() as u32);
+            let current_orders = *strategy.outstanding_orders.get(material).unwrap_or(&0);
+            let quantity_to_buy = fitting(
+                material,
+                quantity_needed.saturating_sub(inventory_quantity + current_orders),
+            );
+            if quantity_to_buy == 0 {
+                continue;
+            }
+            debug!(
+                "{}: I need to buy {} {} as equipment, I already have {} and {} in orders",
+                name,
+                quantity_to_buy,
+                material.name(),
+                inventory_quantity,
+                current_orders
+            );
+            strategy
+                .outstanding_orders
+                .insert(*material, current_orders + quantity_to_buy);
+            let buy_order = BuyOrder {
+                item_type: *material,
+                buyer,
+                expiration: None,
+                order: OrderType::Market,
+                region: region.clone(),
+            };
+            for _ in 0..quantity_to_buy {
+                commands.spawn((
+                    buy_order.clone(),
+                    Name::new(format!("{} buy order @Market", material.name())),
+                ));
             }
         }
     }
@@ -1095,10 +2258,17 @@ pub fn execute_orders(
     mut logs: EventWriter<LogEvent>,
     mut manufacturers: Query<(Entity, &mut Manufacturer)>,
     mut people: Query<(Entity, &mut Person)>,
+    governments: Query<Entity, With<Government>>,
+    regions: Res<Regions>,
     date: Res<Days>,
     config: Res<Config>,
 ) {
     let mut rng = rand::thread_rng();
+    // transport between regions is operated by the government
+    let transport_operator = governments
+        .iter()
+        .next()
+        .expect("There should be a government");
 
     // iterate buy orders in randomized order
     let mut buy_orders: Vec<_> = buy_orders.iter().collect();
@@ -1108,8 +2278,12 @@ pub fn execute_orders(
         let matching_sell_orders: Vec<_> = sell_orders
             .iter()
             .filter(|(_, sell_order)| {
-                sell_order.item_type == buy_order.item_type && !sell_order.items.is_empty()
-            }) // Match by material
+                sell_order.item_type == buy_order.item_type
+                    && !sell_order.items.is_empty()
+                    && regions
+                        .route(&sell_order.region, &buy_order.region)
+                        .is_some()
+            }) // Match by material and reachable region
             .collect();
 
         if !matching_sell_orders.is_empty() {
@@ -1119,15 +2293,26 @@ pub fn execute_orders(
                 .ceil() as usize; // 10% for example
             let sampled_orders: Vec<_> = choose_weighted_orders(&matching_sell_orders, sample_size);
 
-            // Sort by price ascending
+            // Sort by price including transport ascending, so local orders are preferred,
+            // better quality makes the price look lower
+            let quality_sensitivity = config.business.market.quality_sensitivity.value;
             let mut sorted_sample = sampled_orders;
-            sorted_sample.sort_by(|(_, a), (_, b)| a.price.cmp(&b.price));
+            sorted_sample.sort_by(|(_, a), (_, b)| {
+                quality_adjusted_price(&regions, a, &buy_order.region, quality_sensitivity)
+                    .partial_cmp(&quality_adjusted_price(
+                        &regions,
+                        b,
+                        &buy_order.region,
+                        quality_sensitivity,
+                    ))
+                    .unwrap()
+            });
             let sampled_sell_order_ids =
                 sorted_sample.iter().map(|(id, _)| *id).collect::<Vec<_>>();
             debug!(
                 "I have {} sell orders to choose from for {}, prices: ({})",
                 sorted_sample.len(),
-                buy_order.item_type.name,
+                buy_order.item_type.name(),
                 sorted_sample
                     .iter()
                     .map(|(_, sell_order)| sell_order.price.to_string())
@@ -1153,14 +2338,14 @@ pub fn execute_orders(
             debug!(
                 "I'm paying {} for {} (best price was {}) (index: {})!",
                 sorted_sample[index].1.price,
-                buy_order.item_type.name,
+                buy_order.item_type.name(),
                 sorted_sample.first().unwrap().1.price,
                 index
             );
             if let Some(sell_order_id) = sampled_sell_order_ids.get(index) {
                 match buy_order.order {
                     OrderType::Market => {
-                        let _ = execute_order(
+                        if let Err(error) = execute_order(
                             &mut buy_strategy,
                             &mut trade_participants,
                             &mut commands,
@@ -1170,20 +2355,45 @@ pub fn execute_orders(
                             &mut logs,
                             &mut manufacturers,
                             &mut people,
+                            &regions,
+                            transport_operator,
                             &date,
-                        );
+                        ) {
+                            debug!("Buy order {:?} not executed: {}", buy_order, error);
+                        }
                     }
                 }
             }
         } else {
             debug!(
                 "No sell orders for {} (buy order: {:?})",
-                buy_order.item_type.name, buy_order
+                buy_order.item_type.name(),
+                buy_order
             );
         }
     }
 }
 
+/// Price of the sell order for a buyer in given region, including the transport cost
+fn landed_price(regions: &Regions, sell_order: &SellOrder, buyer_region: &Region) -> Money {
+    regions
+        .route(&sell_order.region, buyer_region)
+        .map_or(sell_order.price, |route| {
+            sell_order.price + route.cost_per_item
+        })
+}
+
+/// Landed price divided by quality raised to the buyers' sensitivity to quality
+fn quality_adjusted_price(
+    regions: &Regions,
+    sell_order: &SellOrder,
+    buyer_region: &Region,
+    quality_sensitivity: f64,
+) -> f64 {
+    landed_price(regions, sell_order, buyer_region).as_f64()
+        / (sell_order.quality() as f64).powf(quality_sensitivity)
+}
+
 fn choose_weighted_orders<'a>(
     items: &'a [(Entity, &'a SellOrder)],
     sample_size: usize,
@@ -1213,6 +2423,8 @@ fn execute_order(
     logs: &mut EventWriter<LogEvent>,
     manufacturers: &mut Query<(Entity, &mut Manufacturer)>,
     people: &mut Query<(Entity, &mut Person)>,
+    regions: &Regions,
+    transport_operator: Entity,
     date: &Res<Days>,
 ) -> Result<(), TransactionError> {
     // let (sell_order_id, &mut sell_order) = sell_order;
@@ -1225,12 +2437,21 @@ fn execute_order(
         return Err(TransactionError::SellOrderEmpty);
     }
 
+    let route = regions
+        .route(&sell_order.region, &buy_order.region)
+        .ok_or(TransactionError::NoRoute)?;
     let [mut buyer_wallet, mut seller_wallet] = trade_participants
         .get_many_mut([buy_order.buyer, sell_order.seller])
         .map_err(|_| TransactionError::WalletNotFound)?;
+    let total_price = sell_order.price + route.cost_per_item;
+    if buyer_wallet.money() < total_price {
+        return Err(TransactionError::InsufficientFunds(
+            total_price - buyer_wallet.money(),
+        ));
+    }
 
     let mut item_to_sell = sell_order.items.last().unwrap().clone();
-    item_to_sell.buy_cost = sell_order.price;
+    item_to_sell.buy_cost = total_price;
 
     buyer_wallet.transaction(
         &mut seller_wallet,
@@ -1239,29 +2460,65 @@ fn execute_order(
             buyer: buy_order.buyer,
             seller: sell_order.seller,
             item: item_to_sell.clone(),
-            item_type: sell_order.item_type.clone(),
+            item_type: sell_order.item_type,
             price: sell_order.price,
             date: date.days,
         },
         logs,
     )?;
+    // the trade is paid, transport is part of the same purchase
+    if route.cost_per_item > Money(0) {
+        if let Ok([mut buyer_wallet, mut operator_wallet]) =
+            trade_participants.get_many_mut([buy_order.buyer, transport_operator])
+        {
+            buyer_wallet
+                .transaction(
+                    &mut operator_wallet,
+                    &Transaction::Transfer {
+                        side: TradeSide::Pay,
+                        sender: buy_order.buyer,
+                        receiver: transport_operator,
+                        amount: route.cost_per_item,
+                        date: date.days,
+                    },
+                    logs,
+                )
+                .expect("Buyer should afford transport, funds were checked before the trade");
+        }
+    }
     // we remove the item only if the transaction was successful
     sell_order.items.pop();
+    commands.entity(buy_order_id).despawn();
+    if sell_order.items.is_empty() {
+        commands.entity(*sell_order_id).despawn();
+    }
+    if route.delivery_days > 0 {
+        // outstanding orders are reduced when the shipment arrives
+        commands.spawn((
+            Shipment {
+                item: item_to_sell,
+                receiver: buy_order.buyer,
+                arrival: date.days + route.delivery_days,
+                from_buy_order: true,
+            },
+            Name::new(format!(
+                "{} shipment from {} to {}",
+                sell_order.item_type, sell_order.region, buy_order.region
+            )),
+        ));
+        return Ok(());
+    }
     if let Ok((_, mut strategy)) = buy_strategy.get_mut(buy_order.buyer) {
         *strategy
             .outstanding_orders
             .get_mut(&buy_order.item_type)
             .unwrap() -= 1;
     }
-    commands.entity(buy_order_id).despawn();
-    if sell_order.items.is_empty() {
-        commands.entity(*sell_order_id).despawn();
-    }
     if let Ok((_, mut person)) = people.get_mut(buy_order.buyer) {
         person
             .assets
             .items
-            .entry(sell_order.item_type.clone())
+            .entry(sell_order.item_type)
             .or_default()
             .push(item_to_sell.clone());
     }
@@ -1269,23 +2526,29 @@ fn execute_order(
         manufacturer
             .assets
             .items
-            .entry(sell_order.item_type.clone())
+            .entry(sell_order.item_type)
             .or_default()
             .push(item_to_sell);
     }
     Ok(())
 }
 
+/// Salaries that can't be paid are owed to the workers, they are paid as soon as there is money
 pub fn salary_payout(
-    mut workers: Query<(Entity, &mut Wallet, &Worker), Without<Manufacturer>>,
-    mut manufacturers: Query<(Entity, &mut Wallet, &Manufacturer), Without<Worker>>,
+    mut workers: Query<(Entity, &mut Wallet, Option<&Worker>), Without<Manufacturer>>,
+    mut manufacturers: Query<
+        (Entity, &mut Wallet, &Manufacturer, &mut Liabilities),
+        Without<Worker>,
+    >,
     mut logs: EventWriter<LogEvent>,
     date: Res<Days>,
 ) {
-    for (employer, mut manufacturer_wallet, manufacturer) in manufacturers.iter_mut() {
-        for worker in manufacturer.hired_workers.iter() {
-            if let Ok((worker, mut worker_wallet, worker_data)) = workers.get_mut(*worker) {
-                let _ = manufacturer_wallet.transaction(
+    for (employer, mut manufacturer_wallet, manufacturer, mut liabilities) in
+        manufacturers.iter_mut()
+    {
+        for worker in manufacturer.hired_workers() {
+            if let Ok((worker, mut worker_wallet, Some(worker_data))) = workers.get_mut(*worker) {
+                let paid = manufacturer_wallet.transaction(
                     &mut worker_wallet,
                     &Transaction::Salary {
                         side: TradeSide::Pay,
@@ -1296,8 +2559,38 @@ pub fn salary_payout(
                     },
                     &mut logs,
                 );
+                if paid.is_err() {
+                    *liabilities.salaries.entry(worker).or_insert(Money(0)) += worker_data.salary;
+                }
+            }
+        }
+        // former workers are owed their salaries too
+        let mut paid_off = vec![];
+        for (&worker, &owed) in liabilities.salaries.iter() {
+            let Ok((worker, mut worker_wallet, _)) = workers.get_mut(worker) else {
+                continue;
+            };
+            if manufacturer_wallet.money() >= owed
+                && manufacturer_wallet
+                    .transaction(
+                        &mut worker_wallet,
+                        &Transaction::Salary {
+                            side: TradeSide::Pay,
+                            employer,
+                            worker,
+                            salary: owed,
+                            date: date.days,
+                        },
+                        &mut logs,
+                    )
+                    .is_ok()
+            {
+                paid_off.push(worker);
             }
         }
+        for worker in paid_off {
+            liabilities.salaries.remove(&worker);
+        }
     }
 }
 
@@ -1320,9 +2613,152 @@ pub fn assing_workers_to_businesses(
 ) {
     for (manufacturer_entity, manufacturer) in manufacturers.iter() {
         for (worker_entity, mut worker, _) in workers.iter_mut() {
-            if manufacturer.hired_workers.contains(&worker_entity) {
+            if manufacturer.hired_workers().any(|&w| w == worker_entity) {
                 worker.employed_at = Some(manufacturer_entity);
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DEFAULT_CONFIG_PATH;
+
+    fn config() -> Config {
+        let data = std::fs::read_to_string(DEFAULT_CONFIG_PATH).unwrap();
+        let mut config: Config = serde_json::from_str(&data).unwrap();
+        config.business.keep_resources_for_cycles_amount.value = 3;
+        let settings = &mut config.business.inventory;
+        settings.reorder_point.value = 1;
+        settings.order_up_to.value = 4;
+        settings.order_cost.value = Money(500);
+        settings.forecast_days.value = 10;
+        settings.safety_stock.value = 0.5;
+        config
+    }
+
+    fn stock(in_stock: u32, ordered: u32) -> InputStock {
+        InputStock {
+            per_cycle: 10,
+            in_stock,
+            ordered,
+            daily_usage: 5.0,
+            forecast_usage: Some(5.0),
+            holding_cost: 1.0,
+        }
+    }
+
+    /// Stock without demand, nothing is used and nothing was sold
+    fn idle(in_stock: u32) -> InputStock {
+        InputStock {
+            daily_usage: 0.0,
+            forecast_usage: None,
+            ..stock(in_stock, 0)
+        }
+    }
+
+    #[test]
+    fn target_cycles_keeps_fixed_number_of_cycles() {
+        let config = config();
+        let policy = InventoryPolicy::TargetCycles;
+
+        assert_eq!(order_up_to(policy, &stock(5, 0), &config), Some(30));
+        assert_eq!(order_up_to(policy, &idle(0), &config), Some(30));
+        // orders on the way are not counted against the target
+        assert_eq!(order_up_to(policy, &stock(20, 50), &config), Some(30));
+        assert_eq!(order_up_to(policy, &stock(30, 0), &config), None);
+    }
+
+    #[test]
+    fn reorder_point_orders_up_to_level_once_position_drops() {
+        let config = config();
+        let policy = InventoryPolicy::ReorderPoint;
+
+        assert_eq!(order_up_to(policy, &stock(5, 5), &config), Some(40));
+        assert_eq!(order_up_to(policy, &idle(0), &config), Some(40));
+        assert_eq!(order_up_to(policy, &stock(5, 6), &config), None);
+        assert_eq!(order_up_to(policy, &stock(40, 0), &config), None);
+    }
+
+    #[test]
+    fn economic_order_quantity_balances_order_and_holding_costs() {
+        let config = config();
+        let policy = InventoryPolicy::EconomicOrderQuantity;
+        let with_holding_cost = |in_stock, ordered| InputStock {
+            holding_cost: 12.5,
+            ..stock(in_stock, ordered)
+        };
+
+        // sqrt(2 * 5 * 500 / 12.5) = 20 on top of what is in stock and ordered
+        assert_eq!(
+            order_up_to(policy, &with_holding_cost(0, 0), &config),
+            Some(20)
+        );
+        assert_eq!(
+            order_up_to(policy, &with_holding_cost(4, 2), &config),
+            Some(26)
+        );
+        // a batch larger than the cap of 4 cycles is cut down
+        assert_eq!(order_up_to(policy, &stock(0, 0), &config), Some(40));
+        assert_eq!(order_up_to(policy, &stock(10, 0), &config), None);
+        assert_eq!(order_up_to(policy, &stock(0, 10), &config), None);
+    }
+
+    #[test]
+    fn economic_order_quantity_without_demand_buys_one_cycle() {
+        let config = config();
+
+        assert_eq!(
+            order_up_to(InventoryPolicy::EconomicOrderQuantity, &idle(3), &config),
+            Some(10)
+        );
+    }
+
+    #[test]
+    fn economic_order_quantity_with_free_storage_is_capped() {
+        let config = config();
+        let free = InputStock {
+            holding_cost: 0.0,
+            ..stock(0, 0)
+        };
+        let cheap = InputStock {
+            holding_cost: 0.0001,
+            ..stock(0, 0)
+        };
+
+        assert_eq!(
+            order_up_to(InventoryPolicy::EconomicOrderQuantity, &free, &config),
+            Some(40)
+        );
+        assert_eq!(
+            order_up_to(InventoryPolicy::EconomicOrderQuantity, &cheap, &config),
+            Some(40)
+        );
+    }
+
+    #[test]
+    fn demand_forecast_follows_recent_sales() {
+        let config = config();
+        let policy = InventoryPolicy::DemandForecast;
+
+        // 5 per day for 10 days with 50% safety stock
+        assert_eq!(order_up_to(policy, &stock(0, 0), &config), Some(75));
+        assert_eq!(order_up_to(policy, &stock(50, 24), &config), Some(75));
+        assert_eq!(order_up_to(policy, &stock(50, 25), &config), None);
+    }
+
+    #[test]
+    fn demand_forecast_without_sales_keeps_one_cycle() {
+        let config = config();
+        let policy = InventoryPolicy::DemandForecast;
+        let no_sales = InputStock {
+            forecast_usage: Some(0.0),
+            ..stock(0, 0)
+        };
+
+        assert_eq!(order_up_to(policy, &idle(0), &config), Some(10));
+        assert_eq!(order_up_to(policy, &no_sales, &config), Some(10));
+        assert_eq!(order_up_to(policy, &idle(10), &config), None);
+    }
+}
@@ -1,9 +1,12 @@
+use std::fmt::Display;
 use std::fs;
 use std::fs::{copy, create_dir_all, metadata};
 use std::path::Path;
 
 use crate::money::Money;
+use crate::pricing::Pricing;
 use bevy::prelude::*;
+use enum_display_derive::Display;
 use serde::{Deserialize, Serialize};
 
 pub const DEFAULT_CONFIG_PATH: &str = "./data/config.json";
@@ -22,10 +25,23 @@ pub struct GovernmentInit {
     pub starting_money: ConfigValue<Money>,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TradersInit {
+    pub amount: ConfigValue<u32>,
+    pub starting_money: ConfigValue<Money>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OffWorldInit {
+    pub starting_money: ConfigValue<Money>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Init {
     pub people: PeopleInit,
     pub government: GovernmentInit,
+    pub traders: TradersInit,
+    pub offworld: OffWorldInit,
 }
 
 #[derive(Serialize, Deserialize, Debug, Component)]
@@ -38,6 +54,7 @@ pub struct People {
     pub max_buy_orders_per_day: ConfigValue<u32>,
     pub discount_rate: ConfigValue<f64>,
     pub order_expiration_time: ConfigValue<u64>,
+    pub repair_below: ConfigValue<f32>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -50,12 +67,14 @@ pub struct Prices {
 pub struct Market {
     pub amount_of_sell_orders_seen: ConfigValue<f64>,
     pub amount_of_sell_orders_to_choose_best_price_from: ConfigValue<f64>,
+    pub quality_sensitivity: ConfigValue<f64>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Taxes {
     pub cit: ConfigValue<f32>,
     pub pit: ConfigValue<f32>,
+    pub tariff: ConfigValue<f32>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -67,6 +86,7 @@ pub struct Government {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Business {
     pub prices: Prices,
+    pub pricing: ConfigValue<Pricing>,
     pub market: Market,
     pub keep_resources_for_cycles_amount: ConfigValue<u32>,
     pub money_to_create_business: ConfigValue<Money>,
@@ -74,6 +94,172 @@ pub struct Business {
     pub monthly_dividend: ConfigValue<f32>,
     pub min_days_between_staff_change: ConfigValue<u32>,
     pub goal_produced_cycles_count: ConfigValue<u32>,
+    pub contracts: Contracts,
+    pub quality: Quality,
+    pub byproducts: Byproducts,
+    pub production_lines: ProductionLines,
+    pub tools: Tools,
+    pub skills: Skills,
+    pub research: Research,
+    pub storage: Storage,
+    pub inventory: Inventory,
+    pub staffing: Staffing,
+    pub entrepreneurs: Entrepreneurs,
+    pub acquisitions: Acquisitions,
+    pub bankruptcy: Bankruptcy,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Bankruptcy {
+    pub grace_days: ConfigValue<u32>,
+    pub liquidation_days: ConfigValue<u32>,
+    pub liquidation_discount: ConfigValue<f32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Acquisitions {
+    pub premium: ConfigValue<f32>,
+    pub distressed_discount: ConfigValue<f32>,
+    pub distress_months: ConfigValue<f32>,
+    pub recapitalization_margin: ConfigValue<f32>,
+    pub cooldown_days: ConfigValue<u32>,
+    pub max_per_month: ConfigValue<u32>,
+    pub buyer_reserve: ConfigValue<Money>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Staffing {
+    pub forecast_days: ConfigValue<u32>,
+    pub max_change: ConfigValue<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Entrepreneurs {
+    pub model: ConfigValue<DecisionModel>,
+    pub min_money: ConfigValue<Money>,
+    pub min_expected_profit: ConfigValue<Money>,
+    pub unknown_price_markup: ConfigValue<f32>,
+    pub max_risk_tolerance: ConfigValue<f32>,
+}
+
+/// How people decide which business to found
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Display, Default)]
+pub enum DecisionModel {
+    /// Scores demand against competition and missing inputs, ignores prices
+    RiskScore,
+    /// Estimates monthly profit from prices, input costs, wages and the share of the market
+    #[default]
+    ExpectedProfit,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Inventory {
+    pub policy: ConfigValue<InventoryPolicy>,
+    pub reorder_point: ConfigValue<u32>,
+    pub order_up_to: ConfigValue<u32>,
+    pub order_cost: ConfigValue<Money>,
+    pub capital_cost: ConfigValue<f32>,
+    pub forecast_days: ConfigValue<u32>,
+    pub safety_stock: ConfigValue<f32>,
+}
+
+/// How a business decides when and how much of its inputs to buy
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Display, Default)]
+pub enum InventoryPolicy {
+    /// Keeps inputs for a fixed number of production cycles in stock
+    #[default]
+    TargetCycles,
+    /// Waits until stock drops to the reorder point, then orders up to a higher level
+    ReorderPoint,
+    /// Orders in batches that balance the cost of ordering against the cost of holding stock
+    EconomicOrderQuantity,
+    /// Keeps enough stock for the production recent sales call for
+    DemandForecast,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Storage {
+    pub initial_capacity: ConfigValue<f32>,
+    pub rent_per_volume: ConfigValue<Money>,
+    pub expansion_size: ConfigValue<f32>,
+    pub expansion_cost_per_volume: ConfigValue<Money>,
+    pub expand_above: ConfigValue<f32>,
+    pub input_share: ConfigValue<f32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Research {
+    pub reserve: ConfigValue<Money>,
+    pub staff_share: ConfigValue<f32>,
+    pub spread_chance: ConfigValue<f32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Skills {
+    pub novice_productivity: ConfigValue<f32>,
+    pub expert_productivity: ConfigValue<f32>,
+    pub days_to_learn: ConfigValue<u32>,
+    pub salary_share: ConfigValue<f32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Tools {
+    pub repair_below: ConfigValue<f32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ProductionLines {
+    pub max_lines: ConfigValue<usize>,
+    pub new_line_cost: ConfigValue<Money>,
+    pub retooling_cost: ConfigValue<Money>,
+    pub retooling_days: ConfigValue<u32>,
+}
+
+/// How total cost of a production cycle is split between its outputs
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum CostAllocation {
+    /// Every produced item bears the same cost
+    Quantity,
+    /// Cost is split by the market value of the outputs, by quantity while some output has no price yet
+    MarketValue,
+    /// Main output bears all cost, byproducts are free
+    MainOutput,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Byproducts {
+    pub cost_allocation: ConfigValue<CostAllocation>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Quality {
+    pub understaffing_penalty: ConfigValue<f32>,
+    pub max_investment_bonus: ConfigValue<f32>,
+    pub input_weight: ConfigValue<f32>,
+    pub investment_share: ConfigValue<f32>,
+    pub full_investment: ConfigValue<Money>,
+    pub depreciation: ConfigValue<f32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Contracts {
+    pub duration: ConfigValue<usize>,
+    pub delivery_period: ConfigValue<usize>,
+    pub penalty: ConfigValue<f32>,
+    pub max_share_of_output: ConfigValue<f32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Traders {
+    pub risk_appetite: ConfigValue<f32>,
+    pub required_discount: ConfigValue<f32>,
+    pub min_margin: ConfigValue<f32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OffWorld {
+    pub price_volatility: ConfigValue<f32>,
+    pub import_order_size: ConfigValue<u32>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Resource)]
@@ -82,6 +268,8 @@ pub struct Config {
     pub people: People,
     pub business: Business,
     pub government: Government,
+    pub traders: Traders,
+    pub offworld: OffWorld,
     pub init: Init,
 }
 
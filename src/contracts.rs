@@ -0,0 +1,434 @@
+use bevy::prelude::*;
+
+use macros::measured;
+
+use crate::business::{byproduct_price, BuyStrategy, Item, Manufacturer, SellOrder, SellStrategy};
+use crate::config::Config;
+use crate::government::Government;
+use crate::items::ItemId;
+use crate::logs::LogEvent;
+use crate::money::Money;
+use crate::regions::{Region, Regions, Shipment};
+use crate::ui::debug::Performance;
+use crate::wallet::{TradeSide, Transaction, Wallet};
+use crate::Days;
+
+/// Long-term agreement between two manufacturers to deliver `quantity` items every `period`
+/// days for a fixed `price` until day `end`.
+#[derive(Component, Debug, Clone)]
+pub struct SupplyContract {
+    pub(crate) buyer: Entity,
+    pub(crate) seller: Entity,
+    pub(crate) item_type: ItemId,
+    pub(crate) quantity: u32,
+    pub(crate) period: usize,
+    pub(crate) price: Money,
+    pub(crate) next_delivery: usize,
+    pub(crate) end: usize,
+    /// Paid by the party in breach for every item that was not delivered or not paid for
+    pub(crate) penalty: Money,
+}
+
+impl SupplyContract {
+    pub fn involves(&self, entity: Entity) -> bool {
+        self.buyer == entity || self.seller == entity
+    }
+}
+
+/// Expected amount of items produced as main output of the manufacturer's lines in given number
+/// of days
+fn expected_output(manufacturer: &Manufacturer, item_type: &ItemId, days: usize) -> f32 {
+    manufacturer
+        .production_lines
+        .iter()
+        .filter(|line| line.production_cycle.main_output == *item_type)
+        .map(|line| {
+            let cycle = &line.production_cycle;
+            cycle.main_output_quantity() as f32 * line.hired_workers.len() as f32 * days as f32
+                / cycle.workdays_needed as f32
+        })
+        .sum()
+}
+
+/// Amount of input items the manufacturer's lines use in given number of days, at least one cycle
+fn expected_usage(manufacturer: &Manufacturer, item_type: &ItemId, days: usize) -> u32 {
+    let mut usage = 0.0;
+    let mut quantity_per_cycle = 0;
+    for line in &manufacturer.production_lines {
+        let Some(&quantity) = line.production_cycle.input.get(item_type) else {
+            continue;
+        };
+        let workers = line.hired_workers.len().max(1);
+        usage += (quantity as usize * workers * days) as f32
+            / line.production_cycle.workdays_needed as f32;
+        quantity_per_cycle += quantity;
+    }
+    (usage.ceil() as u32).max(quantity_per_cycle)
+}
+
+/// Price the seller asks for `item_type`, products of other lines are priced like byproducts
+/// over the unit cost of their line
+fn contract_price(
+    manufacturer: &Manufacturer,
+    strategy: &SellStrategy,
+    item_type: &ItemId,
+) -> Option<Money> {
+    if manufacturer.main_output() == item_type {
+        return Some(strategy.current_price);
+    }
+    let unit_cost = manufacturer
+        .production_lines
+        .iter()
+        .filter(|line| line.production_cycle.main_output == *item_type)
+        .map(|line| line.last_unit_cost)
+        .find(|cost| *cost > Money(0))?;
+    Some(byproduct_price(strategy, unit_cost))
+}
+
+#[allow(clippy::too_many_arguments)]
+#[measured]
+pub fn negotiate_supply_contracts(
+    buyers: Query<(Entity, &Manufacturer, &Region), With<BuyStrategy>>,
+    sellers: Query<(Entity, &Manufacturer, &SellStrategy, &Region)>,
+    contracts: Query<&SupplyContract>,
+    names: Query<&Name>,
+    regions: Res<Regions>,
+    mut logs: EventWriter<LogEvent>,
+    mut commands: Commands,
+    date: Res<Days>,
+    config: Res<Config>,
+) {
+    let settings = &config.business.contracts;
+    let period = settings.delivery_period.value;
+    // contracts agreed in this run are not visible in the query yet
+    let mut new_contracts: Vec<SupplyContract> = vec![];
+    for (buyer, buyer_manufacturer, buyer_region) in buyers.iter() {
+        for item_type in buyer_manufacturer.input().keys() {
+            let has_contract = contracts
+                .iter()
+                .chain(new_contracts.iter())
+                .any(|contract| contract.buyer == buyer && contract.item_type == *item_type);
+            if has_contract {
+                continue;
+            }
+            let best_offer = sellers
+                .iter()
+                .filter(|(seller, manufacturer, strategy, _)| {
+                    *seller != buyer
+                        // byproducts are only sold on the spot market
+                        && manufacturer.produces(item_type)
+                        && strategy.current_price > Money(0)
+                })
+                .filter_map(|(seller, manufacturer, strategy, seller_region)| {
+                    let route = regions.route(seller_region, buyer_region)?;
+                    let price = contract_price(manufacturer, strategy, item_type)?;
+                    let contracted = contracts
+                        .iter()
+                        .chain(new_contracts.iter())
+                        .filter(|contract| {
+                            contract.seller == seller && contract.item_type == *item_type
+                        })
+                        .map(|contract| contract.quantity)
+                        .sum::<u32>();
+                    let capacity = (expected_output(manufacturer, item_type, period)
+                        * settings.max_share_of_output.value)
+                        .floor() as u32;
+                    let available = capacity.saturating_sub(contracted);
+                    (available > 0).then_some((
+                        seller,
+                        price,
+                        price + route.cost_per_item,
+                        available,
+                    ))
+                })
+                .min_by_key(|(_, _, landed_price, _)| *landed_price);
+            let Some((seller, price, landed_price, available)) = best_offer else {
+                continue;
+            };
+            let quantity = expected_usage(buyer_manufacturer, item_type, period).min(available);
+            let contract = SupplyContract {
+                buyer,
+                seller,
+                item_type: *item_type,
+                quantity,
+                period,
+                price,
+                next_delivery: date.days + 1,
+                end: date.days + settings.duration.value,
+                penalty: price * settings.penalty.value,
+            };
+            let buyer_name = names.get(buyer).map_or("?".to_string(), |n| n.to_string());
+            let seller_name = names.get(seller).map_or("?".to_string(), |n| n.to_string());
+            logs.send(LogEvent::Generic {
+                text: format!(
+                    "I signed a contract with {} for {} {} every {} days at {} ({} with transport) until day {}",
+                    seller_name, quantity, item_type, period, price, landed_price, contract.end
+                ),
+                entity: buyer,
+            });
+            logs.send(LogEvent::Generic {
+                text: format!(
+                    "I signed a contract to deliver {} {} every {} days to {} at {} until day {}",
+                    quantity, item_type, period, buyer_name, price, contract.end
+                ),
+                entity: seller,
+            });
+            commands.spawn((
+                contract.clone(),
+                Name::new(format!("{} supply contract", item_type)),
+            ));
+            new_contracts.push(contract);
+        }
+    }
+}
+
+/// Pays the penalty for `missing` items from the party in breach, as much as it can afford
+fn pay_penalty(
+    wallets: &mut Query<&mut Wallet>,
+    contract: &SupplyContract,
+    from: Entity,
+    to: Entity,
+    missing: u32,
+    logs: &mut EventWriter<LogEvent>,
+    date: usize,
+) {
+    let Ok([mut from_wallet, mut to_wallet]) = wallets.get_many_mut([from, to]) else {
+        return;
+    };
+    let amount = (contract.penalty * missing).min(from_wallet.money());
+    if amount == Money(0) {
+        return;
+    }
+    from_wallet
+        .transaction(
+            &mut to_wallet,
+            &Transaction::Transfer {
+                side: TradeSide::Pay,
+                sender: from,
+                receiver: to,
+                amount,
+                date,
+            },
+            logs,
+        )
+        .unwrap();
+    logs.send(LogEvent::Generic {
+        text: format!(
+            "I paid {} penalty for {} {} missing in the supply contract",
+            amount, missing, contract.item_type
+        ),
+        entity: from,
+    });
+}
+
+/// Takes up to `wanted` items the seller already listed back off the market, emptied sell orders
+/// are removed
+fn withdraw_listed(
+    sell_orders: &mut Query<(Entity, &mut SellOrder)>,
+    seller: Entity,
+    item_type: ItemId,
+    wanted: u32,
+    commands: &mut Commands,
+) -> Vec<Item> {
+    let mut withdrawn = vec![];
+    for (order_entity, mut sell_order) in sell_orders.iter_mut() {
+        if withdrawn.len() as u32 >= wanted {
+            break;
+        }
+        // emptied orders are only despawned at the end of the frame
+        if sell_order.seller != seller
+            || sell_order.item_type != item_type
+            || sell_order.items.is_empty()
+        {
+            continue;
+        }
+        let take = (wanted as usize - withdrawn.len()).min(sell_order.items.len());
+        let remaining = sell_order.items.len() - take;
+        withdrawn.extend(sell_order.items.drain(remaining..));
+        if sell_order.items.is_empty() {
+            commands.entity(order_entity).despawn_recursive();
+        }
+    }
+    withdrawn
+}
+
+/// Delivers contracted items from the seller's stock, items it already listed on the market are
+/// taken back when the stock is short, contracts come first
+#[allow(clippy::too_many_arguments)]
+#[measured]
+pub fn execute_supply_contracts(
+    mut contracts: Query<(Entity, &mut SupplyContract)>,
+    mut manufacturers: Query<(&mut Manufacturer, &Region)>,
+    mut sell_orders: Query<(Entity, &mut SellOrder)>,
+    mut wallets: Query<&mut Wallet>,
+    governments: Query<Entity, With<Government>>,
+    regions: Res<Regions>,
+    mut logs: EventWriter<LogEvent>,
+    mut commands: Commands,
+    date: Res<Days>,
+) {
+    // transport between regions is operated by the government
+    let transport_operator = governments
+        .iter()
+        .next()
+        .expect("There should be a government");
+    for (contract_entity, mut contract) in contracts.iter_mut() {
+        if contract.next_delivery > date.days {
+            continue;
+        }
+        let Ok([(mut seller, seller_region), (mut buyer, buyer_region)]) =
+            manufacturers.get_many_mut([contract.seller, contract.buyer])
+        else {
+            // the other party was wound up, start_liquidation already voided its contracts and
+            // notified the survivor, wind_up_businesses only removes the business afterwards
+            commands.entity(contract_entity).despawn();
+            continue;
+        };
+        if contract.end < date.days {
+            for entity in [contract.buyer, contract.seller] {
+                logs.send(LogEvent::Generic {
+                    text: format!("My supply contract for {} has ended", contract.item_type),
+                    entity,
+                });
+            }
+            commands.entity(contract_entity).despawn();
+            continue;
+        }
+        let Some(route) = regions.route(seller_region, buyer_region) else {
+            commands.entity(contract_entity).despawn();
+            continue;
+        };
+        let total_price = contract.price + route.cost_per_item;
+        let in_stock = seller
+            .assets
+            .items_to_sell
+            .iter()
+            .filter(|item| item.item_type == contract.item_type)
+            .count() as u32;
+        if in_stock < contract.quantity {
+            let listed = withdraw_listed(
+                &mut sell_orders,
+                contract.seller,
+                contract.item_type,
+                contract.quantity - in_stock,
+                &mut commands,
+            );
+            seller.assets.items_to_sell.extend(listed);
+        }
+        let available = seller
+            .assets
+            .items_to_sell
+            .iter()
+            .filter(|item| item.item_type == contract.item_type)
+            .count() as u32;
+        let mut delivered = 0;
+        let mut buyer_defaulted = false;
+        while delivered < contract.quantity.min(available) {
+            let Ok([mut buyer_wallet, mut seller_wallet, mut operator_wallet]) =
+                wallets.get_many_mut([contract.buyer, contract.seller, transport_operator])
+            else {
+                break;
+            };
+            if buyer_wallet.money() < total_price {
+                buyer_defaulted = true;
+                break;
+            }
+            let index = seller
+                .assets
+                .items_to_sell
+                .iter()
+                .position(|item| item.item_type == contract.item_type)
+                .unwrap();
+            let mut item = seller.assets.items_to_sell.remove(index);
+            item.buy_cost = total_price;
+            // funds were checked above so none of those can fail
+            buyer_wallet
+                .transaction(
+                    &mut seller_wallet,
+                    &Transaction::Trade {
+                        side: TradeSide::Pay,
+                        buyer: contract.buyer,
+                        seller: contract.seller,
+                        item: item.clone(),
+                        item_type: contract.item_type,
+                        price: contract.price,
+                        date: date.days,
+                    },
+                    &mut logs,
+                )
+                .unwrap();
+            if route.cost_per_item > Money(0) {
+                buyer_wallet
+                    .transaction(
+                        &mut operator_wallet,
+                        &Transaction::Transfer {
+                            side: TradeSide::Pay,
+                            sender: contract.buyer,
+                            receiver: transport_operator,
+                            amount: route.cost_per_item,
+                            date: date.days,
+                        },
+                        &mut logs,
+                    )
+                    .unwrap();
+            }
+            if route.delivery_days > 0 {
+                commands.spawn((
+                    Shipment {
+                        item,
+                        receiver: contract.buyer,
+                        arrival: date.days + route.delivery_days,
+                        from_buy_order: false,
+                    },
+                    Name::new(format!(
+                        "{} contract shipment from {} to {}",
+                        contract.item_type, seller_region, buyer_region
+                    )),
+                ));
+            } else {
+                buyer
+                    .assets
+                    .items
+                    .entry(contract.item_type)
+                    .or_default()
+                    .push(item);
+            }
+            delivered += 1;
+        }
+        let missing = contract.quantity - delivered;
+        if buyer_defaulted {
+            pay_penalty(
+                &mut wallets,
+                &contract,
+                contract.buyer,
+                contract.seller,
+                missing,
+                &mut logs,
+                date.days,
+            );
+            for entity in [contract.buyer, contract.seller] {
+                logs.send(LogEvent::Generic {
+                    text: format!(
+                        "Supply contract for {} is terminated, buyer can't pay for the delivery",
+                        contract.item_type
+                    ),
+                    entity,
+                });
+            }
+            commands.entity(contract_entity).despawn();
+            continue;
+        }
+        if missing > 0 {
+            pay_penalty(
+                &mut wallets,
+                &contract,
+                contract.seller,
+                contract.buyer,
+                missing,
+                &mut logs,
+                date.days,
+            );
+        }
+        contract.next_delivery += contract.period;
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use bevy::prelude::*;
+
+use crate::business::Manufacturer;
+use crate::init::{ProductionCycleTemplate, Templates};
+use crate::items::ItemRegistry;
+use crate::money::Money;
+use crate::people::Needs;
+use crate::stats::PriceHistory;
+
+pub const DOT_PATH: &str = "production_chain.dot";
+pub const MERMAID_PATH: &str = "production_chain.mmd";
+pub const EXPORT_COMMAND: &str = "export-diagrams";
+
+/// Live numbers shown next to recipes and items
+#[derive(Default)]
+pub struct Annotations {
+    /// Production lines running each production cycle
+    pub manufacturers: HashMap<String, usize>,
+    /// Last median price of each item over all regions
+    pub prices: HashMap<String, Money>,
+}
+
+impl Annotations {
+    pub fn live<'a>(
+        manufacturers: impl Iterator<Item = &'a Manufacturer>,
+        price_history: &PriceHistory,
+    ) -> Self {
+        let mut annotations = Annotations::default();
+        for manufacturer in manufacturers {
+            for line in manufacturer.production_lines.iter() {
+                *annotations
+                    .manufacturers
+                    .entry(line.production_cycle.name.clone())
+                    .or_insert(0) += 1;
+            }
+        }
+        for (item_type, prices) in price_history.prices.iter() {
+            if let Some(stats) = prices.last() {
+                annotations
+                    .prices
+                    .insert(item_type.name().to_string(), stats.median);
+            }
+        }
+        annotations
+    }
+}
+
+#[derive(PartialEq)]
+enum NodeKind {
+    Recipe,
+    Import,
+    Need,
+}
+
+struct Node {
+    label: String,
+    kind: NodeKind,
+}
+
+struct Edge {
+    from: String,
+    to: String,
+    label: String,
+}
+
+/// Recipes are nodes and items flow between them as edges, inputs nobody produces come from an
+/// import node and final goods lead to the needs they satisfy
+struct Diagram {
+    nodes: BTreeMap<String, Node>,
+    edges: Vec<Edge>,
+}
+
+impl Diagram {
+    fn new(
+        cycles: &[ProductionCycleTemplate],
+        needs: &Needs,
+        annotations: Option<&Annotations>,
+    ) -> Self {
+        let mut nodes = BTreeMap::new();
+        let mut edges = Vec::new();
+        let item_label = |item: &str| match annotations.and_then(|a| a.prices.get(item)) {
+            Some(price) => format!("{} @{}", item, price),
+            None => item.to_string(),
+        };
+        for (index, cycle) in cycles.iter().enumerate() {
+            let label = match annotations {
+                Some(annotations) => format!(
+                    "{}\\n{} lines",
+                    cycle.name,
+                    annotations.manufacturers.get(&cycle.name).unwrap_or(&0)
+                ),
+                None => cycle.name.clone(),
+            };
+            nodes.insert(
+                format!("recipe{}", index),
+                Node {
+                    label,
+                    kind: NodeKind::Recipe,
+                },
+            );
+        }
+        for (index, cycle) in cycles.iter().enumerate() {
+            let consumer = format!("recipe{}", index);
+            let mut needed = cycle
+                .input
+                .iter()
+                .chain(cycle.tools.iter())
+                .collect::<Vec<_>>();
+            needed.sort();
+            for (item, &count) in needed {
+                let producers = cycles
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, producer)| producer.output.contains_key(item))
+                    .collect::<Vec<_>>();
+                if producers.is_empty() {
+                    let import = format!("import_{}", node_id(item));
+                    nodes.entry(import.clone()).or_insert(Node {
+                        label: format!("Import {}", item),
+                        kind: NodeKind::Import,
+                    });
+                    edges.push(Edge {
+                        from: import,
+                        to: consumer.clone(),
+                        label: format!("{} {}", count, item_label(item)),
+                    });
+                }
+                for (producer_index, producer) in producers {
+                    edges.push(Edge {
+                        from: format!("recipe{}", producer_index),
+                        to: consumer.clone(),
+                        label: format!(
+                            "{} {} for {}",
+                            producer.output[item],
+                            item_label(item),
+                            count
+                        ),
+                    });
+                }
+            }
+        }
+        let mut sorted_needs = needs.needs.iter().collect::<Vec<_>>();
+        sorted_needs.sort_by_key(|(need, _)| need.as_str());
+        for (need, details) in sorted_needs {
+            let need_node = format!("need_{}", node_id(need));
+            nodes.insert(
+                need_node.clone(),
+                Node {
+                    label: format!("Need: {}", need),
+                    kind: NodeKind::Need,
+                },
+            );
+            let mut satisfied_by = details.satisfied_by.iter().collect::<Vec<_>>();
+            satisfied_by.sort_by_key(|(item_type, _)| **item_type);
+            for (item_type, satisfaction) in satisfied_by {
+                let item = item_type.name();
+                for (index, cycle) in cycles.iter().enumerate() {
+                    if let Some(count) = cycle.output.get(item) {
+                        edges.push(Edge {
+                            from: format!("recipe{}", index),
+                            to: need_node.clone(),
+                            label: format!(
+                                "{} {} satisfies {}",
+                                count,
+                                item_label(item),
+                                satisfaction
+                            ),
+                        });
+                    }
+                }
+            }
+        }
+        Diagram { nodes, edges }
+    }
+
+    fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph production {\n    rankdir=LR;\n");
+        for (id, node) in self.nodes.iter() {
+            let shape = match node.kind {
+                NodeKind::Recipe => "box",
+                NodeKind::Import => "ellipse",
+                NodeKind::Need => "hexagon",
+            };
+            writeln!(
+                dot,
+                "    {} [label=\"{}\", shape={}];",
+                id,
+                escape(&node.label),
+                shape
+            )
+            .unwrap();
+        }
+        for edge in self.edges.iter() {
+            writeln!(
+                dot,
+                "    {} -> {} [label=\"{}\"];",
+                edge.from,
+                edge.to,
+                escape(&edge.label)
+            )
+            .unwrap();
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn to_mermaid(&self) -> String {
+        let mut mermaid = String::from("flowchart LR\n");
+        for (id, node) in self.nodes.iter() {
+            let label = node.label.replace("\\n", "<br>").replace('"', "#quot;");
+            let shape = match node.kind {
+                NodeKind::Recipe => format!("[\"{}\"]", label),
+                NodeKind::Import => format!("([\"{}\"])", label),
+                NodeKind::Need => format!("{{{{\"{}\"}}}}", label),
+            };
+            writeln!(mermaid, "    {}{}", id, shape).unwrap();
+        }
+        for edge in self.edges.iter() {
+            writeln!(
+                mermaid,
+                "    {} -->|\"{}\"| {}",
+                edge.from,
+                edge.label.replace('"', "#quot;"),
+                edge.to
+            )
+            .unwrap();
+        }
+        mermaid
+    }
+}
+
+fn node_id(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+fn escape(label: &str) -> String {
+    label.replace('"', "\\\"")
+}
+
+/// Writes the production chain as Graphviz and Mermaid flowcharts
+pub fn export(
+    cycles: &[ProductionCycleTemplate],
+    needs: &Needs,
+    annotations: Option<&Annotations>,
+) -> std::io::Result<()> {
+    let diagram = Diagram::new(cycles, needs, annotations);
+    std::fs::write(DOT_PATH, diagram.to_dot())?;
+    std::fs::write(MERMAID_PATH, diagram.to_mermaid())?;
+    info!(
+        "Exported production chain to {} and {}",
+        DOT_PATH, MERMAID_PATH
+    );
+    Ok(())
+}
+
+/// Exports the diagrams straight from the data files, without starting the game
+pub fn export_from_files() -> std::io::Result<()> {
+    ItemRegistry::default().load();
+    let mut templates = Templates::default();
+    templates.load();
+    let mut needs = Needs::default();
+    needs.load();
+    export(&templates.production_cycles, &needs, None)
+}
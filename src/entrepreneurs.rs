@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+
+use bevy::prelude::debug;
+
+use crate::config::{DecisionModel, Entrepreneurs};
+use crate::init::ProductionCycleTemplate;
+use crate::items::ItemId;
+use crate::money::Money;
+use crate::recipes::RecipeGraph;
+use crate::regions::Region;
+use crate::stats::{PriceHistory, DAYS_IN_MONTH};
+
+/// Uncertainty of a business whose main output was never sold on the market
+const UNKNOWN_OUTPUT_PRICE_RISK: f32 = 0.5;
+/// Uncertainty added by every input whose cost can not be estimated from market prices
+const UNKNOWN_INPUT_COST_RISK: f32 = 0.2;
+
+/// What a person looking for a business knows about the market of its region
+pub struct MarketOverview<'a> {
+    /// Open buy orders for each item
+    pub demand: &'a HashMap<ItemId, usize>,
+    /// Items sold by manufacturers in the last `sell_history_days`
+    pub sells: &'a HashMap<ItemId, usize>,
+    /// Production lines built around each item
+    pub producers: &'a HashMap<ItemId, usize>,
+    pub sell_history_days: usize,
+    pub prices: &'a PriceHistory,
+    pub region: &'a Region,
+    pub recipes: &'a RecipeGraph,
+    /// Daily salary of a new worker
+    pub salary: Money,
+}
+
+impl MarketOverview<'_> {
+    fn demand(&self, item: &ItemId) -> usize {
+        *self.demand.get(item).unwrap_or(&0)
+    }
+
+    fn sells(&self, item: &ItemId) -> usize {
+        *self.sells.get(item).unwrap_or(&0)
+    }
+
+    fn producers(&self, item: &ItemId) -> usize {
+        *self.producers.get(item).unwrap_or(&0)
+    }
+
+    fn price(&self, item: &ItemId) -> Option<f64> {
+        self.prices
+            .last_median(self.region, item)
+            .map(|price| price.as_f64())
+    }
+
+    /// Cost of one unit of the item, from its market price or from the prices of the raw materials
+    /// it is made of
+    fn unit_cost(&self, item: &str) -> Option<f64> {
+        let item_id = ItemId::get(item)?;
+        self.price(&item_id).or_else(|| {
+            self.recipes
+                .raw_materials(item)
+                .iter()
+                .map(|(raw, amount)| {
+                    ItemId::get(raw)
+                        .filter(|raw_id| *raw_id != item_id)
+                        .and_then(|raw_id| self.price(&raw_id))
+                        .map(|price| price * *amount as f64)
+                })
+                .sum()
+        })
+    }
+}
+
+/// How attractive a business is according to a decision model
+#[derive(Debug, Clone, Copy)]
+pub struct Estimate {
+    /// Higher is better, the unit depends on the model
+    pub score: f64,
+    /// From 0 for a well known market to 1 for a complete guess
+    pub uncertainty: f32,
+}
+
+/// Way a person judges which business is worth founding
+pub trait BusinessModel {
+    /// Estimate of the business built around the cycle, `None` if it is not worth founding at all
+    fn estimate(
+        &self,
+        cycle: &ProductionCycleTemplate,
+        market: &MarketOverview,
+    ) -> Option<Estimate>;
+}
+
+pub fn business_model(config: &Entrepreneurs) -> Box<dyn BusinessModel> {
+    match config.model.value {
+        DecisionModel::RiskScore => Box::new(RiskScore),
+        DecisionModel::ExpectedProfit => Box::new(ExpectedProfit {
+            min_profit: config.min_expected_profit.value,
+            unknown_price_markup: config.unknown_price_markup.value,
+        }),
+    }
+}
+
+/// Business the founder finds best among those they are willing to risk. Uncertain estimates
+/// are discounted less the more risk tolerant the founder is.
+pub fn choose_business<'a>(
+    model: &dyn BusinessModel,
+    cycles: &'a [ProductionCycleTemplate],
+    market: &MarketOverview,
+    risk_tolerance: f32,
+) -> Option<&'a ProductionCycleTemplate> {
+    cycles
+        .iter()
+        .filter_map(|cycle| Some((cycle, model.estimate(cycle, market)?)))
+        .filter(|(_, estimate)| estimate.uncertainty <= risk_tolerance)
+        .map(|(cycle, estimate)| {
+            let confidence = 1.0 - estimate.uncertainty * (1.0 - risk_tolerance);
+            (cycle, estimate.score * confidence as f64)
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(cycle, _)| cycle)
+}
+
+/// Rewards demand and punishes competition and inputs nobody produces, ignores prices
+pub struct RiskScore;
+
+impl BusinessModel for RiskScore {
+    fn estimate(
+        &self,
+        cycle: &ProductionCycleTemplate,
+        market: &MarketOverview,
+    ) -> Option<Estimate> {
+        let main_output = ItemId::get(cycle.main_output())?;
+        let demand = market.demand(&main_output);
+        let sells = market.sells(&main_output);
+        let demand_exists = demand.min(1);
+        let extreme_demand = demand > sells && sells > 0;
+        let competition = market.producers(&main_output);
+        let extreme_demand_bonus = if extreme_demand { 5 } else { 0 };
+        let missing_input_risk = market
+            .recipes
+            .required_inputs(cycle.main_output())
+            .iter()
+            .filter(|input| ItemId::get(input).is_none_or(|id| market.producers(&id) == 0))
+            .count();
+        let risk = extreme_demand_bonus + demand_exists as i32
+            - competition as i32
+            - missing_input_risk as i32;
+        debug!(
+            "Risk calculation for {} = {}: extreme_demand: {}, demand exists: {} competition size: {} missing input: {}",
+            main_output, risk, extreme_demand, demand_exists, competition, missing_input_risk
+        );
+        Some(Estimate {
+            score: risk as f64,
+            uncertainty: 0.0,
+        })
+    }
+}
+
+/// Estimates the monthly profit of a business with a single worker: its share of the market,
+/// sold at the market price, minus inputs, wages and fixed costs. Byproducts are not counted.
+pub struct ExpectedProfit {
+    pub min_profit: Money,
+    /// Markup over the cost expected when the output has no market price yet
+    pub unknown_price_markup: f32,
+}
+
+impl BusinessModel for ExpectedProfit {
+    fn estimate(
+        &self,
+        cycle: &ProductionCycleTemplate,
+        market: &MarketOverview,
+    ) -> Option<Estimate> {
+        let main_output = ItemId::get(cycle.main_output())?;
+        let mut uncertainty = 0.0;
+        let mut input_cost = 0.0;
+        for (input, count) in cycle.input.iter() {
+            match market.unit_cost(input) {
+                Some(cost) => input_cost += cost * *count as f64,
+                None => uncertainty += UNKNOWN_INPUT_COST_RISK,
+            }
+        }
+        let workdays = cycle.workdays_needed.max(1) as f64;
+        let cycle_cost = input_cost + market.salary.as_f64() * workdays;
+        let output = *cycle.output.get(cycle.main_output()).unwrap_or(&1) as f64;
+        let price = market.price(&main_output).unwrap_or_else(|| {
+            uncertainty += UNKNOWN_OUTPUT_PRICE_RISK;
+            cycle_cost / output * (1.0 + self.unknown_price_markup as f64)
+        });
+
+        let capacity = output * DAYS_IN_MONTH as f64 / workdays;
+        let monthly_sells = market.sells(&main_output) as f64 * DAYS_IN_MONTH as f64
+            / market.sell_history_days.max(1) as f64;
+        let market_size = monthly_sells + market.demand(&main_output) as f64;
+        let share = market_size / (market.producers(&main_output) + 1) as f64;
+        let sold = capacity.min(share);
+        let profit = sold * price - sold / output * cycle_cost - cycle.fixed_costs.as_f64();
+        debug!(
+            "Expected profit for {} = {:.0}: price {:.0}, cost per cycle {:.0}, sold {:.1} of {:.1} made, uncertainty {:.1}",
+            main_output, profit, price, cycle_cost, sold, capacity, uncertainty
+        );
+        (profit >= self.min_profit.as_f64()).then_some(Estimate {
+            score: profit,
+            uncertainty: uncertainty.min(1.0),
+        })
+    }
+}
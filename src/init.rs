@@ -6,21 +6,36 @@ use std::io::Read;
 use bevy::core::Name;
 use bevy::log::info;
 use bevy::prelude::*;
+use rand::seq::SliceRandom;
+use rand::Rng;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
+use crate::bankruptcy::Liabilities;
 use crate::business::{
-    BuyStrategy, Inventory, ItemType, Manufacturer, ManufacturerBundle, ProductionCycle,
+    BuyStrategy, Inventory, Manufacturer, ManufacturerBundle, ProductionCycle, ProductionLine,
     SellStrategy, Worker,
 };
-use crate::config::Config;
+use crate::config::{Config, InventoryPolicy};
 use crate::government::Government;
+use crate::items::{ItemId, ItemRegistry, ITEMS_PATH};
 use crate::money::Money;
+use crate::offworld::OffWorld;
 use crate::people;
-use crate::people::{Items, Person};
+use crate::people::Person;
 use crate::people::{Names, Needs};
+use crate::pricing::Pricing;
+use crate::recipes::RecipeGraph;
+use crate::regions::Regions;
+use crate::research::{Research, Technologies};
+use crate::tools::{Tools, TOOLS_PATH};
+use crate::trader::{Trader, TraderBundle};
+use crate::validation::{checked, loaded, DataIssue, Validation};
 use crate::wallet::Wallet;
 
+pub const PRODUCTION_CYCLES_PATH: &str = "data/production_cycles.json";
+pub const MANUFACTURERS_PATH: &str = "data/manufacturers.json";
+
 #[derive(Serialize, Deserialize, Default, Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum TemplateType {
     #[default]
@@ -32,6 +47,10 @@ pub enum TemplateType {
 pub struct Templates {
     pub manufacturers: Vec<ManufacturerTemplate>,
     pub production_cycles: Vec<ProductionCycleTemplate>,
+    /// Built from the production cycles, rebuild it with `update_recipes` when they change
+    pub(crate) recipes: RecipeGraph,
+    /// Production cycles text the recipes were built from
+    recipes_json: String,
     pub(crate) production_cycles_json: String,
     pub(crate) manufacturers_json: String,
     pub(crate) selected_template: TemplateType,
@@ -44,27 +63,58 @@ impl Default for Templates {
         Self {
             manufacturers: Vec::new(),
             production_cycles: Vec::new(),
+            recipes: RecipeGraph::default(),
+            recipes_json: String::new(),
             production_cycles_json: String::new(),
             manufacturers_json: String::new(),
             selected_template: TemplateType::default(),
-            production_cycles_path: "data/production_cycles.json".to_string(),
-            manufacturers_path: "data/manufacturers.json".to_string(),
+            production_cycles_path: PRODUCTION_CYCLES_PATH.to_string(),
+            manufacturers_path: MANUFACTURERS_PATH.to_string(),
         }
     }
 }
 
 impl Templates {
-    fn load(&mut self) {
-        let (production_json, production_cycles) =
-            Self::load_templates(&self.production_cycles_path)
-                .expect("Unable to load production cycles");
-        let (manufacturers_json, manufacturer_templates) =
-            Self::load_templates(&self.manufacturers_path).expect("Unable to load manufacturers");
-        self.manufacturers = manufacturer_templates;
-        self.production_cycles = production_cycles;
-        self.production_cycles_json = production_json;
-        self.manufacturers_json = manufacturers_json;
+    pub(crate) fn load(&mut self) {
+        *self = loaded(Self::try_load());
+    }
+
+    /// Both template files are read so issues in either of them are reported
+    pub(crate) fn try_load() -> Result<Templates, Vec<DataIssue>> {
+        let mut templates = Templates::default();
+        let mut issues = vec![];
+        let issue =
+            |file: &str, error: Box<dyn Error>| DataIssue::new(file, "file", error.to_string());
+        match Self::load_templates(&templates.production_cycles_path) {
+            Ok((json, production_cycles)) => {
+                templates.production_cycles = production_cycles;
+                templates.production_cycles_json = json;
+            }
+            Err(error) => issues.push(issue(&templates.production_cycles_path, error)),
+        }
+        match Self::load_templates(&templates.manufacturers_path) {
+            Ok((json, manufacturers)) => {
+                templates.manufacturers = manufacturers;
+                templates.manufacturers_json = json;
+            }
+            Err(error) => issues.push(issue(&templates.manufacturers_path, error)),
+        }
+        templates.update_recipes();
+        checked(templates, issues)
+    }
+
+    pub(crate) fn update_recipes(&mut self) {
+        self.recipes = RecipeGraph::new(&self.production_cycles);
+        self.recipes_json = self.production_cycles_json.clone();
     }
+
+    /// Rebuilds the recipes only when the production cycles text changed since they were built
+    pub(crate) fn refresh_recipes(&mut self) {
+        if self.recipes_json != self.production_cycles_json {
+            self.update_recipes();
+        }
+    }
+
     pub(crate) fn save(&self) -> Result<(), Box<dyn Error>> {
         let manufacturers_json = serde_json::to_string_pretty(&self.manufacturers)?;
         let production_cycles_json = serde_json::to_string_pretty(&self.production_cycles)?;
@@ -75,9 +125,8 @@ impl Templates {
         Ok(())
     }
 
-    pub(crate) fn validate(&self) -> (Vec<String>, Vec<String>) {
-        let mut errors = Vec::new();
-        let mut warnings = Vec::new();
+    pub(crate) fn validate(&self, offworld: &OffWorld, tools: &Tools) -> Validation {
+        let mut validation = Validation::default();
         let production_cycle_workdays: HashMap<_, _> = self
             .production_cycles
             .iter()
@@ -100,54 +149,154 @@ impl Templates {
 
                 // Compare the workdays_needed to the manufacturer's number of workers
                 if *cycle_workdays > manufacturer.workers.len() as u32 {
-                    warnings.push(format!(
-                        "Manufacturer {} has fewer workers ({}) than workdays needed ({}) for production cycle {}",
-                        manufacturer.name, manufacturer.workers.len(), cycle_workdays, manufacturer.production_cycle
-                    ));
+                    validation.warning(
+                        &self.manufacturers_path,
+                        &manufacturer.name,
+                        format!(
+                            "has fewer workers ({}) than workdays needed ({}) for production cycle {}",
+                            manufacturer.workers.len(),
+                            cycle_workdays,
+                            manufacturer.production_cycle
+                        ),
+                    );
                 }
             } else {
-                errors.push(format!(
-                    "Manufacturer {} has invalid production cycle {}",
-                    manufacturer.name, manufacturer.production_cycle
-                ));
+                validation.error(
+                    &self.manufacturers_path,
+                    &manufacturer.name,
+                    format!(
+                        "has invalid production cycle {}",
+                        manufacturer.production_cycle
+                    ),
+                );
             }
         }
 
         for name in production_cycle_names.difference(&production_cycle_references) {
-            warnings.push(format!(
-                "Production cycle {} is not referenced by any manufacturer",
-                name
-            ));
+            validation.warning(
+                &self.production_cycles_path,
+                name,
+                "is not referenced by any manufacturer".to_string(),
+            );
         }
 
-        warnings.append(&mut self.validate_input_materials());
+        self.validate_items(&mut validation);
+        self.validate_outputs(&mut validation);
+        self.validate_input_materials(offworld, &mut validation);
+        self.validate_tools(tools, &mut validation);
+        self.validate_recipes(offworld, &mut validation);
+
+        validation
+    }
 
-        (errors, warnings)
+    fn validate_items(&self, validation: &mut Validation) {
+        for production_cycle in &self.production_cycles {
+            for item in production_cycle.unknown_items() {
+                validation.error(
+                    &self.production_cycles_path,
+                    &production_cycle.name,
+                    format!("item {} is not defined in {}", item, ITEMS_PATH),
+                );
+            }
+        }
     }
 
-    fn validate_input_materials(&self) -> Vec<String> {
-        let mut warnings = Vec::new();
+    fn validate_outputs(&self, validation: &mut Validation) {
+        let path = &self.production_cycles_path;
+        for production_cycle in &self.production_cycles {
+            let name = &production_cycle.name;
+            if production_cycle.output.is_empty() {
+                validation.error(path, name, "has no output".to_string());
+                continue;
+            }
+            if production_cycle.output.values().any(|&count| count == 0) {
+                validation.error(path, name, "has an output with zero quantity".to_string());
+            }
+            match &production_cycle.main_output {
+                Some(main_output) if !production_cycle.output.contains_key(main_output) => {
+                    validation.error(
+                        path,
+                        name,
+                        format!("main output {} is not one of its outputs", main_output),
+                    )
+                }
+                None if production_cycle.output.len() > 1 => validation.error(
+                    path,
+                    name,
+                    "has several outputs, set main_output to one of them".to_string(),
+                ),
+                _ => {}
+            }
+        }
+    }
 
-        // Create a set of all materials that are produced
-        let produced_materials: HashSet<_> = self
-            .production_cycles
-            .iter()
-            .map(|p| p.output.0.clone())
-            .collect();
+    fn validate_input_materials(&self, offworld: &OffWorld, validation: &mut Validation) {
+        let unreachable_materials = self.recipes.unreachable_items();
 
-        // Check each production cycle's inputs against the set of produced materials
+        // Check each production cycle's inputs and tools against the set of produced materials
         for production_cycle in &self.production_cycles {
-            for input_material in production_cycle.input.keys() {
-                if !produced_materials.contains(input_material) {
-                    warnings.push(format!(
-                        "Input material {} in production cycle {} cannot be created",
-                        input_material, production_cycle.name
-                    ));
+            for input_material in production_cycle
+                .input
+                .keys()
+                .chain(production_cycle.tools.keys())
+            {
+                if unreachable_materials.contains(input_material)
+                    && !offworld.is_importable(input_material)
+                {
+                    validation.warning(
+                        &self.production_cycles_path,
+                        &production_cycle.name,
+                        format!("input material {} cannot be created", input_material),
+                    );
                 }
             }
         }
+    }
 
-        warnings
+    fn validate_recipes(&self, offworld: &OffWorld, validation: &mut Validation) {
+        let path = &self.production_cycles_path;
+        for found in self.recipes.loops() {
+            validation.warning(
+                path,
+                &found[0],
+                format!("production cycles form a loop, {}", found.join(" needs ")),
+            );
+        }
+        let unused = self.recipes.unused_items();
+        for production_cycle in &self.production_cycles {
+            for output in production_cycle.output.keys() {
+                if output != production_cycle.main_output()
+                    && unused.contains(output)
+                    && !offworld.is_exportable(output)
+                {
+                    validation.warning(
+                        path,
+                        &production_cycle.name,
+                        format!(
+                            "byproduct {} is neither used by any cycle nor exported",
+                            output
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    fn validate_tools(&self, tools: &Tools, validation: &mut Validation) {
+        for production_cycle in &self.production_cycles {
+            for tool in production_cycle.tools.keys() {
+                if !tools.is_tool(tool) {
+                    validation.warning(
+                        &self.production_cycles_path,
+                        &production_cycle.name,
+                        format!(
+                            "tool {} is not defined in {}, it will never wear down",
+                            tool, TOOLS_PATH
+                        ),
+                    );
+                }
+            }
+        }
     }
 
     fn load_templates<T: DeserializeOwned>(
@@ -163,12 +312,21 @@ impl Templates {
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ManufacturerTemplate {
-    name: String,
+    pub(crate) name: String,
     money: Money,
     workers: Vec<Worker>,
     production_cycle: String,
     sell_strategy: SellStrategy,
     copies: u32,
+    /// Region where all copies are located, random region for each copy if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) region: Option<String>,
+    /// How the copies buy their inputs, the policy from the config if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    inventory_policy: Option<InventoryPolicy>,
+    /// How the copies price their main output, the pricing from the config if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pricing: Option<Pricing>,
 }
 
 impl ManufacturerTemplate {
@@ -176,10 +334,22 @@ impl ManufacturerTemplate {
         &self,
         production_cycles: HashMap<String, ProductionCycle>,
         names: &Res<Names>,
+        regions: &Regions,
+        storage_capacity: f32,
+        default_pricing: Pricing,
         commands: &mut Commands,
     ) -> Vec<ManufacturerBundle> {
         let mut manufacturers = Vec::new();
         for _ in 0..self.copies {
+            let region = match &self.region {
+                Some(name) => regions.find(name).unwrap_or_else(|| {
+                    panic!(
+                        "{} not found, make sure region with this name is defined in regions.json",
+                        name
+                    )
+                }),
+                None => regions.random(),
+            };
             let workers = self
                 .workers
                 .iter()
@@ -190,6 +360,7 @@ impl ManufacturerTemplate {
                             Wallet::default(),
                             Person::default(),
                             Name::new(people::generate_name(names)),
+                            region.clone(),
                         ))
                         .id()
                 })
@@ -197,20 +368,30 @@ impl ManufacturerTemplate {
             let manufacturer = ManufacturerBundle {
                 name: Name::new(self.name.clone()),
                 manufacturer: Manufacturer {
-                    production_cycle: production_cycles.get(&self.production_cycle)
-                        .cloned()
-                        .unwrap_or_else(|| panic!("{} not found, make sure production cycle with this name is defined in production_cycles.json", self.production_cycle)),
+                    production_lines: vec![ProductionLine::new(
+                        production_cycles.get(&self.production_cycle)
+                            .cloned()
+                            .unwrap_or_else(|| panic!("{} not found, make sure production cycle with this name is defined in production_cycles.json", self.production_cycle)),
+                        workers.clone(),
+                    )],
                     assets: Inventory {
                         items: HashMap::new(),
                         items_to_sell: Default::default(),
                     },
-                    hired_workers: workers.clone(),
                     days_since_last_staff_change: 150,
                     production_log: VecDeque::new(),
+                    quality_investment: 0.0,
+                    research: Research::default(),
+                    storage_capacity,
                     owner: *workers.first().unwrap(),
                 },
                 wallet: Wallet::new(self.money),
-                sell_strategy: self.sell_strategy,
+                sell_strategy: SellStrategy {
+                    pricing: self.pricing.unwrap_or(default_pricing),
+                    ..self.sell_strategy
+                },
+                liabilities: Liabilities::default(),
+                region,
             };
             manufacturers.push(manufacturer);
         }
@@ -225,63 +406,115 @@ impl ManufacturerTemplate {
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ProductionCycleTemplate {
-    name: String,
+    pub(crate) name: String,
     pub(crate) input: HashMap<String, u32>,
-    pub(crate) output: (String, u32),
-    workdays_needed: u32,
+    /// Items needed for the cycle that wear down instead of being consumed
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) tools: HashMap<String, u32>,
+    pub(crate) output: HashMap<String, u32>,
+    /// Required when there is more than one output, the other outputs are byproducts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) main_output: Option<String>,
+    pub(crate) workdays_needed: u32,
+    /// Monthly rent and utilities of a line running this cycle
+    #[serde(default)]
+    pub(crate) fixed_costs: Money,
 }
 
 impl ProductionCycleTemplate {
-    pub fn to_production_cycle(&self) -> (String, ProductionCycle) {
-        let input = self
-            .input
-            .iter()
-            .map(|(name, &count)| (ItemType { name: name.clone() }, count))
-            .collect();
+    /// Output the business is built around, with a single output it does not need to be named
+    pub fn main_output(&self) -> &String {
+        self.main_output.as_ref().unwrap_or_else(|| {
+            self.output
+                .keys()
+                .min()
+                .unwrap_or_else(|| panic!("Production cycle {} has no output", self.name))
+        })
+    }
 
-        let output = (
-            ItemType {
-                name: self.output.0.clone(),
-            },
-            self.output.1,
-        );
+    /// Items of the cycle that are not defined in items.json
+    pub fn unknown_items(&self) -> impl Iterator<Item = &String> {
+        self.input
+            .keys()
+            .chain(self.tools.keys())
+            .chain(self.output.keys())
+            .filter(|item| ItemId::get(item).is_none())
+    }
+
+    /// None when the cycle uses an item that is not defined in items.json
+    pub fn to_production_cycle(&self) -> Option<(String, ProductionCycle)> {
+        let ids = |items: &HashMap<String, u32>| {
+            items
+                .iter()
+                .map(|(name, &count)| Some((ItemId::get(name)?, count)))
+                .collect::<Option<HashMap<_, _>>>()
+        };
 
         let production_cycle = ProductionCycle {
-            input,
-            output,
+            name: self.name.clone(),
+            input: ids(&self.input)?,
+            tools: ids(&self.tools)?,
+            output: ids(&self.output)?,
+            main_output: ItemId::get(self.main_output())?,
             workdays_needed: self.workdays_needed,
-            workdays_left: self.workdays_needed,
+            workdays_left: self.workdays_needed as f32,
+            fixed_costs: self.fixed_costs,
         };
 
-        (self.name.clone(), production_cycle)
+        Some((self.name.clone(), production_cycle))
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn init_templates(
+    mut templates: ResMut<Templates>,
     mut names: ResMut<Names>,
     mut needs: ResMut<Needs>,
-    mut items: ResMut<Items>,
+    mut items: ResMut<ItemRegistry>,
+    mut regions: ResMut<Regions>,
+    mut offworld: ResMut<OffWorld>,
+    mut tools: ResMut<Tools>,
+    mut technologies: ResMut<Technologies>,
 ) {
+    // every other file refers to items by name, so they are interned first
+    items.load();
     names.load();
     needs.load();
-    items.load();
+    regions.load();
+    offworld.load();
+    tools.load();
+    technologies.load();
+    templates.load();
 }
 
-pub fn init_people(names: Res<Names>, config: Res<Config>, mut commands: Commands) {
+pub fn init_people(
+    names: Res<Names>,
+    regions: Res<Regions>,
+    config: Res<Config>,
+    mut commands: Commands,
+) {
+    let mut rng = rand::thread_rng();
+    let max_risk_tolerance = config.business.entrepreneurs.max_risk_tolerance.value;
+    let mut person = || Person {
+        risk_tolerance: rng.gen_range(0.0..=max_risk_tolerance),
+        ..Default::default()
+    };
     // poor people
     for _ in 0..config.init.people.poor.value {
         commands.spawn((
-            Person::default(),
+            person(),
             Name::new(people::generate_name(&names)),
             Wallet::new(Money(20_000)),
+            regions.random(),
         ));
     }
     // rich people
     for _ in 0..config.init.people.rich.value {
         commands.spawn((
-            Person::default(),
+            person(),
             Name::new(people::generate_name(&names)),
             Wallet::new(Money(1_000_000)),
+            regions.random(),
         ));
     }
 }
@@ -294,17 +527,51 @@ pub fn init_governments(mut commands: Commands, config: Res<Config>) {
     ));
 }
 
+pub fn init_traders(
+    mut commands: Commands,
+    templates: Res<Templates>,
+    regions: Res<Regions>,
+    config: Res<Config>,
+) {
+    let mut item_types = templates
+        .production_cycles
+        .iter()
+        .filter_map(|p| ItemId::get(p.main_output()))
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+    if item_types.is_empty() {
+        return;
+    }
+    item_types.shuffle(&mut rand::thread_rng());
+    for item_type in item_types
+        .iter()
+        .cycle()
+        .take(config.init.traders.amount.value as usize)
+    {
+        commands.spawn(TraderBundle {
+            name: Name::new(format!("{} trader", item_type)),
+            trader: Trader::new(*item_type, config.traders.risk_appetite.value),
+            sell_strategy: SellStrategy::default(),
+            wallet: Wallet::new(config.init.traders.starting_money.value),
+            region: regions.random(),
+        });
+    }
+    info!("Created {} traders", config.init.traders.amount.value);
+}
+
 pub fn init_manufacturers(
     mut commands: Commands,
-    mut templates: ResMut<Templates>,
+    templates: Res<Templates>,
     names: Res<Names>,
+    regions: Res<Regions>,
+    config: Res<Config>,
 ) {
-    templates.load();
     let production_cycles = templates
         .clone()
         .production_cycles
         .into_iter()
-        .map(|p| p.to_production_cycle())
+        .filter_map(|p| p.to_production_cycle())
         .collect::<HashMap<_, _>>();
     info!("Loaded {} production cycles", production_cycles.len());
     info!(
@@ -312,10 +579,18 @@ pub fn init_manufacturers(
         templates.manufacturers.len()
     );
     for template in templates.clone().manufacturers {
-        let manufacturers =
-            template.to_manufacturer(production_cycles.clone(), &names, &mut commands);
+        let manufacturers = template.to_manufacturer(
+            production_cycles.clone(),
+            &names,
+            &regions,
+            config.business.storage.initial_capacity.value,
+            config.business.pricing.value,
+            &mut commands,
+        );
         for manufacturer in manufacturers {
-            if manufacturer.manufacturer.production_cycle.input.is_empty() {
+            if manufacturer.manufacturer.input().is_empty()
+                && manufacturer.manufacturer.tools().is_empty()
+            {
                 commands.spawn(manufacturer);
             } else {
                 // TODO check if this works even if input is empty and if so create default buy strategy
@@ -326,7 +601,9 @@ pub fn init_manufacturers(
                 commands.spawn((
                     manufacturer,
                     BuyStrategy {
-                        target_production_cycles: 2,
+                        policy: template
+                            .inventory_policy
+                            .unwrap_or(config.business.inventory.policy.value),
                         outstanding_orders: HashMap::new(),
                     },
                 ));
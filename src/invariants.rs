@@ -7,7 +7,7 @@ pub fn each_hired_worker_should_have_correct_employer(
     names: Query<&Name>,
 ) {
     for (employer, manufacturer) in manufacturers.iter() {
-        for worker_entity in manufacturer.hired_workers.iter() {
+        for worker_entity in manufacturer.hired_workers() {
             if let Ok(worker) = workers.get(*worker_entity) {
                 let worker_name = names.get(*worker_entity).unwrap();
                 worker
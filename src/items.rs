@@ -0,0 +1,231 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::OnceLock;
+
+use bevy::prelude::*;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
+
+use crate::business::Inventory;
+use crate::validation::{checked, loaded, parse_entries, read_data, DataIssue};
+
+pub const ITEMS_PATH: &str = "data/items.json";
+
+/// Names of all items sorted alphabetically, the position is the id of the item. Filled once when
+/// the registry is loaded so ids can be printed and parsed without access to the resource.
+static ITEM_NAMES: OnceLock<Vec<String>> = OnceLock::new();
+
+/// Compact handle of an item defined in items.json, cheap to copy, hash and compare.
+/// Ids are ordered the same way as the item names.
+#[derive(Hash, Eq, PartialEq, Clone, Copy, Ord, PartialOrd)]
+pub struct ItemId(u16);
+
+impl ItemId {
+    /// Id of a registered item, None for unknown names or before the registry is loaded
+    pub fn get(name: &str) -> Option<ItemId> {
+        ITEM_NAMES
+            .get()?
+            .binary_search_by(|item| item.as_str().cmp(name))
+            .ok()
+            .map(|index| ItemId(index as u16))
+    }
+
+    pub fn name(&self) -> &'static str {
+        ITEM_NAMES
+            .get()
+            .and_then(|names| names.get(self.0 as usize))
+            .map(|name| name.as_str())
+            .expect("Items must be loaded before item ids are used")
+    }
+
+    fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl fmt::Display for ItemId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl fmt::Debug for ItemId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.name())
+    }
+}
+
+/// Data files reference items by name, names missing in items.json are rejected while loading
+impl<'de> Deserialize<'de> for ItemId {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let name = String::deserialize(deserializer)?;
+        ItemId::get(&name).ok_or_else(|| {
+            D::Error::custom(format!(
+                "unknown item {}, it is not defined in {}",
+                name, ITEMS_PATH
+            ))
+        })
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemCategory {
+    RawMaterial,
+    Component,
+    Byproduct,
+    Consumable,
+    Durable,
+    Equipment,
+    Infrastructure,
+}
+
+impl fmt::Display for ItemCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ItemCategory::RawMaterial => "raw material",
+            ItemCategory::Component => "component",
+            ItemCategory::Byproduct => "byproduct",
+            ItemCategory::Consumable => "consumable",
+            ItemCategory::Durable => "durable",
+            ItemCategory::Equipment => "equipment",
+            ItemCategory::Infrastructure => "infrastructure",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Item {
+    /// Name shown to the player, the key in items.json when missing
+    #[serde(default)]
+    display_name: Option<String>,
+    pub category: ItemCategory,
+    #[serde(default)]
+    consumption_rate: f64,
+    /// Durable items are not consumed at random, they wear down evenly over this many days
+    #[serde(default)]
+    lifetime: Option<u32>,
+    /// Items needed to bring a worn durable item back to full condition
+    #[serde(default)]
+    repair: HashMap<ItemId, u32>,
+    /// Space one item takes in a warehouse, in cubic meters
+    #[serde(default = "default_volume")]
+    volume: f32,
+}
+
+fn default_volume() -> f32 {
+    1.0
+}
+
+impl Item {
+    /// Chance the item is gone on a given day, for durable items it is spread over the lifetime
+    pub fn daily_loss(&self) -> f64 {
+        self.lifetime
+            .map_or(self.consumption_rate, |days| 1.0 / days as f64)
+    }
+
+    /// Consumed at random or worn down over its lifetime
+    pub fn is_consumed(&self) -> bool {
+        self.consumption_rate > 0.0 || self.lifetime.is_some()
+    }
+
+    pub fn lifetime(&self) -> Option<u32> {
+        self.lifetime
+    }
+
+    pub fn repair(&self) -> &HashMap<ItemId, u32> {
+        &self.repair
+    }
+}
+
+/// Every item of the economy with its metadata, indexed by item id
+#[derive(Resource, Default, Debug)]
+pub struct ItemRegistry {
+    items: Vec<Item>,
+}
+
+impl ItemRegistry {
+    pub fn load(&mut self) {
+        *self = loaded(Self::try_load());
+        info!("Loaded {} items", self.items.len());
+    }
+
+    /// Names are interned first, so items can refer to each other in repairs
+    pub fn try_load() -> Result<ItemRegistry, Vec<DataIssue>> {
+        let items: BTreeMap<String, serde_json::Value> = read_data(ITEMS_PATH)?;
+        let issue = |key: &str, message: String| vec![DataIssue::new(ITEMS_PATH, key, message)];
+        if items.len() > u16::MAX as usize {
+            return Err(issue(
+                "file",
+                format!("too many items, at most {} are supported", u16::MAX),
+            ));
+        }
+        let names = items.keys().cloned().collect::<Vec<_>>();
+        if *ITEM_NAMES.get_or_init(|| names.clone()) != names {
+            return Err(issue(
+                "file",
+                "items can't change while running, restart after editing them".to_string(),
+            ));
+        }
+        let mut issues = vec![];
+        let items = parse_entries::<Item>(ITEMS_PATH, items, &mut issues);
+        for (name, item) in items.iter() {
+            if item.lifetime == Some(0) {
+                issues.extend(issue(
+                    name,
+                    "durable item must have a lifetime of at least one day".to_string(),
+                ));
+            }
+        }
+        let items = items.into_iter().map(|(_, item)| item).collect();
+        checked(ItemRegistry { items }, issues)
+    }
+
+    pub fn get(&self, item_type: &ItemId) -> &Item {
+        &self.items[item_type.index()]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (ItemId, &Item)> {
+        self.items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| (ItemId(index as u16), item))
+    }
+
+    pub fn display_name(&self, item_type: &ItemId) -> &str {
+        self.get(item_type)
+            .display_name
+            .as_deref()
+            .unwrap_or_else(|| item_type.name())
+    }
+
+    /// Item is used to repair some durable item
+    pub fn repairs_with(&self, item_type: &ItemId) -> bool {
+        self.items
+            .iter()
+            .any(|details| details.repair.contains_key(item_type))
+    }
+
+    pub fn volume(&self, item_type: &ItemId) -> f32 {
+        self.get(item_type).volume
+    }
+
+    /// Volume of everything stored in the inventory, finished goods included
+    pub fn stored_volume(&self, inventory: &Inventory) -> f32 {
+        let stored: f32 = inventory
+            .items
+            .iter()
+            .map(|(item_type, items)| self.volume(item_type) * items.len() as f32)
+            .sum();
+        let to_sell: f32 = inventory
+            .items_to_sell
+            .iter()
+            .map(|item| self.volume(&item.item_type))
+            .sum();
+        stored + to_sell
+    }
+}
@@ -1,4 +1,4 @@
-use crate::business::ItemType;
+use crate::items::ItemId;
 use bevy::prelude::*;
 use std::collections::VecDeque;
 use std::fmt::Display;
@@ -20,7 +20,7 @@ pub enum LogEvent {
     Trade {
         buyer: Entity,
         seller: Entity,
-        item_type: ItemType,
+        item_type: ItemId,
         price: Money,
     },
     Salary {
@@ -14,18 +14,32 @@ use ui::people::PeopleSort;
 use crate::config::Config;
 use crate::ui::logs::LoggingFilterType;
 
+mod acquisitions;
+mod bankruptcy;
 mod business;
 mod commands;
 mod config;
+mod contracts;
+mod diagrams;
+mod entrepreneurs;
 mod government;
 mod init;
 mod invariants;
+mod items;
 mod logs;
 mod money;
+mod offworld;
 mod people;
+mod pricing;
+mod recipes;
+mod regions;
+mod research;
 mod stats;
+mod tools;
+mod trader;
 mod ui;
 mod user_input;
+mod validation;
 mod wallet;
 
 #[derive(Deserialize, Resource, Debug)]
@@ -37,6 +51,30 @@ pub struct BuildInfo {
 }
 
 fn main() {
+    if std::env::args().nth(1).as_deref() == Some(diagrams::EXPORT_COMMAND) {
+        diagrams::export_from_files().expect("Unable to export diagrams");
+        println!(
+            "Exported production chain to {} and {}",
+            diagrams::DOT_PATH,
+            diagrams::MERMAID_PATH
+        );
+        return;
+    }
+    if std::env::args().nth(1).as_deref() == Some(validation::VALIDATE_COMMAND) {
+        let validation = validation::validate_files();
+        for warning in validation.warnings.iter() {
+            println!("warning: {}", warning);
+        }
+        for error in validation.errors.iter() {
+            println!("error: {}", error);
+        }
+        println!(
+            "{} errors, {} warnings",
+            validation.errors.len(),
+            validation.warnings.len()
+        );
+        std::process::exit(if validation.errors.is_empty() { 0 } else { 1 });
+    }
     let file = File::open("build_info.json").expect("Failed to open file");
     let info: BuildInfo = from_reader(file).expect("Failed to deserialize");
     info!("Build Info: {:?}", info);
@@ -48,10 +86,15 @@ fn main() {
         .add_plugins((EguiPlugin, config::ConfigPlugin, FrameTimeDiagnosticsPlugin))
         .insert_resource(Days::default())
         .insert_resource(stats::PriceHistory::default())
+        .insert_resource(stats::PriceIndices::default())
         .insert_resource(init::Templates::default())
         .insert_resource(people::Names::default())
         .insert_resource(people::Needs::default())
-        .insert_resource(people::Items::default())
+        .insert_resource(items::ItemRegistry::default())
+        .insert_resource(regions::Regions::default())
+        .insert_resource(offworld::OffWorld::default())
+        .insert_resource(tools::Tools::default())
+        .insert_resource(research::Technologies::default())
         .insert_resource(ui::config::UiState {
             open_settings_panel: ui::config::SettingsPanel::Init,
         })
@@ -72,6 +115,8 @@ fn main() {
             fuzzy_match_threshold: 50,
             fuzzy_match_order: false,
             regex_error: None,
+            price_history_region: None,
+            market_depth_item: None,
         })
         .insert_resource(logs::Logs::default())
         .add_event::<commands::GameCommand>()
@@ -80,9 +125,13 @@ fn main() {
             Startup,
             (
                 init::init_templates,
+                // before anything is spawned from the data
+                validation::validate_data,
                 init::init_manufacturers,
                 init::init_people,
                 init::init_governments,
+                init::init_traders,
+                offworld::init_offworld_market,
             )
                 .chain(),
         )
@@ -101,24 +150,75 @@ fn main() {
                 // those system run in sequence
                 business::order_expiration,
                 business::salary_payout,
-                business::execute_orders,
+                regions::deliver_shipments,
+                (
+                    business::execute_orders,
+                    trader::buy_cheap_items,
+                    offworld::pay_import_tariffs,
+                    offworld::buy_exports,
+                )
+                    .chain(),
                 // business::process_transactions,
-                business::produce,
-                (business::create_buy_orders, business::create_sell_orders), // those run in parallel
+                (
+                    (business::produce, trader::update_trader_prices),
+                    research::complete_research,
+                    contracts::negotiate_supply_contracts,
+                    contracts::execute_supply_contracts,
+                )
+                    .chain(),
+                (
+                    business::create_buy_orders,
+                    business::create_sell_orders,
+                    trader::create_trader_sell_orders,
+                    (
+                        offworld::update_offworld_prices,
+                        offworld::create_import_sell_orders,
+                    )
+                        .chain(),
+                ), // those run in parallel
                 business::assing_workers_to_businesses,
-                business::fire_staff,
-                business::create_job_offers,
+                (
+                    business::plan_staffing,
+                    business::fire_staff,
+                    business::create_job_offers,
+                )
+                    .chain(),
                 business::create_business,
                 business::take_job_offers,
                 business::update_sell_strategy_margin,
                 business::update_sell_order_prices,
-                business::payout_dividends.run_if(next_month),
-                business::reduce_days_since_last_staff_change,
+                (
+                    business::payout_dividends.run_if(next_month),
+                    acquisitions::acquire_businesses,
+                    bankruptcy::liquidate_insolvent,
+                    bankruptcy::detect_insolvency,
+                    bankruptcy::wind_up_businesses,
+                )
+                    .chain(),
+                (
+                    business::reduce_days_since_last_staff_change,
+                    business::gain_experience,
+                )
+                    .chain(),
                 government::create_business_permit,
                 people::consume,
                 people::create_buy_orders_for_people,
-                business::pay_cit.run_if(next_month),
-                stats::add_sell_orders_to_history,
+                (
+                    business::pay_fixed_costs,
+                    business::pay_cit,
+                    business::invest_in_quality,
+                    business::manage_production_lines,
+                    business::expand_storage,
+                    research::start_research,
+                    research::spread_technology,
+                )
+                    .chain()
+                    .run_if(next_month),
+                (
+                    stats::add_sell_orders_to_history,
+                    stats::update_price_indices,
+                )
+                    .chain(),
             )
                 .chain()
                 .run_if(next_turn),
@@ -135,6 +235,7 @@ fn main() {
                 ui::prices::render_price_history,
                 ui::template::render_template_editor,
                 ui::prices::render_todays_prices,
+                ui::market_depth::render_market_depth,
                 ui::logs::render_logs,
                 ui::config::settings,
             ),
@@ -152,7 +253,6 @@ fn main() {
             )
                 .run_if(next_turn),
         )
-        .add_systems(Last, business::bankruption)
         .run();
 }
 
@@ -170,7 +270,7 @@ impl Days {
         self.days += 1;
         self.next_turn = true;
         self.last_update = time.elapsed_seconds();
-        if self.days % 30 == 0 {
+        if self.days.is_multiple_of(30) {
             info!("Month {} started", self.days / 30);
             self.months += 1;
             self.next_month = true;
@@ -13,8 +13,8 @@ pub struct Money(pub u64);
 
 impl Numeric for Money {
     const INTEGRAL: bool = true;
-    const MIN: Self = Money(std::u64::MIN);
-    const MAX: Self = Money(std::u64::MAX);
+    const MIN: Self = Money(u64::MIN);
+    const MAX: Self = Money(u64::MAX);
 
     fn to_f64(self) -> f64 {
         self.0 as f64
@@ -0,0 +1,330 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use rand::Rng;
+use serde::Deserialize;
+
+use macros::measured;
+
+use crate::business::{Item, SellOrder, SellStrategy};
+use crate::config::Config;
+use crate::government::Government;
+use crate::items::{ItemId, ITEMS_PATH};
+use crate::logs::LogEvent;
+use crate::money::Money;
+use crate::regions::{Region, Regions};
+use crate::ui::debug::Performance;
+use crate::validation::{checked, loaded, parse_entries, read_data, DataIssue};
+use crate::wallet::{TradeSide, Transaction, Wallet};
+use crate::Days;
+
+pub const OFFWORLD_PATH: &str = "data/offworld.json";
+
+/// Prices at which items can be bought from or sold to the rest of the galaxy, tariffs excluded
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct OffWorldPrice {
+    #[serde(default)]
+    pub import: Option<Money>,
+    #[serde(default)]
+    pub export: Option<Money>,
+}
+
+#[derive(Resource, Default, Debug)]
+pub struct OffWorld {
+    pub prices: HashMap<ItemId, OffWorldPrice>,
+}
+
+impl OffWorld {
+    pub fn load(&mut self) {
+        *self = loaded(OffWorld::try_load());
+        info!("Loaded off-world prices for {} items", self.prices.len());
+    }
+
+    pub fn try_load() -> Result<OffWorld, Vec<DataIssue>> {
+        let prices: HashMap<String, serde_json::Value> = read_data(OFFWORLD_PATH)?;
+        let mut issues = vec![];
+        let mut offworld = OffWorld::default();
+        for (name, price) in parse_entries::<OffWorldPrice>(OFFWORLD_PATH, prices, &mut issues) {
+            if let (Some(import), Some(export)) = (price.import, price.export) {
+                if export > import {
+                    issues.push(DataIssue::new(
+                        OFFWORLD_PATH,
+                        &name,
+                        "export price is higher than import price, this would be a money printer"
+                            .to_string(),
+                    ));
+                }
+            }
+            match ItemId::get(&name) {
+                Some(item_type) => {
+                    offworld.prices.insert(item_type, price);
+                }
+                None => issues.push(DataIssue::new(
+                    OFFWORLD_PATH,
+                    &name,
+                    format!("item is not defined in {}", ITEMS_PATH),
+                )),
+            }
+        }
+        checked(offworld, issues)
+    }
+
+    fn price(&self, item_type: &str) -> Option<&OffWorldPrice> {
+        ItemId::get(item_type).and_then(|item_type| self.prices.get(&item_type))
+    }
+
+    pub fn is_importable(&self, item_type: &str) -> bool {
+        self.price(item_type)
+            .is_some_and(|price| price.import.is_some())
+    }
+
+    pub fn is_exportable(&self, item_type: &str) -> bool {
+        self.price(item_type)
+            .is_some_and(|price| price.export.is_some())
+    }
+}
+
+/// Agent trading with the rest of the galaxy. It has unlimited supply of imported items and
+/// unlimited demand for exported ones, all tariffs are paid by it to the government.
+#[derive(Component, Default)]
+pub struct OffWorldMarket {}
+
+pub fn init_offworld_market(mut commands: Commands, config: Res<Config>) {
+    commands.spawn((
+        OffWorldMarket::default(),
+        Name::new("Off-world market".to_string()),
+        Wallet::new(config.init.offworld.starting_money.value),
+    ));
+}
+
+pub fn update_offworld_prices(mut offworld: ResMut<OffWorld>, config: Res<Config>) {
+    let volatility = config.offworld.price_volatility.value;
+    if volatility <= 0.0 {
+        return;
+    }
+    let mut rng = rand::thread_rng();
+    for price in offworld.prices.values_mut() {
+        // import and export move together so the spread stays the same
+        let change = 1.0 + rng.gen_range(-volatility..=volatility);
+        price.import = price.import.map(|p| (p * change).max(Money(1)));
+        price.export = price.export.map(|p| (p * change).max(Money(1)));
+    }
+}
+
+#[measured]
+pub fn create_import_sell_orders(
+    markets: Query<Entity, With<OffWorldMarket>>,
+    mut sell_orders: Query<&mut SellOrder>,
+    offworld: Res<OffWorld>,
+    regions: Res<Regions>,
+    mut commands: Commands,
+    config: Res<Config>,
+) {
+    let Ok(market) = markets.get_single() else {
+        return;
+    };
+    let tariff = config.government.taxes.tariff.value;
+    let order_size = config.offworld.import_order_size.value as usize;
+    let mut listed: HashMap<(ItemId, Region), usize> = HashMap::new();
+    for mut sell_order in sell_orders.iter_mut().filter(|o| o.seller == market) {
+        if let Some(import) = offworld
+            .prices
+            .get(&sell_order.item_type)
+            .and_then(|price| price.import)
+        {
+            sell_order.price = import * (1.0 + tariff);
+            sell_order.base_price = import;
+        }
+        *listed
+            .entry((sell_order.item_type, sell_order.region.clone()))
+            .or_default() += sell_order.items.len();
+    }
+    for (item_type, price) in offworld.prices.iter() {
+        let Some(import) = price.import else {
+            continue;
+        };
+        for region in regions.regions.iter() {
+            let already_listed = *listed.get(&(*item_type, region.clone())).unwrap_or(&0);
+            if already_listed >= order_size {
+                continue;
+            }
+            let items = (already_listed..order_size)
+                .map(|_| Item {
+                    item_type: *item_type,
+                    production_cost: import,
+                    buy_cost: Money(0),
+                    quality: 1.0,
+                    condition: 1.0,
+                })
+                .collect();
+            let price = import * (1.0 + tariff);
+            commands.spawn((
+                SellOrder {
+                    items,
+                    item_type: *item_type,
+                    seller: market,
+                    price,
+                    base_price: import,
+                    region: region.clone(),
+                },
+                Name::new(format!("{} import sell order", item_type)),
+                SellStrategy {
+                    max_price_change_per_day: 0.0,
+                    current_price: price,
+                    base_price: import,
+                    ..Default::default()
+                },
+            ));
+        }
+    }
+}
+
+/// Buys every sell order cheap enough to be exported and pays the export tariff
+#[allow(clippy::too_many_arguments)]
+#[measured]
+pub fn buy_exports(
+    markets: Query<Entity, With<OffWorldMarket>>,
+    governments: Query<Entity, With<Government>>,
+    mut sell_orders: Query<(Entity, &mut SellOrder)>,
+    mut wallets: Query<&mut Wallet>,
+    offworld: Res<OffWorld>,
+    mut logs: EventWriter<LogEvent>,
+    mut commands: Commands,
+    date: Res<Days>,
+    config: Res<Config>,
+) {
+    let Ok(market) = markets.get_single() else {
+        return;
+    };
+    let government = governments
+        .iter()
+        .next()
+        .expect("There should be a government");
+    let tariff = config.government.taxes.tariff.value;
+    for (sell_order_entity, mut sell_order) in sell_orders.iter_mut() {
+        if sell_order.seller == market {
+            continue;
+        }
+        let Some(export) = offworld
+            .prices
+            .get(&sell_order.item_type)
+            .and_then(|price| price.export)
+        else {
+            continue;
+        };
+        let duty = sell_order.price * tariff;
+        if sell_order.price + duty > export {
+            continue;
+        }
+        let mut exported = 0;
+        while let Some(item) = sell_order.items.last().cloned() {
+            let Ok([mut market_wallet, mut seller_wallet, mut government_wallet]) =
+                wallets.get_many_mut([market, sell_order.seller, government])
+            else {
+                break;
+            };
+            if market_wallet.money() < sell_order.price + duty {
+                warn!("Off-world market ran out of money, no more exports possible");
+                break;
+            }
+            // funds were checked above so none of those can fail
+            market_wallet
+                .transaction(
+                    &mut seller_wallet,
+                    &Transaction::Trade {
+                        side: TradeSide::Pay,
+                        buyer: market,
+                        seller: sell_order.seller,
+                        item,
+                        item_type: sell_order.item_type,
+                        price: sell_order.price,
+                        date: date.days,
+                    },
+                    &mut logs,
+                )
+                .unwrap();
+            if duty > Money(0) {
+                market_wallet
+                    .transaction(
+                        &mut government_wallet,
+                        &Transaction::Transfer {
+                            side: TradeSide::Pay,
+                            sender: market,
+                            receiver: government,
+                            amount: duty,
+                            date: date.days,
+                        },
+                        &mut logs,
+                    )
+                    .unwrap();
+            }
+            sell_order.items.pop();
+            exported += 1;
+        }
+        if exported > 0 {
+            logs.send(LogEvent::Generic {
+                text: format!(
+                    "I exported {} {} for {} each",
+                    exported, sell_order.item_type, sell_order.price
+                ),
+                entity: sell_order.seller,
+            });
+        }
+        if sell_order.items.is_empty() {
+            commands.entity(sell_order_entity).despawn();
+        }
+    }
+}
+
+/// Forwards the tariff included in the price of today's imports to the government
+pub fn pay_import_tariffs(
+    markets: Query<Entity, With<OffWorldMarket>>,
+    governments: Query<Entity, With<Government>>,
+    mut wallets: Query<&mut Wallet>,
+    mut logs: EventWriter<LogEvent>,
+    date: Res<Days>,
+    config: Res<Config>,
+) {
+    let Ok(market) = markets.get_single() else {
+        return;
+    };
+    let government = governments
+        .iter()
+        .next()
+        .expect("There should be a government");
+    let tariff = config.government.taxes.tariff.value;
+    let Ok([mut market_wallet, mut government_wallet]) = wallets.get_many_mut([market, government])
+    else {
+        return;
+    };
+    let imported = market_wallet
+        .transactions
+        .iter()
+        .take_while(|transaction| transaction.get_date() == date.days)
+        .filter_map(|transaction| match transaction {
+            Transaction::Trade {
+                side: TradeSide::Receive,
+                price,
+                ..
+            } => Some(*price),
+            _ => None,
+        })
+        .sum::<Money>();
+    // runs before exports are bought so the market still holds the money it received
+    let amount = (imported * (tariff / (1.0 + tariff))).min(market_wallet.money());
+    if amount > Money(0) {
+        if let Err(error) = market_wallet.transaction(
+            &mut government_wallet,
+            &Transaction::Transfer {
+                side: TradeSide::Pay,
+                sender: market,
+                receiver: government,
+                amount,
+                date: date.days,
+            },
+            &mut logs,
+        ) {
+            warn!("Off-world market could not pay import tariffs: {}", error);
+        }
+    }
+}
@@ -4,17 +4,25 @@ use bevy::prelude::*;
 use rand::distributions::{Distribution, WeightedIndex};
 use rand::prelude::{SliceRandom, ThreadRng};
 use rand::Rng;
-use serde::{Deserialize, Deserializer};
+use serde::Deserialize;
 
 use macros::measured;
 
-use crate::business::{BuyOrder, Inventory, ItemType, OrderType};
-use crate::config::Config;
+use crate::business::{BuyOrder, Inventory, OrderType};
+use crate::config::{Config, Skills};
+use crate::items::{ItemId, ItemRegistry};
 use crate::logs::LogEvent;
+use crate::money::Money;
+use crate::regions::Region;
 use crate::stats::PriceHistory;
+use crate::tools::repair_worn_items;
 use crate::ui::debug::Performance;
+use crate::validation::{checked, loaded, parse_entries, read_data, DataIssue};
 use crate::wallet::Wallet;
 
+pub const NEEDS_PATH: &str = "data/needs.json";
+pub const NAMES_PATH: &str = "data/names.json";
+
 #[derive(Debug, Deserialize, Resource, Default, Clone)]
 pub struct Names {
     first_names: Vec<String>,
@@ -26,53 +34,46 @@ pub struct Names {
 pub struct Need {
     pub base: f64,
     pub preference: f64,
-    #[serde(deserialize_with = "deserialize_item_type_map")]
-    pub satisfied_by: HashMap<ItemType, f64>,
-    #[serde(default, deserialize_with = "deserialize_optional_item_type_map")]
-    pub increased_by: Option<HashMap<ItemType, f64>>,
+    pub satisfied_by: HashMap<ItemId, f64>,
+    #[allow(dead_code)]
+    #[serde(default)]
+    pub increased_by: Option<HashMap<ItemId, f64>>,
 }
 
+/// Needs by their name, items satisfying them must be loaded first
 #[derive(Resource, Default)]
 pub struct Needs {
-    pub needs: HashMap<ItemType, Need>,
-}
-
-#[derive(Deserialize, Debug)]
-pub struct Item {
-    consumption_rate: f64,
+    pub needs: HashMap<String, Need>,
 }
 
-#[derive(Debug, Deserialize, Resource, Default)]
-pub struct Items {
-    pub items: HashMap<String, Item>,
-}
-
-impl Items {
+impl Needs {
     pub fn load(&mut self) {
-        let items = std::fs::read_to_string("data/items.json").unwrap();
-        let items: HashMap<String, Item> = serde_json::from_str(&items).unwrap();
-        self.items = items;
+        *self = loaded(Needs::try_load());
     }
-}
 
-impl Needs {
-    pub fn load(&mut self) {
-        let needs = std::fs::read_to_string("data/needs.json").unwrap();
-        let needs: HashMap<String, Need> = serde_json::from_str(&needs).unwrap();
-        self.needs = needs
+    /// Reads every need on its own so all broken ones are reported by their name
+    pub fn try_load() -> Result<Needs, Vec<DataIssue>> {
+        let needs: HashMap<String, serde_json::Value> = read_data(NEEDS_PATH)?;
+        let mut issues = vec![];
+        let needs = parse_entries(NEEDS_PATH, needs, &mut issues)
             .into_iter()
-            .map(|(k, v)| (ItemType { name: k }, v))
             .collect();
+        checked(Needs { needs }, issues)
     }
 }
 
 impl Names {
+    /// Every list a name is picked from, by its key in names.json
+    pub fn lists(&self) -> [(&str, &Vec<String>); 3] {
+        [
+            ("first_names", &self.first_names),
+            ("nicknames", &self.nicknames),
+            ("last_names", &self.last_names),
+        ]
+    }
+
     pub fn load(&mut self) {
-        let names = std::fs::read_to_string("data/names.json").unwrap();
-        let names: Names = serde_json::from_str(&names).unwrap();
-        self.first_names = names.first_names;
-        self.nicknames = names.nicknames;
-        self.last_names = names.last_names;
+        *self = loaded(Names::try_load());
         let unique_names = self.first_names.len() as u64
             * self.nicknames.len() as u64
             * self.last_names.len() as u64;
@@ -82,33 +83,9 @@ impl Names {
         );
         info!("Name collision probabilities for n people: 10: {:.3}%, 100: {:.3}%, 1000: {:.3}%, 10000: {:.3}%", collision_probability(10, unique_names), collision_probability(100, unique_names), collision_probability(1000, unique_names), collision_probability(10000, unique_names));
     }
-}
-
-fn deserialize_item_type_map<'de, D>(deserializer: D) -> Result<HashMap<ItemType, f64>, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let map: HashMap<String, f64> = HashMap::deserialize(deserializer)?;
-    Ok(map
-        .into_iter()
-        .map(|(k, v)| (ItemType { name: k }, v))
-        .collect())
-}
 
-fn deserialize_optional_item_type_map<'de, D>(
-    deserializer: D,
-) -> Result<Option<HashMap<ItemType, f64>>, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let opt_map: Option<HashMap<String, f64>> = Option::deserialize(deserializer)?;
-    match opt_map {
-        Some(map) => Ok(Some(
-            map.into_iter()
-                .map(|(k, v)| (ItemType { name: k }, v))
-                .collect(),
-        )),
-        None => Ok(None),
+    pub fn try_load() -> Result<Names, Vec<DataIssue>> {
+        read_data(NAMES_PATH)
     }
 }
 
@@ -129,29 +106,60 @@ pub(crate) fn generate_name(names: &Res<Names>) -> String {
 pub struct Person {
     pub(crate) assets: Inventory,
     pub utility: VecDeque<f64>,
+    /// Days worked in each industry, named by the main output of the production line
+    pub(crate) experience: HashMap<ItemId, u32>,
+    /// Uncertainty of a business the person is still willing to found, from 0 to 1
+    pub(crate) risk_tolerance: f32,
+}
+
+impl Person {
+    /// Workdays contributed per day of work in the industry. Grows from novice towards expert
+    /// productivity with experience, quickly at first and slower later on.
+    pub fn productivity(&self, industry: &ItemId, config: &Skills) -> f32 {
+        let days = *self.experience.get(industry).unwrap_or(&0) as f32;
+        let learned = 1.0 - (-days / config.days_to_learn.value.max(1) as f32).exp();
+        config.novice_productivity.value
+            + (config.expert_productivity.value - config.novice_productivity.value) * learned
+    }
+
+    /// Salary the person asks for a job in the industry, standard worker gets the base salary
+    pub fn expected_salary(&self, industry: &ItemId, base: Money, config: &Skills) -> Money {
+        let productivity = self.productivity(industry, config);
+        base * (1.0 + config.salary_share.value * (productivity - 1.0)).max(0.0)
+    }
 }
 
 #[measured]
-pub fn consume(mut people: Query<(Entity, &Name, &mut Person)>, items: Res<Items>) {
+pub fn consume(
+    mut people: Query<(Entity, &Name, &mut Person)>,
+    items: Res<ItemRegistry>,
+    config: Res<Config>,
+) {
     let mut rng = rand::thread_rng();
     for (_, name, mut person) in people.iter_mut() {
-        let mut amount_to_remove: HashMap<ItemType, usize> = HashMap::new();
+        repair_durables(&mut person.assets, &items, config.people.repair_below.value);
+        let mut amount_to_remove: HashMap<ItemId, usize> = HashMap::new();
         for (item_type, all_items) in person.assets.items.iter_mut() {
-            let consumption_rate = items
-                .items
-                .get(&item_type.name)
-                .unwrap_or_else(|| {
-                    panic!(
-                        "Item {} does not have consumption rate! Fix this in items.json",
-                        &item_type.name
-                    )
-                })
-                .consumption_rate;
+            let item = items.get(item_type);
+            if let Some(lifetime) = item.lifetime() {
+                for durable in all_items.iter_mut() {
+                    durable.condition -= 1.0 / lifetime as f32;
+                }
+                all_items.retain(|durable| {
+                    let worn_out = durable.condition <= 0.0;
+                    if worn_out {
+                        debug!("{}'s {} is worn out", name, item_type.name());
+                    }
+                    !worn_out
+                });
+                continue;
+            }
+            let consumption_rate = item.daily_loss();
             for _ in all_items.iter_mut() {
                 if rng.gen_range(0.0..=1.0) < consumption_rate {
-                    debug!("{} consumed {}", name, item_type.name);
+                    debug!("{} consumed {}", name, item_type.name());
                     amount_to_remove
-                        .entry(item_type.clone())
+                        .entry(*item_type)
                         .and_modify(|e| *e += 1)
                         .or_insert(1);
                 }
@@ -169,21 +177,89 @@ pub fn consume(mut people: Query<(Entity, &Name, &mut Person)>, items: Res<Items
     }
 }
 
+fn repair_durables(assets: &mut Inventory, items: &ItemRegistry, repair_below: f32) {
+    for (item_type, item) in items.iter().filter(|(_, item)| item.lifetime().is_some()) {
+        repair_worn_items(assets, &item_type, item.repair(), repair_below);
+    }
+}
+
+/// Repair items missing to fix worn durable items the person owns, those already ordered are
+/// not ordered again
+fn repair_items_to_buy(
+    assets: &Inventory,
+    ordered: &HashMap<ItemId, u32>,
+    items: &ItemRegistry,
+    repair_below: f32,
+) -> Vec<ItemId> {
+    let mut needed: HashMap<ItemId, u32> = HashMap::new();
+    for (item_type, owned) in assets.items.iter() {
+        let item = items.get(item_type);
+        let worn = owned
+            .iter()
+            .filter(|durable| durable.condition < repair_below)
+            .count() as u32;
+        if item.lifetime().is_none() || worn == 0 {
+            continue;
+        }
+        for (repair_item, count) in item.repair() {
+            *needed.entry(*repair_item).or_insert(0) += count * worn;
+        }
+    }
+    needed
+        .into_iter()
+        .flat_map(|(item_type, count)| {
+            let owned = assets.items.get(&item_type).map_or(0, |i| i.len()) as u32
+                + ordered.get(&item_type).unwrap_or(&0);
+            std::iter::repeat_n(item_type, count.saturating_sub(owned) as usize)
+        })
+        .collect()
+}
+
 #[allow(clippy::too_many_arguments)]
 #[measured]
 pub fn create_buy_orders_for_people(
-    mut people: Query<(Entity, &Name, &Wallet, &mut Person)>,
+    mut people: Query<(Entity, &Name, &Wallet, &mut Person, &Region)>,
+    buy_orders: Query<&BuyOrder>,
     needs: Res<Needs>,
     price_history: Res<PriceHistory>,
-    items: Res<Items>,
+    items: Res<ItemRegistry>,
     mut logs: EventWriter<LogEvent>,
     mut commands: Commands,
     config: Res<Config>,
 ) {
     let mut rng = rand::thread_rng();
-    for (buyer, name, _, mut person) in people.iter_mut() {
+    let mut ordered: HashMap<Entity, HashMap<ItemId, u32>> = HashMap::new();
+    for buy_order in buy_orders.iter() {
+        *ordered
+            .entry(buy_order.buyer)
+            .or_default()
+            .entry(buy_order.item_type)
+            .or_insert(0) += 1;
+    }
+    for (buyer, name, _, mut person, region) in people.iter_mut() {
         let mut total_assets = calculate_total_items(&person.assets);
         let mut item_buy_success_count = 0;
+        // keeping durable items in shape comes before buying new things
+        for item_type in repair_items_to_buy(
+            &person.assets,
+            ordered.get(&buyer).unwrap_or(&HashMap::new()),
+            &items,
+            config.people.repair_below.value,
+        )
+        .iter()
+        .take(config.people.max_buy_orders_per_day.value as usize)
+        {
+            create_buy_order(
+                &mut logs,
+                &mut commands,
+                buyer,
+                name,
+                region,
+                item_type,
+                config.people.order_expiration_time.value,
+            );
+            item_buy_success_count += 1;
+        }
 
         person
             .utility
@@ -197,12 +273,13 @@ pub fn create_buy_orders_for_people(
                 &mut rng,
                 buyer,
                 name,
+                region,
                 &items,
                 &total_assets,
                 &config,
             ) {
                 Some(item) => {
-                    *total_assets.entry(item).or_insert(0) += 1;
+                    *total_assets.entry(item).or_insert(0.0) += 1.0;
                     item_buy_success_count += 1;
                 }
                 None => break,
@@ -220,19 +297,20 @@ fn try_to_buy_item(
     mut rng: &mut ThreadRng,
     buyer: Entity,
     name: &Name,
-    items: &Items,
-    total_assets: &HashMap<ItemType, u64>,
+    region: &Region,
+    items: &ItemRegistry,
+    total_assets: &HashMap<ItemId, f64>,
     config: &Config,
-) -> Option<ItemType> {
-    let mut person_marginal_utilities: HashMap<ItemType, f64> = HashMap::new();
+) -> Option<ItemId> {
+    let mut person_marginal_utilities: HashMap<ItemId, f64> = HashMap::new();
     let d = config.people.discount_rate.value; // monthly discount rate
     let d = (1.0 + d).powf(1.0 / 30.0) - 1.0; // daily discount rate
-    for need in needs.needs.iter().flat_map(|(_, n)| n.satisfied_by.keys()) {
-        let p = items.items.get(&need.name).unwrap().consumption_rate; // probability that item will be consumed
+    for need in needs.needs.values().flat_map(|n| n.satisfied_by.keys()) {
+        let p = items.get(need).daily_loss(); // probability that item will be consumed
         let cumulation_factor = d * (1.0 - p) / (1.0 - d * (1.0 - p));
         let util =
             cumulation_factor * marginal_utility(needs, name, total_assets, price_history, need);
-        person_marginal_utilities.insert(need.clone(), util);
+        person_marginal_utilities.insert(*need, util);
     }
     if let Some(money_utility) = calculate_money_utility(&person_marginal_utilities, price_history)
     {
@@ -243,6 +321,7 @@ fn try_to_buy_item(
             &mut rng,
             buyer,
             name,
+            region,
             &mut person_marginal_utilities,
             money_utility,
             config,
@@ -254,6 +333,7 @@ fn try_to_buy_item(
                 commands,
                 buyer,
                 name,
+                region,
                 &mut person_marginal_utilities,
                 config,
             )
@@ -264,6 +344,7 @@ fn try_to_buy_item(
             commands,
             buyer,
             name,
+            region,
             &mut person_marginal_utilities,
             config,
         )
@@ -275,9 +356,10 @@ fn create_buy_order_without_money_utlity(
     commands: &mut Commands,
     buyer: Entity,
     name: &Name,
-    person_marginal_utilities: &mut HashMap<ItemType, f64>,
+    region: &Region,
+    person_marginal_utilities: &mut HashMap<ItemId, f64>,
     config: &Config,
-) -> Option<ItemType> {
+) -> Option<ItemId> {
     let biggest_marginal_utility_item_type = person_marginal_utilities
         .iter()
         .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
@@ -288,6 +370,7 @@ fn create_buy_order_without_money_utlity(
         commands,
         buyer,
         name,
+        region,
         biggest_marginal_utility_item_type,
         config.people.order_expiration_time.value,
     ))
@@ -301,10 +384,11 @@ fn create_buy_order_with_money_utility(
     mut rng: &mut &mut ThreadRng,
     buyer: Entity,
     name: &Name,
-    person_marginal_utilities: &mut HashMap<ItemType, f64>,
+    region: &Region,
+    person_marginal_utilities: &mut HashMap<ItemId, f64>,
     money_utility: f64,
     config: &Config,
-) -> Option<ItemType> {
+) -> Option<ItemId> {
     debug!("Money utility for {} is {}", name, money_utility);
     let utilities_with_prices = calculate_marginal_utilities_adjusted_by_prices(
         person_marginal_utilities,
@@ -317,7 +401,7 @@ fn create_buy_order_with_money_utility(
         name, utilities_with_prices
     );
     // Sort by utility
-    let mut utilities: Vec<(&ItemType, &f64)> = utilities_with_prices.iter().collect();
+    let mut utilities: Vec<(&ItemId, &f64)> = utilities_with_prices.iter().collect();
     utilities.sort_by(|a, b| a.1.partial_cmp(b.1).unwrap());
 
     // Convert utilities to weights
@@ -339,12 +423,13 @@ fn create_buy_order_with_money_utility(
     // Get the corresponding item
     let (item_type, _util) = utilities[index];
 
-    trace!("Chosen item for person {} is {}", name, item_type.name);
+    trace!("Chosen item for person {} is {}", name, item_type.name());
     Some(create_buy_order(
         logs,
         commands,
         buyer,
         name,
+        region,
         item_type,
         config.people.order_expiration_time.value,
     ))
@@ -355,41 +440,44 @@ fn create_buy_order(
     commands: &mut Commands,
     buyer: Entity,
     name: &Name,
-    item_type: &ItemType,
+    region: &Region,
+    item_type: &ItemId,
     expiration: u64,
-) -> ItemType {
+) -> ItemId {
     let buy_order = BuyOrder {
-        item_type: item_type.clone(),
+        item_type: *item_type,
         buyer,
         order: OrderType::Market, // Always buying at market price
         expiration: Some(expiration),
+        region: region.clone(),
     };
     logs.send(LogEvent::Generic {
         text: format!(
             "{}: I'll try to buy {} at market price",
-            name, item_type.name
+            name,
+            item_type.name()
         ),
         entity: buyer,
     });
     commands.spawn((
         buy_order.clone(),
-        Name::new(format!("Consumer {} buy order @Market", item_type.name)),
+        Name::new(format!("Consumer {} buy order @Market", item_type.name())),
     ));
-    item_type.clone()
+    *item_type
 }
 
 fn calculate_marginal_utilities_adjusted_by_prices(
-    item_utilities: &HashMap<ItemType, f64>,
+    item_utilities: &HashMap<ItemId, f64>,
     price_history: &PriceHistory,
     money_utility: f64,
-) -> HashMap<ItemType, f64> {
+) -> HashMap<ItemId, f64> {
     let mut result = HashMap::new();
     for (item_type, item_utility) in item_utilities.iter() {
         if let Some(price_stats) = price_history.prices.get(item_type) {
             if let Some(last_price) = price_stats.last() {
                 let updated_utility = item_utility - last_price.median.as_f64() * money_utility;
                 if updated_utility > 0.0 {
-                    result.insert(item_type.clone(), updated_utility);
+                    result.insert(*item_type, updated_utility);
                 }
             }
         }
@@ -398,7 +486,7 @@ fn calculate_marginal_utilities_adjusted_by_prices(
 }
 
 fn calculate_money_utility(
-    item_utilities: &HashMap<ItemType, f64>,
+    item_utilities: &HashMap<ItemId, f64>,
     price_history: &PriceHistory,
 ) -> Option<f64> {
     let mut total_utility = 0.0;
@@ -420,10 +508,14 @@ fn calculate_money_utility(
     }
 }
 
-fn calculate_total_items(assets: &Inventory) -> HashMap<ItemType, u64> {
+/// Quality weighted amount of items, one item of standard quality counts as 1.0
+fn calculate_total_items(assets: &Inventory) -> HashMap<ItemId, f64> {
     let mut result = HashMap::new();
     for (item_type, items) in assets.items.iter() {
-        result.insert(item_type.clone(), items.len() as u64);
+        result.insert(
+            *item_type,
+            items.iter().map(|item| item.quality as f64).sum(),
+        );
     }
     result
 }
@@ -431,17 +523,17 @@ fn calculate_total_items(assets: &Inventory) -> HashMap<ItemType, u64> {
 fn marginal_utility(
     needs: &Needs,
     name: &Name,
-    total_items: &HashMap<ItemType, u64>,
+    total_items: &HashMap<ItemId, f64>,
     price_history: &PriceHistory,
-    item_type: &ItemType,
+    item_type: &ItemId,
 ) -> f64 {
     // Create a mutable copy of the total_items HashMap
     let mut total_items_copy = total_items.clone();
 
-    // Increase the quantity of the given ItemType by one.
-    // If the ItemType is not already in the HashMap, this inserts it with a quantity of one.
+    // Increase the quantity of the given ItemId by one item of standard quality.
+    // If the ItemId is not already in the HashMap, this inserts it with a quantity of one.
     let original_utility = utility(needs, name, total_items, price_history);
-    *total_items_copy.entry(item_type.clone()).or_insert(0) += 1;
+    *total_items_copy.entry(*item_type).or_insert(0.0) += 1.0;
     let new_utility = utility(needs, name, &total_items_copy, price_history);
     new_utility - original_utility
 }
@@ -449,16 +541,15 @@ fn marginal_utility(
 fn utility(
     needs: &Needs,
     _name: &Name,
-    total_items: &HashMap<ItemType, u64>,
+    total_items: &HashMap<ItemId, f64>,
     _price_history: &PriceHistory,
 ) -> f64 {
     let mut result = 1.0;
     // calculate utility for each need
     for (_, need) in needs.needs.iter() {
         for (item_type, amount) in need.satisfied_by.iter() {
-            let items_count = *total_items.get(item_type).unwrap_or(&0);
-            let item_utility =
-                ((items_count as f64 * amount + 1.0) / need.base).powf(need.preference);
+            let items_count = *total_items.get(item_type).unwrap_or(&0.0);
+            let item_utility = ((items_count * amount + 1.0) / need.base).powf(need.preference);
             // info!("Utility for person {} for {} is {}", name, item, item_utility);
             result *= item_utility;
         }
@@ -0,0 +1,276 @@
+use std::fmt::{self, Display};
+
+use serde::{Deserialize, Serialize};
+
+use crate::business::{Manufacturer, SellStrategy};
+use crate::money::Money;
+use crate::wallet::Wallet;
+
+/// What a seller knows about the market of its main output when it reprices it
+pub struct PricingContext<'a> {
+    pub strategy: &'a SellStrategy,
+    pub manufacturer: &'a Manufacturer,
+    pub wallet: &'a Wallet,
+    /// Items of the main output waiting to be sold or listed on the market
+    pub in_stock: usize,
+    /// Last median price of the main output in the seller's region
+    pub market_price: Option<Money>,
+    pub date: usize,
+}
+
+impl PricingContext<'_> {
+    pub fn sold(&self, days: usize) -> usize {
+        self.wallet.get_amount_of_sell_transactions(
+            self.date,
+            self.manufacturer.main_output(),
+            days,
+        )
+    }
+
+    pub fn produced(&self, days: usize) -> usize {
+        self.manufacturer
+            .production_log
+            .iter()
+            .take_while(|log| self.date - log.date <= days)
+            .count()
+    }
+
+    /// Change that moves the current price towards the target as fast as the strategy allows
+    fn towards(&self, target: Money) -> f32 {
+        let current = self.strategy.current_price.as_f64();
+        // nothing was produced yet, so there is no price or cost to start from
+        if current <= 0.0 || target == Money(0) {
+            return 1.0;
+        }
+        let max_change = self.strategy.max_price_change_per_day as f64;
+        (target.as_f64() / current).clamp(1.0 - max_change, 1.0 + max_change) as f32
+    }
+}
+
+/// New price of the main output, as a factor of the current price, with the reason for it
+pub struct PriceChange {
+    pub factor: f32,
+    pub reason: String,
+}
+
+/// Algorithm a seller uses to set the price of its main output once a day
+pub trait PricingStrategy {
+    fn price_change(&self, context: &PricingContext) -> PriceChange;
+}
+
+/// Pricing algorithm of a sell strategy together with its parameters
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "type")]
+pub enum Pricing {
+    RatioRule(RatioRule),
+    CostPlus(CostPlus),
+    InventoryTarget(InventoryTarget),
+    CompetitorFollowing(CompetitorFollowing),
+}
+
+impl Default for Pricing {
+    fn default() -> Self {
+        Pricing::RatioRule(RatioRule::default())
+    }
+}
+
+impl Pricing {
+    pub fn strategy(&self) -> &dyn PricingStrategy {
+        match self {
+            Pricing::RatioRule(strategy) => strategy,
+            Pricing::CostPlus(strategy) => strategy,
+            Pricing::InventoryTarget(strategy) => strategy,
+            Pricing::CompetitorFollowing(strategy) => strategy,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pricing::RatioRule(_) => "Ratio rule",
+            Pricing::CostPlus(_) => "Cost plus",
+            Pricing::InventoryTarget(_) => "Inventory target",
+            Pricing::CompetitorFollowing(_) => "Competitor following",
+        }
+    }
+
+    /// Every algorithm with its default parameters
+    pub fn choices() -> [Pricing; 4] {
+        [
+            Pricing::RatioRule(RatioRule::default()),
+            Pricing::CostPlus(CostPlus::default()),
+            Pricing::InventoryTarget(InventoryTarget::default()),
+            Pricing::CompetitorFollowing(CompetitorFollowing::default()),
+        ]
+    }
+}
+
+impl Display for Pricing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Compares items sold with items produced, lowers the price when too few are sold and raises it
+/// when almost everything sells
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct RatioRule {
+    pub lower_bound: f32,
+    pub upper_bound: f32,
+    pub days: usize,
+}
+
+impl Default for RatioRule {
+    fn default() -> Self {
+        RatioRule {
+            lower_bound: 0.5,
+            upper_bound: 0.8,
+            days: 30,
+        }
+    }
+}
+
+impl PricingStrategy for RatioRule {
+    fn price_change(&self, context: &PricingContext) -> PriceChange {
+        let sold_items = context.sold(self.days);
+        let produced_items = context.produced(self.days);
+        if produced_items == 0 {
+            return PriceChange {
+                factor: 1.0,
+                reason: "I have not produced anything yet".to_string(),
+            };
+        }
+        let strategy = context.strategy;
+        let selling_ratio = sold_items as f32 / produced_items as f32;
+        if selling_ratio < self.lower_bound {
+            let factor =
+                1.0 - (self.lower_bound - selling_ratio) * strategy.max_price_change_per_day;
+            PriceChange {
+                factor,
+                reason: format!(
+                    "I'm selling too slow! (sold {}, produced {}, ratio {:.2})",
+                    sold_items, produced_items, selling_ratio
+                ),
+            }
+        } else if selling_ratio > self.upper_bound {
+            let mut change =
+                (selling_ratio - self.upper_bound).min(1.0) * strategy.max_price_change_per_day;
+            if strategy.current_price < strategy.base_price {
+                change *= 10.0;
+            }
+            PriceChange {
+                factor: 1.0 + change,
+                reason: format!(
+                    "I'm selling too fast! (sold {}, produced {}, ratio {:.2})",
+                    sold_items, produced_items, selling_ratio
+                ),
+            }
+        } else {
+            PriceChange {
+                factor: 1.0,
+                reason: format!(
+                    "I'm selling at a right price! (sold {}, produced {}, ratio {:.2})",
+                    sold_items, produced_items, selling_ratio
+                ),
+            }
+        }
+    }
+}
+
+/// Adds a fixed markup to the production cost
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct CostPlus {
+    pub markup: f32,
+}
+
+impl Default for CostPlus {
+    fn default() -> Self {
+        CostPlus { markup: 0.2 }
+    }
+}
+
+impl PricingStrategy for CostPlus {
+    fn price_change(&self, context: &PricingContext) -> PriceChange {
+        let target = context.strategy.base_price * (1.0 + self.markup);
+        PriceChange {
+            factor: context.towards(target),
+            reason: format!(
+                "I add {:.0}% to my cost of {}",
+                100.0 * self.markup,
+                context.strategy.base_price
+            ),
+        }
+    }
+}
+
+/// Keeps stock for a number of days of sales, a growing stock pushes the price down
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct InventoryTarget {
+    pub target_days: f32,
+    /// Days of sales the daily sales rate is measured over
+    pub days: usize,
+}
+
+impl Default for InventoryTarget {
+    fn default() -> Self {
+        InventoryTarget {
+            target_days: 10.0,
+            days: 30,
+        }
+    }
+}
+
+impl PricingStrategy for InventoryTarget {
+    fn price_change(&self, context: &PricingContext) -> PriceChange {
+        let daily_sales = context.sold(self.days) as f32 / self.days.max(1) as f32;
+        let stock_days = if daily_sales > 0.0 {
+            context.in_stock as f32 / daily_sales
+        } else if context.in_stock > 0 {
+            f32::INFINITY
+        } else {
+            0.0
+        };
+        let target_days = self.target_days.max(1.0);
+        let deviation = ((target_days - stock_days) / target_days).clamp(-1.0, 1.0);
+        PriceChange {
+            factor: 1.0 + deviation * context.strategy.max_price_change_per_day,
+            reason: format!(
+                "I have {} items in stock for {:.1} days of sales, I aim for {:.1}",
+                context.in_stock, stock_days, target_days
+            ),
+        }
+    }
+}
+
+/// Follows the regional market price, slightly below it, but does not sell below cost plus the
+/// minimal markup
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct CompetitorFollowing {
+    pub undercut: f32,
+    pub min_markup: f32,
+}
+
+impl Default for CompetitorFollowing {
+    fn default() -> Self {
+        CompetitorFollowing {
+            undercut: 0.02,
+            min_markup: 0.05,
+        }
+    }
+}
+
+impl PricingStrategy for CompetitorFollowing {
+    fn price_change(&self, context: &PricingContext) -> PriceChange {
+        let floor = context.strategy.base_price * (1.0 + self.min_markup);
+        let Some(market_price) = context.market_price else {
+            return PriceChange {
+                factor: context.towards(floor),
+                reason: "There is no market price to follow yet".to_string(),
+            };
+        };
+        let target = (market_price * (1.0 - self.undercut)).max(floor);
+        PriceChange {
+            factor: context.towards(target),
+            reason: format!("Market price is {}, I aim for {}", market_price, target),
+        }
+    }
+}
@@ -0,0 +1,310 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::init::ProductionCycleTemplate;
+
+/// Items connected by production cycles, built once from the templates. An item depends on the
+/// inputs and tools of every cycle that outputs it.
+#[derive(Debug, Clone, Default)]
+pub struct RecipeGraph {
+    cycles: Vec<ProductionCycleTemplate>,
+    /// Cycles that output the item, the ones it is main output of first
+    producers: HashMap<String, Vec<usize>>,
+    /// Items needed to produce the item, tools included
+    dependencies: HashMap<String, HashSet<String>>,
+    /// Every input and tool of some cycle
+    used: HashSet<String>,
+    /// All items the inputs depend on, directly or through other cycles
+    required_inputs: HashMap<String, HashSet<String>>,
+    tiers: HashMap<String, usize>,
+    loops: Vec<Vec<String>>,
+}
+
+impl RecipeGraph {
+    pub fn new(cycles: &[ProductionCycleTemplate]) -> Self {
+        let mut graph = RecipeGraph {
+            cycles: cycles.to_vec(),
+            ..Default::default()
+        };
+        for (index, cycle) in cycles.iter().enumerate() {
+            for output in cycle.output.keys() {
+                let producers = graph.producers.entry(output.clone()).or_default();
+                if output == cycle.main_output() {
+                    producers.insert(0, index);
+                } else {
+                    producers.push(index);
+                }
+                graph
+                    .dependencies
+                    .entry(output.clone())
+                    .or_default()
+                    .extend(cycle.input.keys().chain(cycle.tools.keys()).cloned());
+            }
+            graph
+                .used
+                .extend(cycle.input.keys().chain(cycle.tools.keys()).cloned());
+        }
+        for item in graph.items() {
+            let mut required = HashSet::new();
+            graph.collect_required_inputs(&item, &mut required);
+            graph.required_inputs.insert(item.clone(), required);
+            let tier = graph.tier_of(&item, &mut vec![]);
+            graph.tiers.insert(item, tier);
+        }
+        graph.loops = graph.find_loops();
+        graph
+    }
+
+    /// Every item that is produced or needed by some cycle
+    pub fn items(&self) -> HashSet<String> {
+        self.producers
+            .keys()
+            .chain(self.used.iter())
+            .cloned()
+            .collect()
+    }
+
+    /// Items needed anywhere down the production chain of the item
+    pub fn required_inputs(&self, item: &str) -> HashSet<String> {
+        self.required_inputs.get(item).cloned().unwrap_or_default()
+    }
+
+    /// Raw materials are tier 0, every production step adds one tier
+    pub fn tier(&self, item: &str) -> usize {
+        *self.tiers.get(item).unwrap_or(&0)
+    }
+
+    /// Chains of items that need each other, like energy needed to make energy
+    pub fn loops(&self) -> &Vec<Vec<String>> {
+        &self.loops
+    }
+
+    /// Inputs and tools no cycle produces, they can only be imported
+    pub fn unreachable_items(&self) -> HashSet<String> {
+        self.used
+            .iter()
+            .filter(|item| !self.producers.contains_key(*item))
+            .cloned()
+            .collect()
+    }
+
+    /// Outputs no cycle needs, final goods for people or exports
+    pub fn unused_items(&self) -> HashSet<String> {
+        self.producers
+            .keys()
+            .filter(|item| !self.used.contains(*item))
+            .cloned()
+            .collect()
+    }
+
+    /// Amount of every raw material used up to produce one unit of the item, following the cycles
+    /// the item is main output of. Items in a loop are counted as raw to break it.
+    pub fn raw_materials(&self, item: &str) -> HashMap<String, f32> {
+        let mut raw = HashMap::new();
+        self.add_raw_materials(item, 1.0, &mut vec![], &mut raw);
+        raw
+    }
+
+    fn add_raw_materials(
+        &self,
+        item: &str,
+        amount: f32,
+        path: &mut Vec<String>,
+        raw: &mut HashMap<String, f32>,
+    ) {
+        let cycle = self
+            .producers
+            .get(item)
+            .and_then(|producers| producers.first())
+            .map(|&index| &self.cycles[index]);
+        match cycle {
+            Some(cycle) if !cycle.input.is_empty() && !path.iter().any(|i| i == item) => {
+                let produced = cycle.output[item] as f32;
+                path.push(item.to_string());
+                for (input, &count) in cycle.input.iter() {
+                    self.add_raw_materials(input, amount * count as f32 / produced, path, raw);
+                }
+                path.pop();
+            }
+            _ => *raw.entry(item.to_string()).or_insert(0.0) += amount,
+        }
+    }
+
+    fn collect_required_inputs(&self, item: &str, required: &mut HashSet<String>) {
+        for input in self.dependencies.get(item).into_iter().flatten() {
+            // byproducts can feed back into earlier steps, so visit every input once
+            if required.insert(input.clone()) {
+                self.collect_required_inputs(input, required);
+            }
+        }
+    }
+
+    fn tier_of(&self, item: &str, path: &mut Vec<String>) -> usize {
+        if path.iter().any(|i| i == item) {
+            return 0;
+        }
+        let Some(dependencies) = self.dependencies.get(item) else {
+            return 0;
+        };
+        if dependencies.is_empty() {
+            return 0;
+        }
+        path.push(item.to_string());
+        let tier = dependencies
+            .iter()
+            .map(|dependency| self.tier_of(dependency, path))
+            .max()
+            .unwrap_or(0)
+            + 1;
+        path.pop();
+        tier
+    }
+
+    /// Depth first search, every edge back into the current path closes a loop
+    fn find_loops(&self) -> Vec<Vec<String>> {
+        let mut loops = Vec::new();
+        let mut visited = HashSet::new();
+        let mut items = self.items().into_iter().collect::<Vec<_>>();
+        items.sort();
+        for item in items {
+            self.visit(&item, &mut vec![], &mut visited, &mut loops);
+        }
+        loops
+    }
+
+    fn visit(
+        &self,
+        item: &String,
+        path: &mut Vec<String>,
+        visited: &mut HashSet<String>,
+        loops: &mut Vec<Vec<String>>,
+    ) {
+        if let Some(start) = path.iter().position(|i| i == item) {
+            let mut found = path[start..].to_vec();
+            found.push(item.clone());
+            loops.push(found);
+            return;
+        }
+        if !visited.insert(item.clone()) {
+            return;
+        }
+        path.push(item.clone());
+        let mut dependencies = self
+            .dependencies
+            .get(item)
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        dependencies.sort();
+        for dependency in dependencies {
+            self.visit(dependency, path, visited, loops);
+        }
+        path.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::money::Money;
+
+    fn cycle(name: &str, input: &[(&str, u32)], output: &[(&str, u32)]) -> ProductionCycleTemplate {
+        let items = |items: &[(&str, u32)]| -> HashMap<String, u32> {
+            items
+                .iter()
+                .map(|(item, count)| (item.to_string(), *count))
+                .collect()
+        };
+        ProductionCycleTemplate {
+            name: name.to_string(),
+            input: items(input),
+            tools: HashMap::new(),
+            output: items(output),
+            main_output: None,
+            workdays_needed: 1,
+            fixed_costs: Money(0),
+        }
+    }
+
+    #[test]
+    fn chain_has_tiers_and_raw_materials() {
+        let graph = RecipeGraph::new(&[
+            cycle("smelting", &[("ore", 2)], &[("metal", 1)]),
+            cycle("forging", &[("metal", 3), ("wood", 1)], &[("hammer", 2)]),
+        ]);
+
+        assert_eq!(graph.tier("ore"), 0);
+        assert_eq!(graph.tier("metal"), 1);
+        assert_eq!(graph.tier("hammer"), 2);
+        assert_eq!(
+            graph.required_inputs("hammer"),
+            HashSet::from(["metal".to_string(), "ore".to_string(), "wood".to_string()])
+        );
+        assert_eq!(
+            graph.raw_materials("hammer"),
+            HashMap::from([("ore".to_string(), 3.0), ("wood".to_string(), 0.5)])
+        );
+        assert!(graph.loops().is_empty());
+        assert_eq!(
+            graph.unreachable_items(),
+            HashSet::from(["ore".to_string(), "wood".to_string()])
+        );
+        assert_eq!(graph.unused_items(), HashSet::from(["hammer".to_string()]));
+    }
+
+    #[test]
+    fn energy_needing_energy_is_a_loop() {
+        let graph = RecipeGraph::new(&[cycle(
+            "energy",
+            &[("energy", 1), ("coal", 1)],
+            &[("energy", 4)],
+        )]);
+
+        assert_eq!(
+            graph.loops(),
+            &vec![vec!["energy".to_string(), "energy".to_string()]]
+        );
+        assert_eq!(graph.tier("energy"), 1);
+        // energy in the loop is counted as raw to break it
+        assert_eq!(
+            graph.raw_materials("energy"),
+            HashMap::from([("coal".to_string(), 0.25), ("energy".to_string(), 0.25)])
+        );
+    }
+
+    #[test]
+    fn loop_through_several_cycles_is_found_once() {
+        let graph = RecipeGraph::new(&[
+            cycle("energy", &[("gas", 1)], &[("energy", 2)]),
+            cycle(
+                "compression",
+                &[("energy", 1), ("raw gas", 2)],
+                &[("gas", 1)],
+            ),
+        ]);
+
+        assert_eq!(
+            graph.loops(),
+            &vec![vec![
+                "energy".to_string(),
+                "gas".to_string(),
+                "energy".to_string()
+            ]]
+        );
+        assert_eq!(graph.tier("energy"), 2);
+        assert_eq!(graph.tier("gas"), 2);
+        assert_eq!(
+            graph.required_inputs("energy"),
+            HashSet::from([
+                "energy".to_string(),
+                "gas".to_string(),
+                "raw gas".to_string()
+            ])
+        );
+        assert_eq!(
+            graph.raw_materials("energy"),
+            HashMap::from([("energy".to_string(), 0.5), ("raw gas".to_string(), 1.0)])
+        );
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Display;
+
+use bevy::prelude::*;
+use rand::seq::SliceRandom;
+use serde::Deserialize;
+
+use crate::business::{BuyStrategy, Item, Manufacturer};
+use crate::logs::LogEvent;
+use crate::money::Money;
+use crate::people::Person;
+use crate::validation::{checked, loaded, read_data, DataIssue};
+use crate::Days;
+
+pub const REGIONS_PATH: &str = "data/regions.json";
+
+/// Planet or station where an entity or order is located
+#[derive(Component, Hash, Eq, PartialEq, Debug, Clone, Ord, PartialOrd)]
+pub struct Region {
+    pub(crate) name: String,
+}
+
+impl Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Route {
+    pub cost_per_item: Money,
+    pub delivery_days: usize,
+}
+
+impl Route {
+    pub const LOCAL: Route = Route {
+        cost_per_item: Money(0),
+        delivery_days: 0,
+    };
+}
+
+#[derive(Deserialize)]
+struct RouteTemplate {
+    between: (String, String),
+    #[serde(flatten)]
+    route: Route,
+}
+
+#[derive(Deserialize)]
+struct RegionsTemplate {
+    regions: Vec<String>,
+    routes: Vec<RouteTemplate>,
+}
+
+#[derive(Resource, Default, Debug)]
+pub struct Regions {
+    pub regions: Vec<Region>,
+    routes: HashMap<(Region, Region), Route>,
+}
+
+impl Regions {
+    pub fn load(&mut self) {
+        *self = loaded(Regions::try_load());
+        info!(
+            "Loaded {} regions with {} routes",
+            self.regions.len(),
+            self.routes.len() / 2
+        );
+    }
+
+    pub fn try_load() -> Result<Regions, Vec<DataIssue>> {
+        let template: RegionsTemplate = read_data(REGIONS_PATH)?;
+        let mut regions = Regions {
+            regions: template
+                .regions
+                .into_iter()
+                .map(|name| Region { name })
+                .collect(),
+            routes: HashMap::new(),
+        };
+        let mut issues = vec![];
+        for route in template.routes {
+            let (from, to) = route.between;
+            let unknown = [&from, &to]
+                .into_iter()
+                .filter(|name| !regions.regions.iter().any(|region| region.name == **name))
+                .map(|name| {
+                    DataIssue::new(
+                        REGIONS_PATH,
+                        "routes",
+                        format!(
+                            "route between {} and {} uses unknown region {}",
+                            from, to, name
+                        ),
+                    )
+                })
+                .collect::<Vec<_>>();
+            if !unknown.is_empty() {
+                issues.extend(unknown);
+                continue;
+            }
+            let (from, to) = (Region { name: from }, Region { name: to });
+            regions
+                .routes
+                .insert((from.clone(), to.clone()), route.route);
+            regions.routes.insert((to, from), route.route);
+        }
+        checked(regions, issues)
+    }
+
+    /// Route between two regions, `None` if goods can't be shipped between them
+    pub fn route(&self, from: &Region, to: &Region) -> Option<Route> {
+        if from == to {
+            Some(Route::LOCAL)
+        } else {
+            self.routes.get(&(from.clone(), to.clone())).copied()
+        }
+    }
+
+    pub fn random(&self) -> Region {
+        self.regions
+            .choose(&mut rand::thread_rng())
+            .cloned()
+            .expect("At least one region must be defined")
+    }
+
+    pub fn find(&self, name: &str) -> Option<Region> {
+        self.regions
+            .iter()
+            .find(|region| region.name == name)
+            .cloned()
+    }
+}
+
+/// Item bought in another region which is on its way to the buyer
+#[derive(Component, Debug)]
+pub struct Shipment {
+    pub(crate) item: Item,
+    pub(crate) receiver: Entity,
+    pub(crate) arrival: usize,
+    /// Shipments bought through a `BuyOrder` reduce the receiver's outstanding orders on arrival
+    pub(crate) from_buy_order: bool,
+}
+
+pub fn deliver_shipments(
+    shipments: Query<(Entity, &Shipment)>,
+    mut people: Query<&mut Person>,
+    mut manufacturers: Query<(&mut Manufacturer, Option<&mut BuyStrategy>)>,
+    mut logs: EventWriter<LogEvent>,
+    mut commands: Commands,
+    date: Res<Days>,
+) {
+    for (entity, shipment) in shipments.iter() {
+        if shipment.arrival > date.days {
+            continue;
+        }
+        let item_type = shipment.item.item_type;
+        if let Ok(mut person) = people.get_mut(shipment.receiver) {
+            person
+                .assets
+                .items
+                .entry(item_type)
+                .or_default()
+                .push(shipment.item.clone());
+        } else if let Ok((mut manufacturer, strategy)) = manufacturers.get_mut(shipment.receiver) {
+            manufacturer
+                .assets
+                .items
+                .entry(item_type)
+                .or_default()
+                .push(shipment.item.clone());
+            if let Some(mut strategy) = strategy.filter(|_| shipment.from_buy_order) {
+                if let Some(outstanding) = strategy.outstanding_orders.get_mut(&item_type) {
+                    *outstanding = outstanding.saturating_sub(1);
+                }
+            }
+        } else {
+            debug!(
+                "Receiver of {} no longer exists, shipment is lost",
+                item_type
+            );
+            commands.entity(entity).despawn();
+            continue;
+        }
+        logs.send(LogEvent::Generic {
+            text: format!("My shipment of {} has arrived", item_type),
+            entity: shipment.receiver,
+        });
+        commands.entity(entity).despawn();
+    }
+}
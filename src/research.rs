@@ -0,0 +1,395 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+use either::Right;
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::bankruptcy::Liquidation;
+use crate::business::{Manufacturer, ProductionCycle};
+use crate::config::Config;
+use crate::government::Government;
+use crate::init::{ProductionCycleTemplate, Templates};
+use crate::logs::LogEvent;
+use crate::money::Money;
+use crate::validation::{checked, loaded, parse_entries, read_data, DataIssue};
+use crate::wallet::{TradeSide, Transaction, Wallet};
+use crate::Days;
+
+pub const TECHNOLOGIES_PATH: &str = "data/technologies.json";
+
+/// Knowledge a business can pay for and work on, unlocking or improving production cycles
+#[derive(Debug, Clone, Deserialize)]
+pub struct Technology {
+    /// Paid to the government when the research starts
+    pub cost: Money,
+    /// Work the staff has to put into the research project
+    pub workdays: u32,
+    /// Technologies that must be known before this one can be researched
+    #[serde(default)]
+    pub requires: Vec<String>,
+    /// Production cycles nobody can run until they know this technology
+    #[serde(default)]
+    pub unlocks: Vec<String>,
+    /// Production cycles that get cheaper to run, by name
+    #[serde(default)]
+    pub improves: HashMap<String, Improvement>,
+}
+
+/// Multipliers applied to a production cycle, inputs and workdays are rounded up, outputs down
+#[derive(Debug, Clone, Deserialize)]
+pub struct Improvement {
+    #[serde(default = "no_change")]
+    pub workdays_factor: f32,
+    #[serde(default = "no_change")]
+    pub input_factor: f32,
+    #[serde(default = "no_change")]
+    pub output_factor: f32,
+}
+
+fn no_change() -> f32 {
+    1.0
+}
+
+impl Improvement {
+    /// Both improvements at once, the factors multiply
+    fn and(&self, other: &Improvement) -> Improvement {
+        Improvement {
+            workdays_factor: self.workdays_factor * other.workdays_factor,
+            input_factor: self.input_factor * other.input_factor,
+            output_factor: self.output_factor * other.output_factor,
+        }
+    }
+
+    pub fn apply(&self, cycle: &mut ProductionCycle) {
+        cycle.workdays_needed =
+            ((cycle.workdays_needed as f32 * self.workdays_factor).ceil() as u32).max(1);
+        cycle.workdays_left = cycle.workdays_left.min(cycle.workdays_needed as f32);
+        for count in cycle.input.values_mut() {
+            *count = ((*count as f32 * self.input_factor).ceil() as u32).max(1);
+        }
+        for count in cycle.output.values_mut() {
+            *count = ((*count as f32 * self.output_factor).floor() as u32).max(1);
+        }
+    }
+}
+
+/// Technologies known by a business and the project it is working on
+#[derive(Debug, Default, Clone)]
+pub struct Research {
+    pub known: HashSet<String>,
+    pub project: Option<ResearchProject>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ResearchProject {
+    pub technology: String,
+    pub workdays_left: f32,
+}
+
+#[derive(Resource, Default, Debug)]
+pub struct Technologies {
+    pub technologies: HashMap<String, Technology>,
+}
+
+impl Technologies {
+    pub fn load(&mut self) {
+        *self = loaded(Technologies::try_load());
+        info!("Loaded {} technologies", self.technologies.len());
+    }
+
+    pub fn try_load() -> Result<Technologies, Vec<DataIssue>> {
+        let technologies: HashMap<String, serde_json::Value> = read_data(TECHNOLOGIES_PATH)?;
+        let mut issues = vec![];
+        let technologies: HashMap<String, Technology> =
+            parse_entries(TECHNOLOGIES_PATH, technologies, &mut issues)
+                .into_iter()
+                .collect();
+        for (name, technology) in technologies.iter() {
+            for required in technology.requires.iter() {
+                if !technologies.contains_key(required) {
+                    issues.push(DataIssue::new(
+                        TECHNOLOGIES_PATH,
+                        name,
+                        format!("requires unknown technology {}", required),
+                    ));
+                }
+            }
+        }
+        checked(Technologies { technologies }, issues)
+    }
+
+    /// Cycle is available when no technology locks it or one of the unlocking ones is known
+    pub fn is_available(&self, cycle_name: &str, known: &HashSet<String>) -> bool {
+        let mut unlocked_by = self
+            .technologies
+            .iter()
+            .filter(|(_, technology)| technology.unlocks.iter().any(|c| c == cycle_name))
+            .peekable();
+        unlocked_by.peek().is_none() || unlocked_by.any(|(name, _)| known.contains(name))
+    }
+
+    /// Templates a business knowing given technologies can choose from, templates with unknown
+    /// items are never offered
+    pub fn available_cycles(
+        &self,
+        cycles: &[ProductionCycleTemplate],
+        known: &HashSet<String>,
+    ) -> Vec<ProductionCycleTemplate> {
+        cycles
+            .iter()
+            .filter(|cycle| self.is_available(&cycle.name, known))
+            .filter(|cycle| cycle.unknown_items().next().is_none())
+            .cloned()
+            .collect()
+    }
+
+    /// Production cycle of the template with all known improvements applied, None when the
+    /// template uses unknown items
+    pub fn production_cycle(
+        &self,
+        template: &ProductionCycleTemplate,
+        known: &HashSet<String>,
+    ) -> Option<ProductionCycle> {
+        let (_, mut cycle) = template.to_production_cycle()?;
+        let mut names = known.iter().collect::<Vec<_>>();
+        names.sort();
+        // factors are combined first and rounded once, the result does not depend on the order
+        // the technologies were learned in
+        let improvement = names
+            .into_iter()
+            .filter_map(|name| self.technologies.get(name)?.improves.get(&cycle.name))
+            .fold(None, |combined: Option<Improvement>, improvement| {
+                Some(combined.map_or(improvement.clone(), |combined| combined.and(improvement)))
+            });
+        if let Some(improvement) = improvement {
+            improvement.apply(&mut cycle);
+        }
+        Some(cycle)
+    }
+
+    fn can_research(&self, name: &str, known: &HashSet<String>) -> bool {
+        !known.contains(name)
+            && self.technologies[name]
+                .requires
+                .iter()
+                .all(|required| known.contains(required))
+    }
+
+    /// Learns the technology and rebuilds the lines that run cycles it improves from their
+    /// templates with every known improvement
+    fn learn(
+        &self,
+        manufacturer: &mut Manufacturer,
+        name: &str,
+        templates: &[ProductionCycleTemplate],
+    ) {
+        manufacturer.research.known.insert(name.to_string());
+        if manufacturer
+            .research
+            .project
+            .as_ref()
+            .is_some_and(|project| project.technology == name)
+        {
+            manufacturer.research.project = None;
+        }
+        let Some(technology) = self.technologies.get(name) else {
+            return;
+        };
+        let known = &manufacturer.research.known;
+        for line in manufacturer.production_lines.iter_mut() {
+            if !technology
+                .improves
+                .contains_key(&line.production_cycle.name)
+            {
+                continue;
+            }
+            let Some(mut cycle) = templates
+                .iter()
+                .find(|template| template.name == line.production_cycle.name)
+                .and_then(|template| self.production_cycle(template, known))
+            else {
+                continue;
+            };
+            cycle.workdays_left = line
+                .production_cycle
+                .workdays_left
+                .min(cycle.workdays_needed as f32);
+            line.production_cycle = cycle;
+        }
+    }
+
+    /// Technologies that can never be researched, their requirements loop back to them or to a
+    /// technology that needs them
+    pub fn unreachable(&self) -> Vec<&String> {
+        let mut reachable: HashSet<&String> = HashSet::new();
+        loop {
+            let next = self
+                .technologies
+                .iter()
+                .filter(|(name, technology)| {
+                    !reachable.contains(name)
+                        && technology
+                            .requires
+                            .iter()
+                            .all(|required| reachable.contains(required))
+                })
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>();
+            if next.is_empty() {
+                break;
+            }
+            reachable.extend(next);
+        }
+        let mut unreachable = self
+            .technologies
+            .keys()
+            .filter(|name| !reachable.contains(name))
+            .collect::<Vec<_>>();
+        unreachable.sort();
+        unreachable
+    }
+
+    /// Cheapest technology the business can research, improving own lines comes first
+    fn next_project(&self, manufacturer: &Manufacturer) -> Option<&String> {
+        let known = &manufacturer.research.known;
+        let improves_own_line = |technology: &Technology| {
+            manufacturer.production_lines.iter().any(|line| {
+                technology
+                    .improves
+                    .contains_key(&line.production_cycle.name)
+            })
+        };
+        self.technologies
+            .iter()
+            .filter(|(name, _)| self.can_research(name, known))
+            .min_by_key(|(_, technology)| (!improves_own_line(technology), technology.cost))
+            .map(|(name, _)| name)
+    }
+}
+
+/// Once a month profitable businesses without a research project pay for a new one when they can
+/// keep the reserve. Part of their staff works on it instead of production until it's done.
+pub fn start_research(
+    mut manufacturers: Query<(Entity, &mut Manufacturer), Without<Liquidation>>,
+    governments: Query<Entity, With<Government>>,
+    mut wallets: Query<&mut Wallet>,
+    technologies: Res<Technologies>,
+    mut logs: EventWriter<LogEvent>,
+    date: Res<Days>,
+    config: Res<Config>,
+) {
+    let government = governments
+        .iter()
+        .next()
+        .expect("There should be a government");
+    for (entity, mut manufacturer) in manufacturers.iter_mut() {
+        if manufacturer.research.project.is_some() {
+            continue;
+        }
+        let Some(name) = technologies.next_project(&manufacturer) else {
+            continue;
+        };
+        let technology = &technologies.technologies[name];
+        let Ok([mut wallet, mut government_wallet]) = wallets.get_many_mut([entity, government])
+        else {
+            continue;
+        };
+        let profitable = matches!(
+            wallet.calculate_total_change(date.days, 30),
+            Right(profit) if profit > Money(0)
+        );
+        if !profitable || wallet.money() < technology.cost + config.business.research.reserve.value
+        {
+            continue;
+        }
+        wallet
+            .transaction(
+                &mut government_wallet,
+                &Transaction::Transfer {
+                    side: TradeSide::Pay,
+                    sender: entity,
+                    receiver: government,
+                    amount: technology.cost,
+                    date: date.days,
+                },
+                &mut logs,
+            )
+            .unwrap();
+        manufacturer.research.project = Some(ResearchProject {
+            technology: name.clone(),
+            workdays_left: technology.workdays as f32,
+        });
+        logs.send(LogEvent::Generic {
+            text: format!("I paid {} to start researching {}", technology.cost, name),
+            entity,
+        });
+    }
+}
+
+/// Finished research projects turn into known technology
+pub fn complete_research(
+    mut manufacturers: Query<(Entity, &mut Manufacturer)>,
+    technologies: Res<Technologies>,
+    templates: Res<Templates>,
+    mut logs: EventWriter<LogEvent>,
+) {
+    for (entity, mut manufacturer) in manufacturers.iter_mut() {
+        let finished = manufacturer
+            .research
+            .project
+            .as_ref()
+            .is_some_and(|project| project.workdays_left <= 0.0);
+        if !finished {
+            continue;
+        }
+        let project = manufacturer.research.project.take().unwrap();
+        technologies.learn(
+            &mut manufacturer,
+            &project.technology,
+            &templates.production_cycles,
+        );
+        logs.send(LogEvent::Generic {
+            text: format!("We finished research of {}!", project.technology),
+            entity,
+        });
+    }
+}
+
+/// Every month competitors may pick up technologies known by others, the more businesses know a
+/// technology the more likely it spreads
+pub fn spread_technology(
+    mut manufacturers: Query<(Entity, &mut Manufacturer)>,
+    technologies: Res<Technologies>,
+    templates: Res<Templates>,
+    mut logs: EventWriter<LogEvent>,
+    config: Res<Config>,
+) {
+    let total = manufacturers.iter().count();
+    if total == 0 {
+        return;
+    }
+    let mut known_by: HashMap<String, usize> = HashMap::new();
+    for (_, manufacturer) in manufacturers.iter() {
+        for name in manufacturer.research.known.iter() {
+            *known_by.entry(name.clone()).or_insert(0) += 1;
+        }
+    }
+    let mut rng = rand::thread_rng();
+    for (entity, mut manufacturer) in manufacturers.iter_mut() {
+        for (name, count) in known_by.iter() {
+            if !technologies.can_research(name, &manufacturer.research.known) {
+                continue;
+            }
+            let chance =
+                config.business.research.spread_chance.value * *count as f32 / total as f32;
+            if rng.gen::<f32>() < chance {
+                technologies.learn(&mut manufacturer, name, &templates.production_cycles);
+                logs.send(LogEvent::Generic {
+                    text: format!("We picked up {} from our competitors", name),
+                    entity,
+                });
+            }
+        }
+    }
+}
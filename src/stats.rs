@@ -1,15 +1,25 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt;
 
-use bevy::prelude::{debug, Query, Res, ResMut, Resource};
+use bevy::prelude::{debug, Query, Res, ResMut, Resource, With};
 
-use crate::business::{ItemType, SellOrder};
+use crate::business::{Manufacturer, SellOrder};
+use crate::init::Templates;
+use crate::items::ItemId;
 use crate::money::Money;
+use crate::people::{Needs, Person};
+use crate::regions::Region;
+use crate::wallet::Wallet;
 use crate::Days;
 
+pub const DAYS_IN_MONTH: usize = 30;
+pub const DAYS_IN_YEAR: usize = 12 * DAYS_IN_MONTH;
+/// Purchases older than this many days barely affect the basket weights
+const BASKET_WEIGHT_DAYS: f64 = 30.0;
+
 #[derive(Debug)]
 pub struct PriceStats {
-    pub item_type: ItemType,
+    pub item_type: ItemId,
     pub min: Money,
     pub max: Money,
     pub median: Money,
@@ -22,7 +32,7 @@ pub struct PriceStats {
 
 impl fmt::Display for PriceStats {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        writeln!(f, "Price for {}: {}\n", self.item_type.name, self.avg)?;
+        writeln!(f, "Price for {}: {}\n", self.item_type.name(), self.avg)?;
         writeln!(f, "📉  MIN Price: {}", self.min)?;
         writeln!(f, "🔳  25th Percentile Price: {}", self.p25)?;
         writeln!(f, "🎯  MEDIAN Price: {}", self.median)?;
@@ -34,27 +44,8 @@ impl fmt::Display for PriceStats {
     }
 }
 
-#[derive(Resource, Default)]
-pub struct PriceHistory {
-    pub prices: HashMap<ItemType, Vec<PriceStats>>,
-}
-
-pub fn add_sell_orders_to_history(
-    mut history: ResMut<PriceHistory>,
-    days: Res<Days>,
-    sell_orders: Query<&SellOrder>,
-) {
-    let mut grouped_orders = BTreeMap::new();
-    debug!("Adding sell orders to history");
-
-    for sell_order in sell_orders.iter() {
-        grouped_orders
-            .entry(sell_order.item_type.clone())
-            .or_insert_with(Vec::new)
-            .push(sell_order);
-    }
-    for (item_type, sell_order) in grouped_orders.iter() {
-        let mut prices = sell_order.iter().map(|o| o.price).collect::<Vec<_>>();
+impl PriceStats {
+    fn from_prices(item_type: &ItemId, day: usize, mut prices: Vec<Money>) -> Self {
         prices.sort_unstable();
 
         let min = *prices.first().unwrap();
@@ -65,9 +56,9 @@ pub fn add_sell_orders_to_history(
         let len = prices.len();
         let avg = prices.iter().sum::<Money>() / len;
 
-        let stats = PriceStats {
-            item_type: item_type.clone(),
-            day: days.days,
+        PriceStats {
+            item_type: *item_type,
+            day,
             min,
             max,
             median,
@@ -75,11 +66,202 @@ pub fn add_sell_orders_to_history(
             p75,
             avg,
             total_orders: len,
-        };
-        history
-            .prices
-            .entry(item_type.clone())
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct PriceHistory {
+    pub prices: HashMap<ItemId, Vec<PriceStats>>,
+    pub regional_prices: HashMap<Region, HashMap<ItemId, Vec<PriceStats>>>,
+}
+
+impl PriceHistory {
+    /// Last median price in the region, falls back to the global price if the item was never sold there
+    pub fn last_median(&self, region: &Region, item_type: &ItemId) -> Option<Money> {
+        self.regional_prices
+            .get(region)
+            .and_then(|prices| prices.get(item_type))
+            .or_else(|| self.prices.get(item_type))
+            .and_then(|prices| prices.last())
+            .map(|stats| stats.median)
+    }
+}
+
+pub fn add_sell_orders_to_history(
+    mut history: ResMut<PriceHistory>,
+    days: Res<Days>,
+    sell_orders: Query<&SellOrder>,
+) {
+    let mut grouped_orders = BTreeMap::new();
+    let mut grouped_regional_orders = BTreeMap::new();
+    debug!("Adding sell orders to history");
+
+    for sell_order in sell_orders.iter() {
+        grouped_orders
+            .entry(sell_order.item_type)
+            .or_insert_with(Vec::new)
+            .push(sell_order.price);
+        grouped_regional_orders
+            .entry((sell_order.region.clone(), sell_order.item_type))
             .or_insert_with(Vec::new)
+            .push(sell_order.price);
+    }
+    for (item_type, prices) in grouped_orders {
+        let stats = PriceStats::from_prices(&item_type, days.days, prices);
+        history.prices.entry(item_type).or_default().push(stats);
+    }
+    for ((region, item_type), prices) in grouped_regional_orders {
+        let stats = PriceStats::from_prices(&item_type, days.days, prices);
+        history
+            .regional_prices
+            .entry(region)
+            .or_default()
+            .entry(item_type)
+            .or_default()
             .push(stats);
     }
 }
+
+/// Chained price index of a basket of goods, weighted by recently bought quantities.
+/// Starts at 100 on the first day any item of the basket has a price.
+#[derive(Debug, Default)]
+pub struct PriceIndex {
+    weights: HashMap<ItemId, f64>,
+    last_prices: HashMap<ItemId, Money>,
+    pub values: Vec<(usize, f64)>,
+}
+
+impl PriceIndex {
+    fn update(
+        &mut self,
+        basket: &HashSet<ItemId>,
+        bought: &HashMap<ItemId, usize>,
+        prices: &HashMap<ItemId, Money>,
+        day: usize,
+    ) {
+        for weight in self.weights.values_mut() {
+            *weight *= 1.0 - 1.0 / BASKET_WEIGHT_DAYS;
+        }
+        for (item_type, &amount) in bought.iter().filter(|(i, _)| basket.contains(i)) {
+            *self.weights.entry(*item_type).or_default() += amount as f64;
+        }
+        let comparable = basket
+            .iter()
+            .filter(|item_type| {
+                prices.contains_key(item_type) && self.last_prices.contains_key(item_type)
+            })
+            .collect::<Vec<_>>();
+        let total_weight = comparable
+            .iter()
+            .map(|item_type| self.weights.get(item_type).unwrap_or(&0.0))
+            .sum::<f64>();
+        // use equal weights until anything from the basket is bought
+        let weight = |item_type: &ItemId| {
+            if total_weight > 0.0 {
+                *self.weights.get(item_type).unwrap_or(&0.0)
+            } else {
+                1.0
+            }
+        };
+        let current_cost = comparable
+            .iter()
+            .map(|item_type| weight(item_type) * prices[*item_type].as_f64())
+            .sum::<f64>();
+        let previous_cost = comparable
+            .iter()
+            .map(|item_type| weight(item_type) * self.last_prices[*item_type].as_f64())
+            .sum::<f64>();
+        let last_value = self.values.last().map(|(_, value)| *value);
+        match last_value {
+            Some(value) if previous_cost > 0.0 => self
+                .values
+                .push((day, value * current_cost / previous_cost)),
+            Some(value) => self.values.push((day, value)),
+            None if basket
+                .iter()
+                .any(|item_type| prices.contains_key(item_type)) =>
+            {
+                self.values.push((day, 100.0))
+            }
+            None => {}
+        }
+        for item_type in basket {
+            if let Some(price) = prices.get(item_type) {
+                self.last_prices.insert(*item_type, *price);
+            }
+        }
+    }
+
+    pub fn last(&self) -> Option<f64> {
+        self.values.last().map(|(_, value)| *value)
+    }
+
+    /// Relative change of the index over the last `days`, `None` if the index is younger than that
+    pub fn inflation(&self, days: usize) -> Option<f64> {
+        let (day, value) = *self.values.last()?;
+        let (_, past_value) = self
+            .values
+            .iter()
+            .rev()
+            .find(|(past_day, _)| *past_day + days <= day)?;
+        Some(value / past_value - 1.0)
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct PriceIndices {
+    /// Consumer price index of goods satisfying needs, weighted by household purchases
+    pub cpi: PriceIndex,
+    /// Producer price index of goods used as production inputs, weighted by manufacturer purchases
+    pub ppi: PriceIndex,
+}
+
+pub fn update_price_indices(
+    mut indices: ResMut<PriceIndices>,
+    history: Res<PriceHistory>,
+    needs: Res<Needs>,
+    templates: Res<Templates>,
+    households: Query<&Wallet, With<Person>>,
+    manufacturers: Query<&Wallet, With<Manufacturer>>,
+    days: Res<Days>,
+) {
+    let consumer_goods = needs
+        .needs
+        .values()
+        .flat_map(|need| need.satisfied_by.keys().cloned())
+        .collect::<HashSet<_>>();
+    let intermediate_goods = templates
+        .production_cycles
+        .iter()
+        .flat_map(|cycle| cycle.input.keys())
+        .filter_map(|name| ItemId::get(name))
+        .collect::<HashSet<_>>();
+    let prices = history
+        .prices
+        .iter()
+        .filter_map(|(item_type, stats)| {
+            stats
+                .last()
+                .filter(|stats| stats.day == days.days)
+                .map(|stats| (*item_type, stats.median))
+        })
+        .collect::<HashMap<_, _>>();
+    let bought_by = |wallets: Vec<&Wallet>| {
+        let mut bought: HashMap<ItemId, usize> = HashMap::new();
+        for wallet in wallets {
+            for (item_type, amount) in wallet.get_bought_items(days.days, 0) {
+                *bought.entry(item_type).or_default() += amount;
+            }
+        }
+        bought
+    };
+    let household_purchases = bought_by(households.iter().collect());
+    let producer_purchases = bought_by(manufacturers.iter().collect());
+    indices
+        .cpi
+        .update(&consumer_goods, &household_purchases, &prices, days.days);
+    indices
+        .ppi
+        .update(&intermediate_goods, &producer_purchases, &prices, days.days);
+}
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::business::Inventory;
+use crate::items::{ItemId, ITEMS_PATH};
+use crate::money::Money;
+use crate::validation::{checked, loaded, parse_entries, read_data, DataIssue};
+
+pub const TOOLS_PATH: &str = "data/tools.json";
+
+/// Item that is not consumed by a production cycle but must be present for it to run
+#[derive(Debug, Clone, Deserialize)]
+pub struct Tool {
+    /// Condition lost in every cycle the tool is used in, tool is scrapped at 0
+    pub wear_per_cycle: f32,
+    /// Items consumed to bring the tool back to full condition
+    #[serde(default)]
+    pub repair: HashMap<ItemId, u32>,
+}
+
+#[derive(Resource, Default, Debug)]
+pub struct Tools {
+    pub tools: HashMap<ItemId, Tool>,
+}
+
+impl Tools {
+    pub fn load(&mut self) {
+        *self = loaded(Tools::try_load());
+        info!("Loaded {} tools", self.tools.len());
+    }
+
+    pub fn try_load() -> Result<Tools, Vec<DataIssue>> {
+        let tools: HashMap<String, serde_json::Value> = read_data(TOOLS_PATH)?;
+        let mut issues = vec![];
+        let mut loaded_tools = Tools::default();
+        for (name, tool) in parse_entries::<Tool>(TOOLS_PATH, tools, &mut issues) {
+            if !(0.0..=1.0).contains(&tool.wear_per_cycle) {
+                issues.push(DataIssue::new(
+                    TOOLS_PATH,
+                    &name,
+                    "wear per cycle must be between 0 and 1".to_string(),
+                ));
+            }
+            match ItemId::get(&name) {
+                Some(item_type) => {
+                    loaded_tools.tools.insert(item_type, tool);
+                }
+                None => issues.push(DataIssue::new(
+                    TOOLS_PATH,
+                    &name,
+                    format!("item is not defined in {}", ITEMS_PATH),
+                )),
+            }
+        }
+        checked(loaded_tools, issues)
+    }
+
+    /// Item is used to repair some tool
+    pub fn repairs_with(&self, item_type: &ItemId) -> bool {
+        self.tools
+            .values()
+            .any(|tool| tool.repair.contains_key(item_type))
+    }
+
+    pub fn is_tool(&self, item_type: &str) -> bool {
+        ItemId::get(item_type).is_some_and(|item_type| self.tools.contains_key(&item_type))
+    }
+
+    /// Items needed to repair every worn tool in the inventory
+    pub fn repair_demand(&self, assets: &Inventory, repair_below: f32) -> HashMap<ItemId, u32> {
+        let mut demand = HashMap::new();
+        for (item_type, items) in assets.items.iter() {
+            let worn = items
+                .iter()
+                .filter(|item| item.condition < repair_below)
+                .count() as u32;
+            let Some(tool) = self.tools.get(item_type) else {
+                continue;
+            };
+            if worn == 0 {
+                continue;
+            }
+            for (repair_item, count) in tool.repair.iter() {
+                *demand.entry(*repair_item).or_insert(0) += count * worn;
+            }
+        }
+        demand
+    }
+
+    /// Repairs worn tools for which all repair items are in the inventory
+    pub fn repair(&self, assets: &mut Inventory, repair_below: f32) {
+        for (item_type, tool) in self.tools.iter() {
+            repair_worn_items(assets, item_type, &tool.repair, repair_below);
+        }
+    }
+}
+
+/// Brings worn items of given type back to full condition as long as all repair items are in the
+/// inventory. Cost of the used items is added to the value of the repaired item, so a tool is
+/// depreciated by it in later cycles.
+pub fn repair_worn_items(
+    assets: &mut Inventory,
+    item_type: &ItemId,
+    repair_items: &HashMap<ItemId, u32>,
+    repair_below: f32,
+) {
+    if repair_items.is_empty() {
+        return;
+    }
+    let worn = assets.items.get(item_type).map_or(0, |items| {
+        items
+            .iter()
+            .filter(|item| item.condition < repair_below)
+            .count()
+    });
+    for _ in 0..worn {
+        let has_repair_items = repair_items.iter().all(|(repair_item, &count)| {
+            assets.items.get(repair_item).map_or(0, |items| items.len()) >= count as usize
+        });
+        if !has_repair_items {
+            return;
+        }
+        let mut repair_cost = Money(0);
+        for (repair_item, &count) in repair_items.iter() {
+            repair_cost += assets
+                .items
+                .get_mut(repair_item)
+                .unwrap()
+                .drain(..count as usize)
+                .map(|item| item.buy_cost)
+                .sum::<Money>();
+        }
+        let item = assets
+            .items
+            .get_mut(item_type)
+            .unwrap()
+            .iter_mut()
+            .find(|item| item.condition < repair_below)
+            .unwrap();
+        item.condition = 1.0;
+        item.buy_cost += repair_cost;
+        debug!("Repaired {} for {}", item_type, repair_cost);
+    }
+}
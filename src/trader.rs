@@ -0,0 +1,206 @@
+use bevy::prelude::*;
+
+use macros::measured;
+
+use crate::business::{Item, SellOrder, SellStrategy};
+use crate::config::Config;
+use crate::items::ItemId;
+use crate::logs::LogEvent;
+use crate::money::Money;
+use crate::regions::Region;
+use crate::stats::PriceHistory;
+use crate::ui::debug::Performance;
+use crate::wallet::{TradeSide, Transaction, Wallet};
+use crate::Days;
+
+/// Wholesale trader that buys items of a single type below the market price, keeps them
+/// in stock and relists them later with its own `SellStrategy`.
+#[derive(Component, Debug)]
+pub struct Trader {
+    pub(crate) item_type: ItemId,
+    pub(crate) stock: Vec<Item>,
+    /// Fraction of the trader's capital (money + stock at cost) that may be held in stock
+    pub(crate) risk_appetite: f32,
+}
+
+impl Trader {
+    pub fn new(item_type: ItemId, risk_appetite: f32) -> Self {
+        Self {
+            item_type,
+            stock: vec![],
+            risk_appetite,
+        }
+    }
+
+    /// Items held by the trader, both in stock and already listed on the market
+    fn held_items<'a>(
+        &'a self,
+        trader: Entity,
+        sell_orders: impl Iterator<Item = &'a SellOrder>,
+    ) -> Vec<&'a Item> {
+        sell_orders
+            .filter(|sell_order| sell_order.seller == trader)
+            .flat_map(|sell_order| sell_order.items.iter())
+            .chain(self.stock.iter())
+            .collect()
+    }
+}
+
+#[derive(Bundle)]
+pub struct TraderBundle {
+    pub name: Name,
+    pub trader: Trader,
+    pub sell_strategy: SellStrategy,
+    pub wallet: Wallet,
+    pub region: Region,
+}
+
+#[allow(clippy::too_many_arguments)]
+#[measured]
+pub fn buy_cheap_items(
+    mut traders: Query<(Entity, &mut Trader, &Region)>,
+    mut sell_orders: Query<(Entity, &mut SellOrder)>,
+    mut wallets: Query<&mut Wallet>,
+    price_history: Res<PriceHistory>,
+    mut logs: EventWriter<LogEvent>,
+    mut commands: Commands,
+    date: Res<Days>,
+    config: Res<Config>,
+) {
+    for (trader_entity, mut trader, region) in traders.iter_mut() {
+        let Some(market_price) = price_history.last_median(region, &trader.item_type) else {
+            continue;
+        };
+        let max_buy_price = market_price * (1.0 - config.traders.required_discount.value);
+        let mut cheap_orders = sell_orders
+            .iter()
+            .filter(|(_, sell_order)| {
+                sell_order.item_type == trader.item_type
+                    && sell_order.region == *region
+                    && sell_order.seller != trader_entity
+                    && sell_order.price <= max_buy_price
+                    && !sell_order.items.is_empty()
+            })
+            .map(|(entity, sell_order)| (entity, sell_order.price))
+            .collect::<Vec<_>>();
+        if cheap_orders.is_empty() {
+            continue;
+        }
+        cheap_orders.sort_by_key(|(_, price)| *price);
+        let mut stock_value = trader
+            .held_items(trader_entity, sell_orders.iter().map(|(_, order)| order))
+            .iter()
+            .map(|item| item.buy_cost)
+            .sum::<Money>();
+
+        let mut bought = 0;
+        let mut spent = Money(0);
+        for (sell_order_entity, _) in cheap_orders {
+            let (_, mut sell_order) = sell_orders.get_mut(sell_order_entity).unwrap();
+            while !sell_order.items.is_empty() {
+                let Ok([mut trader_wallet, mut seller_wallet]) =
+                    wallets.get_many_mut([trader_entity, sell_order.seller])
+                else {
+                    break;
+                };
+                let capital = trader_wallet.money() + stock_value;
+                if stock_value + sell_order.price > capital * trader.risk_appetite {
+                    break;
+                }
+                let mut item = sell_order.items.last().unwrap().clone();
+                item.buy_cost = sell_order.price;
+                let transaction = Transaction::Trade {
+                    side: TradeSide::Pay,
+                    buyer: trader_entity,
+                    seller: sell_order.seller,
+                    item: item.clone(),
+                    item_type: sell_order.item_type,
+                    price: sell_order.price,
+                    date: date.days,
+                };
+                if trader_wallet
+                    .transaction(&mut seller_wallet, &transaction, &mut logs)
+                    .is_err()
+                {
+                    break;
+                }
+                sell_order.items.pop();
+                spent += sell_order.price;
+                stock_value += sell_order.price;
+                bought += 1;
+                trader.stock.push(item);
+            }
+            if sell_order.items.is_empty() {
+                commands.entity(sell_order_entity).despawn();
+            }
+        }
+        if bought > 0 {
+            logs.send(LogEvent::Generic {
+                text: format!(
+                    "I bought {} {} for {} in total, market price is {}",
+                    bought, trader.item_type, spent, market_price
+                ),
+                entity: trader_entity,
+            });
+        }
+    }
+}
+
+pub fn update_trader_prices(
+    mut traders: Query<(Entity, &Trader, &mut SellStrategy, &Region)>,
+    sell_orders: Query<&SellOrder>,
+    price_history: Res<PriceHistory>,
+    mut logs: EventWriter<LogEvent>,
+    config: Res<Config>,
+) {
+    for (entity, trader, mut sell_strategy, region) in traders.iter_mut() {
+        let held_items = trader.held_items(entity, sell_orders.iter());
+        if held_items.is_empty() {
+            continue;
+        }
+        let average_cost =
+            held_items.iter().map(|item| item.buy_cost).sum::<Money>() / held_items.len();
+        let min_price = average_cost * (1.0 + config.traders.min_margin.value);
+        let market_price = price_history
+            .last_median(region, &trader.item_type)
+            .unwrap_or(min_price);
+        let old_price = sell_strategy.current_price;
+        sell_strategy.base_price = average_cost;
+        sell_strategy.current_price = market_price.max(min_price);
+        if old_price != sell_strategy.current_price {
+            logs.send(LogEvent::Generic {
+                text: format!(
+                    "I'm relisting {} at {} (average buy cost {}, market price {})",
+                    trader.item_type, sell_strategy.current_price, average_cost, market_price
+                ),
+                entity,
+            });
+        }
+    }
+}
+
+#[measured]
+pub fn create_trader_sell_orders(
+    mut commands: Commands,
+    mut traders: Query<(Entity, &mut Trader, &SellStrategy, &Region)>,
+) {
+    for (seller, mut trader, strategy, region) in traders.iter_mut() {
+        if trader.stock.is_empty() {
+            continue;
+        }
+        let items = trader.stock.drain(..).collect::<Vec<_>>();
+        let sell_order = SellOrder {
+            items,
+            item_type: trader.item_type,
+            seller,
+            price: strategy.current_price,
+            base_price: strategy.base_price,
+            region: region.clone(),
+        };
+        commands.spawn((
+            sell_order,
+            Name::new(format!("{} trader sell order", trader.item_type)),
+            *strategy,
+        ));
+    }
+}
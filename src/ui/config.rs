@@ -7,7 +7,11 @@ use bevy_egui::{
 };
 use enum_display_derive::Display;
 
-use crate::config::{Config, ConfigValue, CONFIG_PATH, DEFAULT_CONFIG_PATH};
+use crate::config::{
+    Config, ConfigValue, CostAllocation, DecisionModel, InventoryPolicy, CONFIG_PATH,
+    DEFAULT_CONFIG_PATH,
+};
+use crate::pricing::Pricing;
 
 #[derive(PartialEq, Eq, Display)]
 pub enum SettingsPanel {
@@ -64,15 +68,24 @@ pub fn settings(
                 ui.collapsing("Government", |ui| {
                     draw_config_value(ui, &mut config.init.government.starting_money);
                 });
+                ui.collapsing("Traders", |ui| {
+                    draw_config_value(ui, &mut config.init.traders.amount);
+                    draw_config_value(ui, &mut config.init.traders.starting_money);
+                });
+                ui.collapsing("Off-world market", |ui| {
+                    draw_config_value(ui, &mut config.init.offworld.starting_money);
+                });
             }),
             SettingsPanel::People =>
                 add_options_grid(ui, |ui| {
                     draw_config_value(ui, &mut config.people.max_buy_orders_per_day);
                     draw_config_value(ui, &mut config.people.discount_rate);
+                    draw_config_value(ui, &mut config.people.repair_below);
                 }),
             SettingsPanel::Business => add_options_grid(ui, |ui| {
                 draw_config_value(ui, &mut config.business.prices.max_change_per_day);
                 draw_config_value(ui, &mut config.business.prices.sell_history_to_consider);
+                draw_choice_config_value(ui, &mut config.business.pricing, &Pricing::choices());
                 draw_config_value(ui, &mut config.business.goal_produced_cycles_count);
                 draw_config_value(ui, &mut config.business.keep_resources_for_cycles_amount);
                 draw_config_value(ui, &mut config.business.min_days_between_staff_change);
@@ -81,12 +94,122 @@ pub fn settings(
                 draw_config_value(ui, &mut config.business.new_worker_salary);
                 draw_config_value(ui, &mut config.business.market.amount_of_sell_orders_seen);
                 draw_config_value(ui, &mut config.business.market.amount_of_sell_orders_to_choose_best_price_from);
+                draw_config_value(ui, &mut config.business.market.quality_sensitivity);
+                ui.collapsing("Contracts", |ui| {
+                    draw_config_value(ui, &mut config.business.contracts.duration);
+                    draw_config_value(ui, &mut config.business.contracts.delivery_period);
+                    draw_config_value(ui, &mut config.business.contracts.penalty);
+                    draw_config_value(ui, &mut config.business.contracts.max_share_of_output);
+                });
+                ui.collapsing("Quality", |ui| {
+                    draw_config_value(ui, &mut config.business.quality.understaffing_penalty);
+                    draw_config_value(ui, &mut config.business.quality.max_investment_bonus);
+                    draw_config_value(ui, &mut config.business.quality.input_weight);
+                    draw_config_value(ui, &mut config.business.quality.investment_share);
+                    draw_config_value(ui, &mut config.business.quality.full_investment);
+                    draw_config_value(ui, &mut config.business.quality.depreciation);
+                });
+                ui.collapsing("Byproducts", |ui| {
+                    draw_choice_config_value(
+                        ui,
+                        &mut config.business.byproducts.cost_allocation,
+                        &[CostAllocation::Quantity, CostAllocation::MarketValue, CostAllocation::MainOutput],
+                    );
+                });
+                ui.collapsing("Production Lines", |ui| {
+                    draw_config_value(ui, &mut config.business.production_lines.max_lines);
+                    draw_config_value(ui, &mut config.business.production_lines.new_line_cost);
+                    draw_config_value(ui, &mut config.business.production_lines.retooling_cost);
+                    draw_config_value(ui, &mut config.business.production_lines.retooling_days);
+                });
+                ui.collapsing("Tools", |ui| {
+                    draw_config_value(ui, &mut config.business.tools.repair_below);
+                });
+                ui.collapsing("Skills", |ui| {
+                    draw_config_value(ui, &mut config.business.skills.novice_productivity);
+                    draw_config_value(ui, &mut config.business.skills.expert_productivity);
+                    draw_config_value(ui, &mut config.business.skills.days_to_learn);
+                    draw_config_value(ui, &mut config.business.skills.salary_share);
+                });
+                ui.collapsing("Research", |ui| {
+                    draw_config_value(ui, &mut config.business.research.reserve);
+                    draw_config_value(ui, &mut config.business.research.staff_share);
+                    draw_config_value(ui, &mut config.business.research.spread_chance);
+                });
+                ui.collapsing("Storage", |ui| {
+                    draw_config_value(ui, &mut config.business.storage.initial_capacity);
+                    draw_config_value(ui, &mut config.business.storage.rent_per_volume);
+                    draw_config_value(ui, &mut config.business.storage.expansion_size);
+                    draw_config_value(ui, &mut config.business.storage.expansion_cost_per_volume);
+                    draw_config_value(ui, &mut config.business.storage.expand_above);
+                    draw_config_value(ui, &mut config.business.storage.input_share);
+                });
+                ui.collapsing("Inventory", |ui| {
+                    draw_choice_config_value(
+                        ui,
+                        &mut config.business.inventory.policy,
+                        &[
+                            InventoryPolicy::TargetCycles,
+                            InventoryPolicy::ReorderPoint,
+                            InventoryPolicy::EconomicOrderQuantity,
+                            InventoryPolicy::DemandForecast,
+                        ],
+                    );
+                    draw_config_value(ui, &mut config.business.inventory.reorder_point);
+                    draw_config_value(ui, &mut config.business.inventory.order_up_to);
+                    draw_config_value(ui, &mut config.business.inventory.order_cost);
+                    draw_config_value(ui, &mut config.business.inventory.capital_cost);
+                    draw_config_value(ui, &mut config.business.inventory.forecast_days);
+                    draw_config_value(ui, &mut config.business.inventory.safety_stock);
+                });
+                ui.collapsing("Staffing", |ui| {
+                    draw_config_value(ui, &mut config.business.staffing.forecast_days);
+                    draw_config_value(ui, &mut config.business.staffing.max_change);
+                });
+                ui.collapsing("Entrepreneurs", |ui| {
+                    draw_choice_config_value(
+                        ui,
+                        &mut config.business.entrepreneurs.model,
+                        &[DecisionModel::RiskScore, DecisionModel::ExpectedProfit],
+                    );
+                    draw_config_value(ui, &mut config.business.entrepreneurs.min_money);
+                    draw_config_value(ui, &mut config.business.entrepreneurs.min_expected_profit);
+                    draw_config_value(ui, &mut config.business.entrepreneurs.unknown_price_markup);
+                    draw_config_value(ui, &mut config.business.entrepreneurs.max_risk_tolerance);
+                });
+                ui.collapsing("Acquisitions", |ui| {
+                    draw_config_value(ui, &mut config.business.acquisitions.premium);
+                    draw_config_value(ui, &mut config.business.acquisitions.distressed_discount);
+                    draw_config_value(ui, &mut config.business.acquisitions.distress_months);
+                    draw_config_value(
+                        ui,
+                        &mut config.business.acquisitions.recapitalization_margin,
+                    );
+                    draw_config_value(ui, &mut config.business.acquisitions.cooldown_days);
+                    draw_config_value(ui, &mut config.business.acquisitions.max_per_month);
+                    draw_config_value(ui, &mut config.business.acquisitions.buyer_reserve);
+                });
+                ui.collapsing("Bankruptcy", |ui| {
+                    draw_config_value(ui, &mut config.business.bankruptcy.grace_days);
+                    draw_config_value(ui, &mut config.business.bankruptcy.liquidation_days);
+                    draw_config_value(ui, &mut config.business.bankruptcy.liquidation_discount);
+                });
+                ui.collapsing("Traders", |ui| {
+                    draw_config_value(ui, &mut config.traders.risk_appetite);
+                    draw_config_value(ui, &mut config.traders.required_discount);
+                    draw_config_value(ui, &mut config.traders.min_margin);
+                });
+                ui.collapsing("Off-world market", |ui| {
+                    draw_config_value(ui, &mut config.offworld.price_volatility);
+                    draw_config_value(ui, &mut config.offworld.import_order_size);
+                });
             }),
             SettingsPanel::Government => add_options_grid(ui, |ui| {
                 draw_config_value(ui, &mut config.government.min_time_between_business_creation);
                 ui.collapsing("Taxes" ,|ui| {
                     draw_config_value(ui, &mut config.government.taxes.cit);
                     draw_config_value(ui, &mut config.government.taxes.pit);
+                    draw_config_value(ui, &mut config.government.taxes.tariff);
                 });
             })
         }
@@ -101,6 +224,25 @@ fn _draw_bool_config_value(ui: &mut Ui, value: &mut ConfigValue<bool>) {
     ui.checkbox(&mut value.value, "");
 }
 
+fn draw_choice_config_value<T: PartialEq + Display + Copy>(
+    ui: &mut Ui,
+    value: &mut ConfigValue<T>,
+    choices: &[T],
+) {
+    let label = ui.label(&value.name);
+    if let Some(hint) = &value.description {
+        label.on_hover_text(hint);
+    }
+    egui::ComboBox::from_id_source(&value.name)
+        .selected_text(value.value.to_string())
+        .show_ui(ui, |ui| {
+            for choice in choices {
+                ui.selectable_value(&mut value.value, *choice, choice.to_string());
+            }
+        });
+    ui.end_row();
+}
+
 fn add_settings_panel(ui: &mut Ui, value: &mut SettingsPanel, label: SettingsPanel) {
     let text = label.to_string();
     ui.selectable_value(value, label, text);
@@ -36,8 +36,8 @@ pub fn render_logs(
             ui.radio_value(&mut ui_state.logging_filter_type, LoggingFilterType::Regex, "regex");
             ui.radio_value(&mut ui_state.logging_filter_type, LoggingFilterType::Fuzzy, "fuzzy");
         });
-        if ui_state.regex_error.is_some() {
-            ui.label(format!("Regex error: {}", ui_state.regex_error.as_ref().unwrap()));
+        if let Some(regex_error) = &ui_state.regex_error {
+            ui.label(format!("Regex error: {}", regex_error));
         }
         ui.collapsing("Instructions & settings", |ui| {
             ui.label("Click on a 'P' button in other windows to pin entites. Only pinned entities will be shown here. Click 'U' button to unpin entities. Clicking on 'Pin' column header will list only pinned entities without changing sorting selected");
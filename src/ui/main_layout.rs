@@ -5,6 +5,8 @@ use bevy_egui::EguiContexts;
 use macros::measured;
 
 use crate::commands::GameCommand;
+use crate::items::ItemId;
+use crate::regions::Region;
 use crate::ui::debug::Performance;
 use crate::ui::logs::LoggingFilterType;
 use crate::ui::manufacturers::ManufacturerSort;
@@ -139,4 +141,6 @@ pub struct UiState {
     pub fuzzy_match_order: bool,
     pub regex_error: Option<String>,
     pub logs_show_all_if_no_pins: bool,
+    pub price_history_region: Option<Region>,
+    pub market_depth_item: Option<ItemId>,
 }
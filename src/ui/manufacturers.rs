@@ -8,9 +8,13 @@ use egui_extras::{Column, TableBuilder};
 
 use macros::measured;
 
-use crate::business::{BuyOrder, ItemType, Manufacturer, SellOrder, SellStrategy, Worker};
+use crate::business::{BuyOrder, Manufacturer, SellOrder, SellStrategy, Worker};
+use crate::contracts::SupplyContract;
+use crate::items::ItemId;
+use crate::items::ItemRegistry;
 use crate::logs::Pinned;
 use crate::money::{Money, MoneyChange};
+use crate::regions::Region;
 use crate::stats::PriceHistory;
 use crate::ui::debug::Performance;
 use crate::ui::main_layout::UiState;
@@ -22,14 +26,23 @@ use crate::Days;
 #[measured]
 pub fn render_manufacturers_stats(
     mut egui_context: EguiContexts,
-    manufacturers: Query<(Entity, &Name, &Wallet, &Manufacturer, &SellStrategy)>,
+    manufacturers: Query<(
+        Entity,
+        &Name,
+        &Wallet,
+        &Manufacturer,
+        &SellStrategy,
+        &Region,
+    )>,
     sell_orders: Query<&SellOrder>,
     buy_orders: Query<&BuyOrder>,
+    contracts: Query<&SupplyContract>,
     names: Query<&Name>,
     workers: Query<&Worker>,
     pins: Query<&Pinned>,
     mut ui_state: ResMut<UiState>,
     price_history: Res<PriceHistory>,
+    items: Res<ItemRegistry>,
     mut commands: Commands,
     date: Res<Days>,
 ) {
@@ -37,7 +50,7 @@ pub fn render_manufacturers_stats(
         let mut owner_counts: HashMap<Entity, u32> = HashMap::new();
         let total_money = manufacturers
             .iter()
-            .map(|(_, _, wallet, _, _)| wallet.money())
+            .map(|(_, _, wallet, _, _, _)| wallet.money())
             .sum::<Money>();
 
         for order in sell_orders.iter() {
@@ -52,6 +65,7 @@ pub fn render_manufacturers_stats(
             .column(Column::auto())
             .column(Column::auto())
             .column(Column::auto())
+            .column(Column::auto())
             .column(Column::initial(80.0).range(80.0..=200.0))
             .column(Column::auto())
             .column(Column::auto())
@@ -59,6 +73,7 @@ pub fn render_manufacturers_stats(
             .column(Column::auto())
             .column(Column::auto())
             .column(Column::auto())
+            .column(Column::auto())
             .column(Column::remainder())
             .min_scrolled_height(0.0);
 
@@ -74,6 +89,11 @@ pub fn render_manufacturers_stats(
                         ui_state.manufacturers = ManufacturerSort::Name;
                     }
                 });
+                header.col(|ui| {
+                    if ui.button("Region").clicked() {
+                        ui_state.manufacturers = ManufacturerSort::Region;
+                    }
+                });
                 header.col(|ui| {
                     if ui.button("Produces").clicked() {
                         ui_state.manufacturers = ManufacturerSort::Production;
@@ -109,6 +129,11 @@ pub fn render_manufacturers_stats(
                         ui_state.manufacturers = ManufacturerSort::BuyOrders;
                     }
                 });
+                header.col(|ui| {
+                    if ui.button("Contracts").clicked() {
+                        ui_state.manufacturers = ManufacturerSort::Contracts;
+                    }
+                });
                 header.col(|ui| {
                     if ui.button("Price").clicked() {
                         ui_state.manufacturers = ManufacturerSort::CurrentPrice;
@@ -121,21 +146,21 @@ pub fn render_manufacturers_stats(
                 });
             })
             .body(|mut body| {
-                let buy_order_by_type: HashMap<ItemType, usize> = buy_orders
+                let buy_order_by_type: HashMap<ItemId, usize> = buy_orders
                     .iter()
-                    .map(|x| x.item_type.clone())
+                    .map(|x| x.item_type)
                     .fold(HashMap::new(), |mut acc, x| {
                         *acc.entry(x).or_insert(0) += 1;
                         acc
                     });
-                let buy_order_by_type_and_buyer: HashMap<(ItemType, Name), usize> = buy_orders
+                let buy_order_by_type_and_buyer: HashMap<(ItemId, Name), usize> = buy_orders
                     .iter()
-                    .map(|x| (x.item_type.clone(), names.get(x.buyer).unwrap().clone()))
+                    .map(|x| (x.item_type, names.get(x.buyer).unwrap().clone()))
                     .fold(HashMap::new(), |mut acc, x| {
                         *acc.entry(x).or_insert(0) += 1;
                         acc
                     });
-                let mut buy_order_vec: Vec<((ItemType, Name), usize)> =
+                let mut buy_order_vec: Vec<((ItemId, Name), usize)> =
                     buy_order_by_type_and_buyer.into_iter().collect();
                 buy_order_vec.sort_by(|((_, a_name), a), ((_, b_name), b)| {
                     b.cmp(a).then_with(|| a_name.cmp(b_name))
@@ -143,50 +168,91 @@ pub fn render_manufacturers_stats(
                 let mut rows = manufacturers
                     .iter()
                     .map(
-                        |(entity, name, wallet, manufacturer, sell_strategy)| ManufacturerRow {
-                            entity,
-                            pinned: pins.get(entity).is_ok(),
-                            name: name.to_string(),
-                            production: manufacturer.production_cycle.output.0.name.to_string(),
-                            production_text: format!("{}", manufacturer.production_cycle),
-                            money: wallet.money(),
-                            money_text: wallet.get_summary(date.days, 30, 30),
-                            workers: manufacturer.hired_workers.len(),
-                            workers_text: manufacturer
-                                .hired_workers
-                                .iter()
-                                .map(|x| {
-                                    format!(
-                                        "{} ({})",
-                                        names.get(*x).unwrap(),
-                                        workers.get(*x).map_or(Money(0), |w| w.salary)
-                                    )
-                                })
-                                .collect::<Vec<String>>()
-                                .join("\n"),
-                            items: count_items(&manufacturer.assets.items),
-                            items_text: items_to_string(&manufacturer.assets.items),
-                            items_to_sell: manufacturer.assets.items_to_sell.len(),
-                            on_market: *owner_counts.get(&entity).unwrap_or(&0),
-                            on_market_text: price_history
-                                .prices
-                                .get(&manufacturer.production_cycle.output.0)
-                                .and_then(|x| x.last())
-                                .map_or_else(
-                                    || "".to_string(),
-                                    |price_stats| format!("{}", price_stats),
+                        |(entity, name, wallet, manufacturer, sell_strategy, region)| {
+                            ManufacturerRow {
+                                entity,
+                                pinned: pins.get(entity).is_ok(),
+                                name: name.to_string(),
+                                region: region.to_string(),
+                                production: manufacturer
+                                    .production_lines
+                                    .iter()
+                                    .map(|line| line.production_cycle.main_output.name())
+                                    .collect::<Vec<_>>()
+                                    .join(", "),
+                                production_text: format!(
+                                    "{}\nQuality investment: {:.0}%\n{}\nWarehouse: {:.0} of {:.0} m³",
+                                    lines_to_string(manufacturer),
+                                    manufacturer.quality_investment * 100.0,
+                                    research_to_string(manufacturer),
+                                    items.stored_volume(&manufacturer.assets),
+                                    manufacturer.storage_capacity
                                 ),
-                            buy_orders: *buy_order_by_type
-                                .get(&manufacturer.production_cycle.output.0)
-                                .unwrap_or(&0),
-                            buy_orders_text: buy_order_vec
-                                .iter()
-                                .filter(|x| x.0 .0 == manufacturer.production_cycle.output.0)
-                                .map(|x| format!("{}: {}", x.0 .1, x.1))
-                                .collect::<Vec<_>>()
-                                .join("\n"),
-                            current_price: sell_strategy.current_price,
-                            change: wallet.calculate_total_change(date.days, 30),
+                                money: wallet.money(),
+                                money_text: wallet.get_summary(date.days, 30, 30),
+                                workers: manufacturer.workers_count(),
+                                workers_text: manufacturer
+                                    .hired_workers()
+                                    .map(|x| {
+                                        format!(
+                                            "{} ({})",
+                                            names.get(*x).unwrap(),
+                                            workers.get(*x).map_or(Money(0), |w| w.salary)
+                                        )
+                                    })
+                                    .collect::<Vec<String>>()
+                                    .join("\n"),
+                                items: count_items(&manufacturer.assets.items),
+                                items_text: items_to_string(&manufacturer.assets.items),
+                                items_to_sell: manufacturer.assets.items_to_sell.len(),
+                                on_market: *owner_counts.get(&entity).unwrap_or(&0),
+                                on_market_text: price_history
+                                    .prices
+                                    .get(manufacturer.main_output())
+                                    .and_then(|x| x.last())
+                                    .map_or_else(
+                                        || "".to_string(),
+                                        |price_stats| format!("{}", price_stats),
+                                    ),
+                                buy_orders: *buy_order_by_type
+                                    .get(manufacturer.main_output())
+                                    .unwrap_or(&0),
+                                buy_orders_text: buy_order_vec
+                                    .iter()
+                                    .filter(|x| x.0 .0 == *manufacturer.main_output())
+                                    .map(|x| format!("{}: {}", x.0 .1, x.1))
+                                    .collect::<Vec<_>>()
+                                    .join("\n"),
+                                contracts: contracts
+                                    .iter()
+                                    .filter(|contract| contract.involves(entity))
+                                    .count(),
+                                contracts_text: contracts
+                                    .iter()
+                                    .filter(|contract| contract.involves(entity))
+                                    .map(|contract| {
+                                        let (direction, other_party) = if contract.buyer == entity {
+                                            ("from", contract.seller)
+                                        } else {
+                                            ("to", contract.buyer)
+                                        };
+                                        format!(
+                                            "{} {} every {} days {} {} at {} until day {}",
+                                            contract.quantity,
+                                            contract.item_type,
+                                            contract.period,
+                                            direction,
+                                            names.get(other_party).map_or("?", |n| n.as_str()),
+                                            contract.price,
+                                            contract.end
+                                        )
+                                    })
+                                    .collect::<Vec<_>>()
+                                    .join("\n"),
+                                current_price: sell_strategy.current_price,
+                                pricing: sell_strategy.pricing.name(),
+                                change: wallet.calculate_total_change(date.days, 30),
+                            }
                         },
                     )
                     .collect::<Vec<_>>();
@@ -194,6 +260,9 @@ pub fn render_manufacturers_stats(
                     ManufacturerSort::Name => {
                         rows.sort_by(|a, b| a.name.partial_cmp(&b.name).unwrap())
                     }
+                    ManufacturerSort::Region => {
+                        rows.sort_by(|a, b| a.region.cmp(&b.region).then(a.name.cmp(&b.name)))
+                    }
                     ManufacturerSort::Production => {
                         rows.sort_by(|a, b| a.production.partial_cmp(&b.production).unwrap())
                     }
@@ -215,6 +284,9 @@ pub fn render_manufacturers_stats(
                     ManufacturerSort::BuyOrders => {
                         rows.sort_by(|a, b| b.buy_orders.partial_cmp(&a.buy_orders).unwrap())
                     }
+                    ManufacturerSort::Contracts => {
+                        rows.sort_by(|a, b| b.contracts.partial_cmp(&a.contracts).unwrap())
+                    }
                     ManufacturerSort::CurrentPrice => {
                         rows.sort_by(|a, b| b.current_price.partial_cmp(&a.current_price).unwrap())
                     }
@@ -240,11 +312,14 @@ pub fn render_manufacturers_stats(
                         row.col(|ui| {
                             ui.label(&r.name);
                         });
+                        row.col(|ui| {
+                            ui.label(&r.region);
+                        });
                         row.col(|ui| {
                             ui.label(&r.production).on_hover_text(&r.production_text);
                         });
                         row.col(|ui| {
-                            ui.label(&r.money.to_string()).on_hover_text(&r.money_text);
+                            ui.label(r.money.to_string()).on_hover_text(&r.money_text);
                         });
                         row.col(|ui| {
                             label_with_hover_text(ui, r.workers, &r.workers_text);
@@ -253,7 +328,7 @@ pub fn render_manufacturers_stats(
                             label_with_hover_text(ui, r.items, &r.items_text);
                         });
                         row.col(|ui| {
-                            ui.label(&r.items_to_sell.to_string());
+                            ui.label(r.items_to_sell.to_string());
                         });
                         row.col(|ui| {
                             if r.on_market_text.is_empty() {
@@ -270,7 +345,10 @@ pub fn render_manufacturers_stats(
                             label_with_hover_text(ui, r.buy_orders, &r.buy_orders_text);
                         });
                         row.col(|ui| {
-                            ui.label(&r.current_price.to_string());
+                            label_with_hover_text(ui, r.contracts, &r.contracts_text);
+                        });
+                        row.col(|ui| {
+                            ui.label(r.current_price.to_string()).on_hover_text(r.pricing);
                         });
                         row.col(|ui| match r.change {
                             MoneyChange::Right(change) => {
@@ -288,12 +366,14 @@ pub fn render_manufacturers_stats(
 
 pub enum ManufacturerSort {
     Name,
+    Region,
     Money,
     Workers,
     Items,
     ItemsToSell,
     OnMarket,
     BuyOrders,
+    Contracts,
     Production,
     CurrentPrice,
     Change,
@@ -303,6 +383,7 @@ struct ManufacturerRow {
     entity: Entity,
     pinned: bool,
     name: String,
+    region: String,
     production: String,
     money: Money,
     money_text: String,
@@ -314,8 +395,54 @@ struct ManufacturerRow {
     buy_orders: usize,
     items_text: String,
     buy_orders_text: String,
+    contracts: usize,
+    contracts_text: String,
     production_text: String,
     workers_text: String,
     current_price: Money,
+    pricing: &'static str,
     change: MoneyChange,
 }
+
+fn research_to_string(manufacturer: &Manufacturer) -> String {
+    let research = &manufacturer.research;
+    let project = research.project.as_ref().map_or_else(
+        || "no research project".to_string(),
+        |project| {
+            format!(
+                "researching {} ({:.0} workdays left)",
+                project.technology, project.workdays_left
+            )
+        },
+    );
+    let mut known = research.known.iter().cloned().collect::<Vec<_>>();
+    known.sort();
+    if known.is_empty() {
+        format!("Technologies: none, {}", project)
+    } else {
+        format!("Technologies: {}, {}", known.join(", "), project)
+    }
+}
+
+fn lines_to_string(manufacturer: &Manufacturer) -> String {
+    manufacturer
+        .production_lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            let retooling = if line.retooling_days_left > 0 {
+                format!(", retooling for {} more days", line.retooling_days_left)
+            } else {
+                "".to_string()
+            };
+            format!(
+                "Line {} ({} workers{}):\n{}",
+                i + 1,
+                line.hired_workers.len(),
+                retooling,
+                line.production_cycle
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
@@ -0,0 +1,178 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use bevy::core::Name;
+use bevy::prelude::{Entity, Query, Res, ResMut};
+use bevy_egui::egui::plot::{
+    Bar, BarChart, Legend, Line, MarkerShape, Plot, PlotPoints, Points, VLine,
+};
+use bevy_egui::egui::{Color32, ComboBox, Window};
+use bevy_egui::EguiContexts;
+
+use macros::measured;
+
+use crate::business::{BuyOrder, SellOrder, SellStrategy};
+use crate::money::Money;
+use crate::ui::debug::Performance;
+use crate::ui::main_layout::UiState;
+use crate::ui::prices::string_to_rgb;
+use crate::wallet::{TradeSide, Transaction, Wallet};
+use crate::Days;
+
+#[allow(clippy::too_many_arguments)]
+#[measured]
+pub fn render_market_depth(
+    mut egui_context: EguiContexts,
+    sell_orders: Query<(&SellOrder, Option<&SellStrategy>)>,
+    buy_orders: Query<&BuyOrder>,
+    strategies: Query<&SellStrategy>,
+    wallets: Query<&Wallet>,
+    names: Query<&Name>,
+    mut ui_state: ResMut<UiState>,
+    date: Res<Days>,
+) {
+    Window::new("Market Depth").show(egui_context.ctx_mut(), |ui| {
+        let item_types = sell_orders
+            .iter()
+            .map(|(sell_order, _)| sell_order.item_type)
+            .chain(
+                buy_orders
+                    .iter()
+                    .map(|buy_order| buy_order.item_type),
+            )
+            .collect::<BTreeSet<_>>();
+        ComboBox::from_label("Item")
+            .selected_text(
+                ui_state
+                    .market_depth_item
+                    .as_ref()
+                    .map_or("Select item".to_string(), |item_type| item_type.to_string()),
+            )
+            .show_ui(ui, |ui| {
+                for item_type in item_types.iter() {
+                    ui.selectable_value(
+                        &mut ui_state.market_depth_item,
+                        Some(*item_type),
+                        item_type.name(),
+                    );
+                }
+            });
+        let Some(item_type) = ui_state.market_depth_item else {
+            return;
+        };
+        let name_of = |entity: Entity| names.get(entity).map_or("?", |name| name.as_str());
+
+        // ask curve, cheapest orders first
+        let mut asks = sell_orders
+            .iter()
+            .filter(|(sell_order, _)| {
+                sell_order.item_type == item_type && !sell_order.items.is_empty()
+            })
+            .collect::<Vec<_>>();
+        asks.sort_by_key(|(sell_order, _)| sell_order.price);
+        let mut bars_by_seller: BTreeMap<&str, Vec<Bar>> = BTreeMap::new();
+        let mut ask_curve = vec![];
+        let mut cumulative = 0.0;
+        for (sell_order, order_strategy) in asks.iter() {
+            let quantity = sell_order.items.len() as f64;
+            // bankrupt sellers are gone, their orders keep a copy of the strategy
+            let strategy = strategies.get(sell_order.seller).ok().or(*order_strategy);
+            let seller_name = name_of(sell_order.seller);
+            ask_curve.push([cumulative, sell_order.price.as_f64()]);
+            ask_curve.push([cumulative + quantity, sell_order.price.as_f64()]);
+            bars_by_seller.entry(seller_name).or_default().push(
+                Bar::new(cumulative + quantity / 2.0, sell_order.price.as_f64())
+                    .width(quantity)
+                    .name(format!(
+                        "{}\n{} items in {} for {}\nQuality: {:.2}\nBase price: {}\nCurrent price: {}",
+                        seller_name,
+                        quantity,
+                        sell_order.region,
+                        sell_order.price,
+                        sell_order.quality(),
+                        strategy.map_or("-".to_string(), |s| s.base_price.to_string()),
+                        strategy.map_or("-".to_string(), |s| s.current_price.to_string())
+                    ))
+                    .fill(string_to_rgb(seller_name)),
+            );
+            cumulative += quantity;
+        }
+
+        let outstanding = buy_orders
+            .iter()
+            .filter(|buy_order| buy_order.item_type == item_type)
+            .count();
+
+        // transactions are stored newest first, so only the head of each wallet is from today
+        let mut trades_today = wallets
+            .iter()
+            .flat_map(|wallet| {
+                wallet
+                    .transactions
+                    .iter()
+                    .take_while(|transaction| transaction.get_date() == date.days)
+            })
+            .filter_map(|transaction| match transaction {
+                Transaction::Trade {
+                    side: TradeSide::Receive,
+                    item_type: traded,
+                    price,
+                    ..
+                } if *traded == item_type => Some(*price),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        trades_today.sort_unstable();
+
+        ui.label(format!(
+            "On sale: {} items in {} orders, outstanding buy orders: {}",
+            cumulative,
+            asks.len(),
+            outstanding
+        ));
+        if trades_today.is_empty() {
+            ui.label("No trades executed today");
+        } else {
+            ui.label(format!(
+                "Executed today: {} trades, min: {}, avg: {}, max: {}",
+                trades_today.len(),
+                trades_today.first().unwrap(),
+                trades_today.iter().sum::<Money>() / trades_today.len(),
+                trades_today.last().unwrap()
+            ));
+        }
+
+        Plot::new("Market depth")
+            .legend(Legend::default())
+            .show(ui, |ui| {
+                for (seller_name, bars) in bars_by_seller {
+                    ui.bar_chart(
+                        BarChart::new(bars)
+                            .name(seller_name)
+                            .color(string_to_rgb(seller_name)),
+                    );
+                }
+                ui.line(
+                    Line::new(PlotPoints::new(ask_curve))
+                        .color(Color32::WHITE)
+                        .name("Ask curve"),
+                );
+                ui.vline(
+                    VLine::new(outstanding as f64)
+                        .color(Color32::RED)
+                        .name(format!("Outstanding buy orders ({})", outstanding)),
+                );
+                let trade_points = trades_today
+                    .iter()
+                    .enumerate()
+                    .map(|(i, price)| [i as f64 + 0.5, price.as_f64()])
+                    .collect::<Vec<_>>();
+                ui.points(
+                    Points::new(PlotPoints::new(trade_points))
+                        .shape(MarkerShape::Diamond)
+                        .radius(3.0)
+                        .color(Color32::YELLOW)
+                        .name("Executed trades today"),
+                );
+            });
+    });
+}
@@ -4,6 +4,7 @@ pub mod governments;
 pub mod logs;
 pub mod main_layout;
 pub mod manufacturers;
+pub mod market_depth;
 pub mod people;
 pub mod prices;
 pub mod template;
@@ -151,19 +151,19 @@ pub fn render_people_stats(
                             ui.label(&r.name);
                         });
                         row.col(|ui| {
-                            ui.label(&r.money.to_string()).on_hover_text(&r.money_text);
+                            ui.label(r.money.to_string()).on_hover_text(&r.money_text);
                         });
                         row.col(|ui| {
                             label_with_hover_text(ui, r.items, &r.items_text);
                         });
                         row.col(|ui| {
-                            ui.label(&r.utility.to_string());
+                            ui.label(r.utility.to_string());
                         });
                         row.col(|ui| {
                             ui.label(&r.employed_at);
                         });
                         row.col(|ui| {
-                            ui.label(&r.salary.to_string());
+                            ui.label(r.salary.to_string());
                         });
                     });
                 }
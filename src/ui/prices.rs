@@ -2,19 +2,25 @@ use std::collections::hash_map::DefaultHasher;
 use std::collections::{BTreeMap, HashMap};
 use std::hash::{Hash, Hasher};
 
-use bevy::prelude::{Query, Res, ResMut};
+use bevy::prelude::{Query, Res, ResMut, With};
 use bevy_egui::egui::plot::{
     BoxElem, BoxPlot, BoxSpread, Legend, Line, LineStyle, Plot, PlotPoints,
 };
-use bevy_egui::egui::{Color32, Window};
+use bevy_egui::egui::{Color32, ComboBox, Ui, Window};
 use bevy_egui::EguiContexts;
 
 use macros::measured;
 
-use crate::business::{ItemType, SellOrder};
+use crate::business::SellOrder;
+use crate::items::ItemId;
 use crate::money::Money;
-use crate::stats::PriceHistory;
+use crate::offworld::OffWorldMarket;
+use crate::regions::Regions;
+use crate::stats::{PriceHistory, PriceIndex, PriceIndices, DAYS_IN_MONTH, DAYS_IN_YEAR};
 use crate::ui::debug::Performance;
+use crate::ui::main_layout::UiState;
+use crate::wallet::{TradeSide, Transaction, Wallet};
+use crate::Days;
 
 #[measured]
 pub fn render_todays_prices(mut egui_context: EguiContexts, sell_orders: Query<&SellOrder>) {
@@ -23,7 +29,7 @@ pub fn render_todays_prices(mut egui_context: EguiContexts, sell_orders: Query<&
 
         for sell_order in sell_orders.iter() {
             grouped_orders
-                .entry(sell_order.item_type.clone())
+                .entry(sell_order.item_type)
                 .or_insert_with(Vec::new)
                 .push(sell_order.price);
         }
@@ -59,7 +65,7 @@ pub fn render_todays_prices(mut egui_context: EguiContexts, sell_orders: Query<&
 
 #[allow(clippy::too_many_arguments)]
 fn create_box_plot(
-    item_type: &ItemType,
+    item_type: &ItemId,
     x: u64,
     min: u64,
     p25: u64,
@@ -80,16 +86,120 @@ fn create_box_plot(
         ),
     )
     .name(format!("Total Items: {}\nAvg: {}", len, avg))])
-    .name(item_type.name.as_str())
-    .color(string_to_rgb(item_type.name.as_str()))
+    .name(item_type.name())
+    .color(string_to_rgb(item_type.name()))
 }
 
-pub fn render_price_history(history: Res<PriceHistory>, mut egui_context: EguiContexts) {
+fn index_label(ui: &mut Ui, name: &str, index: &PriceIndex) {
+    let format_change = |change: Option<f64>| {
+        change.map_or("-".to_string(), |change| format!("{:+.2}%", change * 100.0))
+    };
+    ui.label(format!(
+        "{}: {} (MoM: {}, YoY: {})",
+        name,
+        index
+            .last()
+            .map_or("-".to_string(), |value| format!("{:.2}", value)),
+        format_change(index.inflation(DAYS_IN_MONTH)),
+        format_change(index.inflation(DAYS_IN_YEAR)),
+    ));
+}
+
+fn trade_balance_label(ui: &mut Ui, wallet: &Wallet, date: usize) {
+    let (mut imports, mut exports) = (Money(0), Money(0));
+    for transaction in wallet
+        .transactions
+        .iter()
+        .take_while(|transaction| date - transaction.get_date() <= DAYS_IN_MONTH)
+    {
+        match transaction {
+            // the market receives money for imported items and pays for exported ones
+            Transaction::Trade {
+                side: TradeSide::Receive,
+                price,
+                ..
+            } => imports += *price,
+            Transaction::Trade {
+                side: TradeSide::Pay,
+                price,
+                ..
+            } => exports += *price,
+            _ => {}
+        }
+    }
+    let balance = if exports >= imports {
+        format!("+{}", exports - imports)
+    } else {
+        format!("-{}", imports - exports)
+    };
+    ui.label(format!(
+        "Last {} days: exports {}, imports {}, trade balance {}",
+        DAYS_IN_MONTH, exports, imports, balance
+    ));
+}
+
+pub fn render_price_history(
+    history: Res<PriceHistory>,
+    indices: Res<PriceIndices>,
+    offworld_markets: Query<&Wallet, With<OffWorldMarket>>,
+    date: Res<Days>,
+    regions: Res<Regions>,
+    mut ui_state: ResMut<UiState>,
+    mut egui_context: EguiContexts,
+) {
     Window::new("Price History").show(egui_context.ctx_mut(), |ui| {
+        if let Ok(wallet) = offworld_markets.get_single() {
+            ui.collapsing("Off-world trade", |ui| {
+                trade_balance_label(ui, wallet, date.days);
+            });
+        }
+        ui.collapsing("Price indices", |ui| {
+            index_label(ui, "CPI", &indices.cpi);
+            index_label(ui, "PPI", &indices.ppi);
+            Plot::new("Price indices")
+                .legend(Legend::default())
+                .height(200.0)
+                .show(ui, |ui| {
+                    for (name, index) in [("CPI", &indices.cpi), ("PPI", &indices.ppi)] {
+                        let points = index
+                            .values
+                            .iter()
+                            .map(|(day, value)| [*day as f64, *value])
+                            .collect::<Vec<_>>();
+                        ui.line(
+                            Line::new(PlotPoints::new(points))
+                                .color(string_to_rgb(name))
+                                .name(name),
+                        );
+                    }
+                });
+        });
+        ComboBox::from_label("Region")
+            .selected_text(
+                ui_state
+                    .price_history_region
+                    .as_ref()
+                    .map_or("All regions".to_string(), |region| region.to_string()),
+            )
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut ui_state.price_history_region, None, "All regions");
+                for region in regions.regions.iter() {
+                    ui.selectable_value(
+                        &mut ui_state.price_history_region,
+                        Some(region.clone()),
+                        region.name.as_str(),
+                    );
+                }
+            });
+        let empty = HashMap::new();
+        let prices = match &ui_state.price_history_region {
+            Some(region) => history.regional_prices.get(region).unwrap_or(&empty),
+            None => &history.prices,
+        };
         let mut line_avg = HashMap::new();
         let mut line_p25 = HashMap::new();
         let mut line_p75 = HashMap::new();
-        for (item_type, price_history) in history.prices.iter() {
+        for (item_type, price_history) in prices.iter() {
             let mut avgs = vec![];
             let mut p25s = vec![];
             let mut p75s = vec![];
@@ -106,9 +216,9 @@ pub fn render_price_history(history: Res<PriceHistory>, mut egui_context: EguiCo
                 p25s.push([day as f64, p25.as_f64()]);
                 p75s.push([day as f64, p75.as_f64()]);
             }
-            line_avg.insert(item_type.clone(), avgs);
-            line_p25.insert(item_type.clone(), p25s);
-            line_p75.insert(item_type.clone(), p75s);
+            line_avg.insert(*item_type, avgs);
+            line_p25.insert(*item_type, p25s);
+            line_p75.insert(*item_type, p75s);
         }
         Plot::new("Price history")
             .legend(Legend::default())
@@ -116,23 +226,23 @@ pub fn render_price_history(history: Res<PriceHistory>, mut egui_context: EguiCo
                 for (item_type, points) in line_avg {
                     ui.line(
                         Line::new(PlotPoints::new(points))
-                            .color(string_to_rgb(item_type.name.as_str()))
-                            .name(item_type.name.as_str()),
+                            .color(string_to_rgb(item_type.name()))
+                            .name(item_type.name()),
                     );
                 }
                 for (item_type, points) in line_p25 {
                     ui.line(
                         Line::new(PlotPoints::new(points))
-                            .color(string_to_rgb(item_type.name.as_str()))
-                            .name(item_type.name.as_str())
+                            .color(string_to_rgb(item_type.name()))
+                            .name(item_type.name())
                             .style(LineStyle::Dashed { length: 7.0 }),
                     );
                 }
                 for (item_type, points) in line_p75 {
                     ui.line(
                         Line::new(PlotPoints::new(points))
-                            .color(string_to_rgb(item_type.name.as_str()))
-                            .name(item_type.name.as_str())
+                            .color(string_to_rgb(item_type.name()))
+                            .name(item_type.name())
                             .style(LineStyle::Dashed { length: 7.0 }),
                     );
                 }
@@ -140,7 +250,7 @@ pub fn render_price_history(history: Res<PriceHistory>, mut egui_context: EguiCo
     });
 }
 
-fn string_to_rgb(input: &str) -> Color32 {
+pub(crate) fn string_to_rgb(input: &str) -> Color32 {
     let mut hasher = DefaultHasher::new();
     input.hash(&mut hasher);
     let hash = hasher.finish();
@@ -1,19 +1,37 @@
 use std::process::Command;
 
-use bevy::prelude::ResMut;
+use bevy::prelude::{error, Query, Res, ResMut};
 use bevy_egui::egui::{Button, ScrollArea, TextEdit, TextStyle, Window};
 use bevy_egui::EguiContexts;
 
 use macros::measured;
 
+use crate::business::Manufacturer;
+use crate::diagrams::{self, Annotations, DOT_PATH, MERMAID_PATH};
 use crate::init::{ManufacturerTemplate, ProductionCycleTemplate, TemplateType, Templates};
+use crate::items::{ItemRegistry, ITEMS_PATH};
+use crate::offworld::OffWorld;
+use crate::people::Needs;
+use crate::stats::PriceHistory;
+use crate::tools::Tools;
 use crate::ui::debug::Performance;
+use crate::validation::Validation;
 
+#[allow(clippy::too_many_arguments)]
 #[measured]
-pub fn render_template_editor(mut egui_context: EguiContexts, mut templates: ResMut<Templates>) {
+pub fn render_template_editor(
+    mut egui_context: EguiContexts,
+    mut templates: ResMut<Templates>,
+    offworld: Res<OffWorld>,
+    tools: Res<Tools>,
+    needs: Res<Needs>,
+    items: Res<ItemRegistry>,
+    manufacturers: Query<&Manufacturer>,
+    price_history: Res<PriceHistory>,
+) {
     Window::new("Template editor").show(egui_context.ctx_mut(), |ui| {
         ScrollArea::vertical().show(ui, |ui| {
-            let (errors, warnings) = templates.validate();
+            let Validation { errors, warnings } = templates.validate(&offworld, &tools);
             ui.radio_value(
                 &mut templates.selected_template,
                 TemplateType::Manufacturers,
@@ -44,7 +62,23 @@ pub fn render_template_editor(mut egui_context: EguiContexts, mut templates: Res
                             &templates.production_cycles_json,
                         );
                         match production_cycles {
-                            Ok(production_cycles) => templates.production_cycles = production_cycles,
+                            // cycles with unknown items would crash the running game, they are
+                            // not applied until the items are fixed
+                            Ok(production_cycles) => match production_cycles
+                                .iter()
+                                .find_map(|cycle| Some((cycle, cycle.unknown_items().next()?)))
+                            {
+                                Some((cycle, item)) => {
+                                    json_error = format!(
+                                        "item {} of {} is not defined in {}",
+                                        item, cycle.name, ITEMS_PATH
+                                    );
+                                }
+                                None => {
+                                    templates.production_cycles = production_cycles;
+                                    templates.refresh_recipes();
+                                }
+                            },
                             Err(error) => {
                                 json_error = error.to_string();
                             }
@@ -62,7 +96,7 @@ pub fn render_template_editor(mut egui_context: EguiContexts, mut templates: Res
                     let mut sorted_errors = errors.clone();
                     sorted_errors.sort();
                     for error in sorted_errors {
-                        ui.label(error);
+                        ui.label(error.to_string());
                     }
                 });
             }
@@ -72,7 +106,7 @@ pub fn render_template_editor(mut egui_context: EguiContexts, mut templates: Res
                     let mut sorted_warnings = warnings.clone();
                     sorted_warnings.sort();
                     for warning in sorted_warnings {
-                        ui.label(warning);
+                        ui.label(warning.to_string());
                     }
                 });
             }
@@ -95,6 +129,49 @@ pub fn render_template_editor(mut egui_context: EguiContexts, mut templates: Res
                     .desired_width(f32::INFINITY),
                 // .layouter(&mut layouter),
             );
+            ui.horizontal(|ui| {
+                if ui.button("Export diagrams").on_hover_text(format!("Write the production chain to {} and {}", DOT_PATH, MERMAID_PATH)).clicked() {
+                    if let Err(error) = diagrams::export(&templates.production_cycles, &needs, None) {
+                        error!("Unable to export diagrams: {}", error);
+                    }
+                }
+                if ui.button("Export diagrams with live data").on_hover_text("Include the number of lines running each cycle and current median prices").clicked() {
+                    let annotations = Annotations::live(manufacturers.iter(), &price_history);
+                    if let Err(error) = diagrams::export(&templates.production_cycles, &needs, Some(&annotations)) {
+                        error!("Unable to export diagrams: {}", error);
+                    }
+                }
+            });
+            ui.collapsing("Recipe analysis", |ui| {
+                let recipes = &templates.recipes;
+                let mut items = recipes.items().into_iter().collect::<Vec<_>>();
+                items.sort_by_key(|item| (recipes.tier(item), item.clone()));
+                for item in items {
+                    let tier = recipes.tier(&item);
+                    if tier == 0 {
+                        ui.label(format!("Tier 0: {}", item));
+                        continue;
+                    }
+                    let mut raw = recipes
+                        .raw_materials(&item)
+                        .into_iter()
+                        .map(|(material, amount)| format!("{:.2} {}", amount, material))
+                        .collect::<Vec<_>>();
+                    raw.sort();
+                    ui.label(format!("Tier {}: {} needs {} per unit", tier, item, raw.join(", ")));
+                }
+            });
+            ui.collapsing("Items", |ui| {
+                for (item_type, item) in items.iter() {
+                    ui.label(format!(
+                        "{} ({}): {}, {} m³",
+                        items.display_name(&item_type),
+                        item_type,
+                        item.category,
+                        items.volume(&item_type)
+                    ));
+                }
+            });
         });
     });
 }
@@ -2,7 +2,8 @@ use std::collections::HashMap;
 
 use bevy_egui::egui::Ui;
 
-use crate::business::{Item, ItemType};
+use crate::business::Item;
+use crate::items::ItemId;
 
 pub(crate) fn label_with_hover_text(ui: &mut Ui, amount: usize, hover_text: &str) {
     let label = ui.label(amount.to_string());
@@ -11,15 +12,15 @@ pub(crate) fn label_with_hover_text(ui: &mut Ui, amount: usize, hover_text: &str
     }
 }
 
-pub(crate) fn count_items(items: &HashMap<ItemType, Vec<Item>>) -> usize {
+pub(crate) fn count_items(items: &HashMap<ItemId, Vec<Item>>) -> usize {
     items.values().map(|x| x.len()).sum()
 }
 
-pub(crate) fn items_to_string(items: &HashMap<ItemType, Vec<Item>>) -> String {
+pub(crate) fn items_to_string(items: &HashMap<ItemId, Vec<Item>>) -> String {
     items
         .iter()
         .filter(|(_, items)| !items.is_empty())
-        .map(|(item_type, items)| format!("{}: {}", item_type.name, items.len()))
+        .map(|(item_type, items)| format!("{}: {}", item_type.name(), items.len()))
         .collect::<Vec<_>>()
         .join("\n")
 }
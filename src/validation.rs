@@ -0,0 +1,335 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use bevy::prelude::*;
+use serde::de::DeserializeOwned;
+
+use crate::init::{Templates, MANUFACTURERS_PATH};
+use crate::items::{ItemId, ItemRegistry, ITEMS_PATH};
+use crate::offworld::OffWorld;
+use crate::people::{Names, Needs, NAMES_PATH};
+use crate::regions::{Regions, REGIONS_PATH};
+use crate::research::{Technologies, TECHNOLOGIES_PATH};
+use crate::tools::{Tools, TOOLS_PATH};
+
+pub const VALIDATE_COMMAND: &str = "validate";
+
+/// Problem found in a data file, key is the entry it was found in
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DataIssue {
+    pub file: String,
+    pub key: String,
+    pub message: String,
+}
+
+impl DataIssue {
+    pub fn new(file: &str, key: &str, message: String) -> Self {
+        DataIssue {
+            file: file.to_string(),
+            key: key.to_string(),
+            message,
+        }
+    }
+}
+
+impl fmt::Display for DataIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} [{}]: {}", self.file, self.key, self.message)
+    }
+}
+
+/// Errors make the simulation crash or misbehave, warnings point at data that is likely a mistake
+#[derive(Debug, Default)]
+pub struct Validation {
+    pub errors: Vec<DataIssue>,
+    pub warnings: Vec<DataIssue>,
+}
+
+impl Validation {
+    pub fn error(&mut self, file: &str, key: &str, message: String) {
+        self.errors.push(DataIssue::new(file, key, message));
+    }
+
+    pub fn warning(&mut self, file: &str, key: &str, message: String) {
+        self.warnings.push(DataIssue::new(file, key, message));
+    }
+}
+
+/// Reads a whole data file, a missing file or broken JSON is reported as a single issue
+pub fn read_data<T: DeserializeOwned>(file: &str) -> Result<T, Vec<DataIssue>> {
+    let issue = |message: String| vec![DataIssue::new(file, "file", message)];
+    let text = std::fs::read_to_string(file).map_err(|e| issue(e.to_string()))?;
+    serde_json::from_str(&text).map_err(|e| issue(e.to_string()))
+}
+
+/// Parses every entry on its own so all broken ones are reported by their key
+pub fn parse_entries<T: DeserializeOwned>(
+    file: &str,
+    entries: impl IntoIterator<Item = (String, serde_json::Value)>,
+    issues: &mut Vec<DataIssue>,
+) -> Vec<(String, T)> {
+    entries
+        .into_iter()
+        .filter_map(|(key, value)| match serde_json::from_value(value) {
+            Ok(entry) => Some((key, entry)),
+            Err(e) => {
+                issues.push(DataIssue::new(file, &key, e.to_string()));
+                None
+            }
+        })
+        .collect()
+}
+
+/// Loaded data when no issues were found
+pub fn checked<T>(data: T, mut issues: Vec<DataIssue>) -> Result<T, Vec<DataIssue>> {
+    if issues.is_empty() {
+        Ok(data)
+    } else {
+        issues.sort();
+        Err(issues)
+    }
+}
+
+/// Data of a file that must load, the game does not start with broken data
+pub fn loaded<T>(result: Result<T, Vec<DataIssue>>) -> T {
+    result.unwrap_or_else(|issues| panic!("{}", invalid_data(&issues)))
+}
+
+fn invalid_data(issues: &[DataIssue]) -> String {
+    let issues = issues
+        .iter()
+        .map(|issue| issue.to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!(
+        "Data files are invalid, fix them and check again with the {} command:\n{}",
+        VALIDATE_COMMAND, issues
+    )
+}
+
+/// Data that loaded, defaults for broken files whose issues are collected instead
+fn or_default<T: Default>(result: Result<T, Vec<DataIssue>>, issues: &mut Vec<DataIssue>) -> T {
+    result.unwrap_or_else(|found| {
+        issues.extend(found);
+        T::default()
+    })
+}
+
+/// Everything loaded from the data directory
+pub struct DataFiles<'a> {
+    pub templates: &'a Templates,
+    pub needs: &'a Needs,
+    pub items: &'a ItemRegistry,
+    pub names: &'a Names,
+    pub regions: &'a Regions,
+    pub offworld: &'a OffWorld,
+    pub tools: &'a Tools,
+    pub technologies: &'a Technologies,
+}
+
+impl DataFiles<'_> {
+    /// Checks that the files agree with each other, templates included
+    pub fn validate(&self) -> Validation {
+        let mut validation = self.templates.validate(self.offworld, self.tools);
+        self.validate_consumers(&mut validation);
+        self.validate_names(&mut validation);
+        self.validate_regions(&mut validation);
+        self.validate_repairs(&mut validation);
+        self.validate_technologies(&mut validation);
+        validation.errors.sort();
+        validation.warnings.sort();
+        validation
+    }
+
+    /// Produced items should end up somewhere, in another cycle, with people or off-world
+    fn validate_consumers(&self, validation: &mut Validation) {
+        let needed: HashSet<&ItemId> = self
+            .needs
+            .needs
+            .values()
+            .flat_map(|need| need.satisfied_by.keys())
+            .collect();
+        let recipes = &self.templates.recipes;
+        let mut unused = recipes.unused_items().into_iter().collect::<Vec<_>>();
+        unused.sort();
+        for item in unused {
+            let Some(item_type) = ItemId::get(&item) else {
+                continue;
+            };
+            let consumed = self.items.get(&item_type).is_consumed();
+            let repairs =
+                self.tools.repairs_with(&item_type) || self.items.repairs_with(&item_type);
+            if !needed.contains(&item_type)
+                && !consumed
+                && !repairs
+                && !self.offworld.is_exportable(&item)
+            {
+                validation.warning(
+                    ITEMS_PATH,
+                    &item,
+                    "is produced but nobody consumes it, it has no consumption rate, no need it satisfies and can't be exported".to_string(),
+                );
+            }
+        }
+    }
+
+    fn validate_names(&self, validation: &mut Validation) {
+        for (key, names) in self.names.lists() {
+            if names.is_empty() {
+                validation.error(
+                    NAMES_PATH,
+                    key,
+                    "list is empty, people could not be named".to_string(),
+                );
+            }
+        }
+    }
+
+    fn validate_regions(&self, validation: &mut Validation) {
+        if self.regions.regions.is_empty() {
+            validation.error(
+                REGIONS_PATH,
+                "regions",
+                "there must be at least one region".to_string(),
+            );
+        }
+        for manufacturer in self.templates.manufacturers.iter() {
+            if let Some(region) = &manufacturer.region {
+                if !self.regions.regions.iter().any(|r| r.name == *region) {
+                    validation.error(
+                        MANUFACTURERS_PATH,
+                        &manufacturer.name,
+                        format!("region {} is not defined in {}", region, REGIONS_PATH),
+                    );
+                }
+            }
+        }
+    }
+
+    fn validate_repairs(&self, validation: &mut Validation) {
+        let recipes = &self.templates.recipes;
+        let unreachable = recipes.unreachable_items();
+        let produced: HashSet<String> = recipes.items().difference(&unreachable).cloned().collect();
+        let repairs = self
+            .tools
+            .tools
+            .iter()
+            .map(|(tool, details)| (TOOLS_PATH, *tool, &details.repair))
+            .chain(
+                self.items
+                    .iter()
+                    .map(|(item, details)| (ITEMS_PATH, item, details.repair())),
+            );
+        for (file, key, repair) in repairs {
+            for repair_item in repair.keys().map(|item| item.name()) {
+                if !produced.contains(repair_item) && !self.offworld.is_importable(repair_item) {
+                    validation.warning(
+                        file,
+                        key.name(),
+                        format!(
+                            "repair item {} is neither produced nor imported, it will never be repaired",
+                            repair_item
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    fn validate_technologies(&self, validation: &mut Validation) {
+        for name in self.technologies.unreachable() {
+            validation.error(
+                TECHNOLOGIES_PATH,
+                name,
+                "can never be researched, its requirements form a loop".to_string(),
+            );
+        }
+        let cycles: HashSet<&String> = self
+            .templates
+            .production_cycles
+            .iter()
+            .map(|cycle| &cycle.name)
+            .collect();
+        for (name, technology) in self.technologies.technologies.iter() {
+            for cycle in technology.unlocks.iter().chain(technology.improves.keys()) {
+                if !cycles.contains(cycle) {
+                    validation.error(
+                        TECHNOLOGIES_PATH,
+                        name,
+                        format!("production cycle {} does not exist", cycle),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Loads every data file the same way the game does and validates them together. Files that
+/// fail to load are reported without the checks across files, those need every file loaded.
+pub fn validate_files() -> Validation {
+    let mut issues = vec![];
+    let items = or_default(ItemRegistry::try_load(), &mut issues);
+    if !issues.is_empty() {
+        // every other file refers to items, they would only repeat the same issues
+        return Validation {
+            errors: issues,
+            warnings: vec![],
+        };
+    }
+    let templates = or_default(Templates::try_load(), &mut issues);
+    let needs = or_default(Needs::try_load(), &mut issues);
+    let names = or_default(Names::try_load(), &mut issues);
+    let regions = or_default(Regions::try_load(), &mut issues);
+    let offworld = or_default(OffWorld::try_load(), &mut issues);
+    let tools = or_default(Tools::try_load(), &mut issues);
+    let technologies = or_default(Technologies::try_load(), &mut issues);
+    if !issues.is_empty() {
+        issues.sort();
+        return Validation {
+            errors: issues,
+            warnings: vec![],
+        };
+    }
+    let data = DataFiles {
+        templates: &templates,
+        needs: &needs,
+        items: &items,
+        names: &names,
+        regions: &regions,
+        offworld: &offworld,
+        tools: &tools,
+        technologies: &technologies,
+    };
+    data.validate()
+}
+
+/// Runs once all data is loaded, the game does not start with broken data
+#[allow(clippy::too_many_arguments)]
+pub fn validate_data(
+    templates: Res<Templates>,
+    needs: Res<Needs>,
+    items: Res<ItemRegistry>,
+    names: Res<Names>,
+    regions: Res<Regions>,
+    offworld: Res<OffWorld>,
+    tools: Res<Tools>,
+    technologies: Res<Technologies>,
+) {
+    let validation = DataFiles {
+        templates: &templates,
+        needs: &needs,
+        items: &items,
+        names: &names,
+        regions: &regions,
+        offworld: &offworld,
+        tools: &tools,
+        technologies: &technologies,
+    }
+    .validate();
+    for warning in validation.warnings.iter() {
+        warn!("{}", warning);
+    }
+    if !validation.errors.is_empty() {
+        panic!("{}", invalid_data(&validation.errors));
+    }
+}
@@ -1,4 +1,5 @@
-use crate::business::{Item, ItemType};
+use crate::business::Item;
+use crate::items::ItemId;
 use crate::logs::LogEvent;
 use crate::money::{Money, MoneyChange};
 use bevy::prelude::*;
@@ -29,7 +30,7 @@ pub enum Transaction {
         buyer: Entity,
         seller: Entity,
         item: Item,
-        item_type: ItemType,
+        item_type: ItemId,
         price: Money,
         date: usize,
     },
@@ -47,6 +48,14 @@ pub enum Transaction {
         salary: Money,
         date: usize,
     },
+    /// Rent, utilities and other overhead a business pays regardless of its production
+    FixedCost {
+        side: TradeSide,
+        payer: Entity,
+        landlord: Entity,
+        amount: Money,
+        date: usize,
+    },
 }
 
 impl fmt::Display for Transaction {
@@ -57,11 +66,14 @@ impl fmt::Display for Transaction {
                 item_type,
                 price,
                 ..
-            } => write!(f, "{} {} for {}", side, price, item_type.name),
+            } => write!(f, "{} {} for {}", side, price, item_type.name()),
             Transaction::Transfer { side, amount, .. } => {
                 write!(f, "{} transfer of {}", side, amount)
             }
             Transaction::Salary { side, salary, .. } => write!(f, "{} salary: {}", side, salary),
+            Transaction::FixedCost { side, amount, .. } => {
+                write!(f, "{} fixed costs: {}", side, amount)
+            }
         }
     }
 }
@@ -151,6 +163,10 @@ impl Transaction {
                 TradeSide::Pay => Either::Left(*salary),
                 TradeSide::Receive => Either::Right(*salary),
             },
+            Transaction::FixedCost { side, amount, .. } => match side {
+                TradeSide::Pay => Either::Left(*amount),
+                TradeSide::Receive => Either::Right(*amount),
+            },
         }
     }
 
@@ -159,6 +175,7 @@ impl Transaction {
             Transaction::Trade { date, .. } => *date,
             Transaction::Transfer { date, .. } => *date,
             Transaction::Salary { date, .. } => *date,
+            Transaction::FixedCost { date, .. } => *date,
         }
     }
 }
@@ -168,6 +185,20 @@ pub enum TransactionError {
     InsufficientFunds(Money),
     SellOrderEmpty,
     WalletNotFound,
+    NoRoute,
+}
+
+impl fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransactionError::InsufficientFunds(missing) => {
+                write!(f, "Insufficient funds, missing {}", missing)
+            }
+            TransactionError::SellOrderEmpty => write!(f, "Sell order is empty"),
+            TransactionError::WalletNotFound => write!(f, "Wallet not found"),
+            TransactionError::NoRoute => write!(f, "No route between regions"),
+        }
+    }
 }
 
 #[derive(Component, Default)]
@@ -226,7 +257,7 @@ impl Wallet {
                     buyer: seller,
                     seller: buyer,
                     item,
-                    item_type: item_type.clone(),
+                    item_type,
                     price,
                     date,
                 };
@@ -289,6 +320,31 @@ impl Wallet {
                     salary,
                 });
             }
+            Transaction::FixedCost {
+                side,
+                payer,
+                landlord,
+                amount,
+                date,
+            } => {
+                self.process_payout(other_wallet, side.clone(), amount)?;
+                let symmetric_transaction = Transaction::FixedCost {
+                    side: match side {
+                        TradeSide::Pay => TradeSide::Receive,
+                        TradeSide::Receive => TradeSide::Pay,
+                    },
+                    payer: landlord,
+                    landlord: payer,
+                    amount,
+                    date,
+                };
+                other_wallet.transactions.push_front(symmetric_transaction);
+                logs.send(LogEvent::MoneyTransfer {
+                    sender: payer,
+                    receiver: landlord,
+                    amount,
+                });
+            }
         }
         self.transactions.push_front(transaction.clone());
         Ok(())
@@ -361,7 +417,6 @@ impl Wallet {
     /// # Returns
     ///
     /// * A `String` containing the summary.
-
     pub fn get_summary(&self, current_date: usize, n: usize, m: usize) -> String {
         let mut costs = BTreeMap::new();
         let mut profits = BTreeMap::new();
@@ -371,6 +426,8 @@ impl Wallet {
         let mut salary_profits = Money(0);
         let mut transfer_gains = Money(0);
         let mut transfer_losses = Money(0);
+        let mut fixed_costs = Money(0);
+        let mut fixed_cost_gains = Money(0);
         let transactions = self
             .transactions
             .iter()
@@ -407,18 +464,23 @@ impl Wallet {
                         transfer_losses += *amount;
                     }
                 }
+                Transaction::FixedCost { side, amount, .. } => match side {
+                    TradeSide::Pay => fixed_costs += *amount,
+                    TradeSide::Receive => fixed_cost_gains += *amount,
+                },
             }
         }
 
-        let total_costs: Money = costs.values().sum::<Money>() + salary_costs + transfer_losses;
+        let total_costs: Money =
+            costs.values().sum::<Money>() + salary_costs + transfer_losses + fixed_costs;
         let total_profits: Money =
-            profits.values().sum::<Money>() + salary_profits + transfer_gains;
+            profits.values().sum::<Money>() + salary_profits + transfer_gains + fixed_cost_gains;
 
         let mut summary = String::new();
 
         summary.push_str(&format!("Summary for the last {} days:\n\n", n));
 
-        if !costs.is_empty() || salary_costs.0 > 0 {
+        if !costs.is_empty() || salary_costs.0 > 0 || fixed_costs.0 > 0 {
             summary.push_str("Costs:\n");
         }
 
@@ -435,13 +497,16 @@ impl Wallet {
             }
             summary.push_str(&format!(
                 "  Total Purchases: {}\n",
-                total_costs - salary_costs
+                costs.values().sum::<Money>()
             ));
         }
 
         if salary_costs.0 > 0 {
             summary.push_str(&format!("  Salaries: {}\n", salary_costs));
         }
+        if fixed_costs.0 > 0 {
+            summary.push_str(&format!("  Fixed costs: {}\n", fixed_costs));
+        }
         if transfer_losses.0 > 0 {
             summary.push_str(&format!("  Transfers: {}\n", transfer_losses));
         }
@@ -471,6 +536,9 @@ impl Wallet {
         if salary_profits.0 > 0 {
             summary.push_str(&format!("  Salaries: {}\n\n", salary_profits));
         }
+        if fixed_cost_gains.0 > 0 {
+            summary.push_str(&format!("  Rent and utilities: {}\n\n", fixed_cost_gains));
+        }
         if transfer_gains.0 > 0 {
             summary.push_str(&format!("  Transfers: {}\n\n", transfer_gains));
         }
@@ -492,7 +560,7 @@ impl Wallet {
     pub fn get_amount_of_sell_transactions(
         &self,
         current_date: usize,
-        item_type: &ItemType,
+        item_type: &ItemId,
         n: usize,
     ) -> usize {
         self.transactions
@@ -514,4 +582,24 @@ impl Wallet {
             })
             .count()
     }
+
+    /// Amount of items of each type bought in the last n days
+    pub fn get_bought_items(&self, current_date: usize, n: usize) -> HashMap<ItemId, usize> {
+        let mut bought = HashMap::new();
+        for transaction in self
+            .transactions
+            .iter()
+            .take_while(|transaction| current_date - transaction.get_date() <= n)
+        {
+            if let Transaction::Trade {
+                side: TradeSide::Pay,
+                item_type,
+                ..
+            } = transaction
+            {
+                *bought.entry(*item_type).or_insert(0) += 1;
+            }
+        }
+        bought
+    }
 }